@@ -1,15 +1,26 @@
 #[allow(unused)]
 use rayon::prelude::*;
-use sql_parse::Statement::{self, InsertReplace};
-use sql_parse::{parse_statements, Expression, ParseOptions, SQLDialect};
-use std::path::Path;
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
 
+use anyhow::{bail, Context};
 use clap::Parser;
 
-// use crate::parser::MyParser;
-use crate::rules::get_struct_by_name;
+use crate::discovery::{discover, name_matches, DiscoveryOptions};
+use crate::masking::{
+    detokenize_dump, ColumnSelector, LeakCheck, Masker, Predicate, TokenVault, DEFAULT_MIN_LENGTH,
+};
+use crate::rules::{self, Strategy};
 use crate::ExtractResult;
-use crate::{settings::parse_masking_config, simple_parse, sqlparse::to_json, types::Database};
+use crate::{
+    settings::{parse_masking_config, MaskingRule},
+    simple_parse,
+    sqlparse::to_json,
+    types::Database,
+};
 
 #[allow(unused)]
 static DEFAULT_JSON_FILTER: &str = r#"to_entries | map({table: .key, columns: .value.columns | map(select(.name | test("pass"; "i")))}) | map(select(.columns | length > 0))"#;
@@ -23,9 +34,10 @@ Usage:
 
 --query <query>
 "))]
+#[command(subcommand_negates_reqs = true)]
 pub struct Args {
-    #[arg(short, long)]
-    pub sql_file: String,
+    #[arg(short, long, required = true)]
+    pub sql_file: Option<String>,
 
     #[arg(short, long)]
     pub query: Option<String>,
@@ -38,6 +50,77 @@ pub struct Args {
 pub enum Commands {
     #[command(about = "Mask PII from a SQL file")]
     MaskPII(MaskPIIArgs),
+    #[command(about = "Report the columns of a SQL file that are likely to hold PII")]
+    DiscoverPII(DiscoverPIIArgs),
+    #[command(about = "Inspect the rules masking configs can use")]
+    Rules(RulesArgs),
+    #[command(about = "Restore tokenized values of a masked SQL file from a token vault")]
+    Detokenize(DetokenizeArgs),
+    #[command(about = "Check that no original value of the masked columns survived masking")]
+    VerifyMask(VerifyMaskArgs),
+}
+
+#[derive(Parser)]
+pub struct RulesArgs {
+    #[command(subcommand)]
+    pub cmd: RulesCommands,
+}
+
+#[derive(Parser)]
+pub enum RulesCommands {
+    #[command(about = "List the strategies and fakers with their arguments")]
+    List,
+    #[command(about = "Decrypt values masked with an `fpe` rule")]
+    Decrypt(DecryptArgs),
+}
+
+#[derive(Parser)]
+pub struct DecryptArgs {
+    /// The rule the values were masked with, e.g. `fpe('ff3-1', 'users')`
+    #[arg(short, long)]
+    rule: String,
+
+    /// The masking key the values were masked with, or else
+    /// `SQLEX_MASKING_KEY`
+    #[arg(long)]
+    masking_key: Option<String>,
+
+    /// The masked values
+    #[arg(required = true)]
+    values: Vec<String>,
+}
+
+#[derive(Clone, Copy, Debug, Default, clap::ValueEnum)]
+pub enum ReportFormat {
+    #[default]
+    Json,
+    Markdown,
+}
+
+#[derive(Parser)]
+pub struct DiscoverPIIArgs {
+    #[arg(short, long)]
+    pub sql_file: String,
+
+    /// Format of the report
+    #[arg(short, long, value_enum, default_value_t)]
+    format: ReportFormat,
+
+    /// Write the report to this file instead of stdout
+    #[arg(short, long)]
+    output: Option<String>,
+
+    /// Also write a starter masking config with the flagged columns
+    #[arg(long)]
+    starter_config: Option<String>,
+
+    /// How many values of each column to inspect
+    #[arg(long, default_value_t = DiscoveryOptions::default().sample_size)]
+    sample_size: usize,
+
+    /// Score from 0 to 1 at which a column is flagged as PII
+    #[arg(long, default_value_t = DiscoveryOptions::default().min_score)]
+    min_score: f64,
 }
 
 #[derive(Parser)]
@@ -47,6 +130,90 @@ pub struct MaskPIIArgs {
 
     #[arg(short, long)]
     masking_config: Option<String>,
+
+    /// Write the masked dump to this file instead of stdout
+    #[arg(short, long)]
+    output: Option<String>,
+
+    /// Secret that makes masking deterministic, overriding `masking_key`
+    /// from the masking config
+    #[arg(long)]
+    masking_key: Option<String>,
+
+    /// Print how each column would be masked instead of masking the dump
+    #[arg(long)]
+    explain: bool,
+
+    /// Token vault recording the values replaced by `tokenize` rules,
+    /// created when it does not exist
+    #[arg(long)]
+    vault: Option<String>,
+
+    /// Passphrase of the token vault, or else `SQLEX_VAULT_KEY`
+    #[arg(long)]
+    vault_key: Option<String>,
+}
+
+#[derive(Parser)]
+pub struct DetokenizeArgs {
+    #[arg(short, long)]
+    pub sql_file: String,
+
+    /// Token vault written by `mask-pii --vault`
+    #[arg(long)]
+    vault: String,
+
+    /// Passphrase of the token vault, or else `SQLEX_VAULT_KEY`
+    #[arg(long)]
+    vault_key: Option<String>,
+
+    /// Only restore these columns, as `column`, `table.column` or
+    /// `database.table.column`; all of them by default
+    #[arg(short, long, value_delimiter = ',')]
+    columns: Vec<String>,
+
+    /// Only restore the rows of INSERTs, and the UPDATEs, matching this
+    /// condition, e.g. `id IN (1, 2)`
+    #[arg(short, long)]
+    r#where: Option<String>,
+
+    /// Write the restored dump to this file instead of stdout
+    #[arg(short, long)]
+    output: Option<String>,
+}
+
+#[derive(Parser)]
+pub struct VerifyMaskArgs {
+    /// The dump before masking
+    #[arg(long)]
+    original: String,
+
+    /// The same dump after masking
+    #[arg(long)]
+    masked: String,
+
+    /// The masking config the dump was masked with, which tells the
+    /// columns whose values are all replaced
+    #[arg(short, long)]
+    masking_config: Option<String>,
+
+    /// Masked columns, on top of those of the masking config, as `column`,
+    /// `table.column` or `database.table.column`
+    #[arg(short, long, value_delimiter = ',')]
+    columns: Vec<String>,
+
+    /// Columns whose original values are not looked for, such as remapped
+    /// ids, as `column`, `table.column` or `database.table.column`
+    #[arg(short, long, value_delimiter = ',')]
+    exclude: Vec<String>,
+
+    /// Ignore original values shorter than this many characters
+    #[arg(long, default_value_t = DEFAULT_MIN_LENGTH)]
+    min_length: usize,
+
+    /// Write the report to this file instead of stdout
+    #[arg(short, long)]
+    output: Option<String>,
 }
 
 pub fn exec() -> ExtractResult<Vec<String>> {
@@ -54,133 +221,281 @@ pub fn exec() -> ExtractResult<Vec<String>> {
 
     match args.cmd {
         Some(Commands::MaskPII(args)) => {
-            run_mask_pii_action(&args);
+            run_mask_pii_action(&args)?;
+        }
+        Some(Commands::DiscoverPII(args)) => {
+            run_discover_pii_action(&args)?;
+        }
+        Some(Commands::Rules(RulesArgs {
+            cmd: RulesCommands::List,
+        })) => {
+            run_rules_list_action(&mut std::io::stdout().lock())?;
+        }
+        Some(Commands::Rules(RulesArgs {
+            cmd: RulesCommands::Decrypt(args),
+        })) => {
+            run_rules_decrypt_action(&args, &mut std::io::stdout().lock())?;
+        }
+        Some(Commands::Detokenize(args)) => {
+            run_detokenize_action(&args)?;
+        }
+        Some(Commands::VerifyMask(args)) => {
+            let leaks = run_verify_mask_action(&args)?;
+            if leaks > 0 {
+                bail!("{leaks} original values survived masking");
+            }
         }
         _ => {
-            run_default_action(&args);
+            run_default_action(&args)?;
         }
     }
     Ok(vec![])
 }
 
 /// Mask PII from a SQL file
-///  
-/// 1. Parse the SQL file and print the JSON representation of the SQL.
-/// 2. If the `--mask-pii` flag is provided, mask the PII in the SQL file.
 ///
-/// Returns a list of statements to be executed.
-fn run_mask_pii_action(args: &MaskPIIArgs) -> ExtractResult<Vec<Statement>> {
+/// 1. Load the masking config and scan the SQL file for its tables, foreign
+///    keys and the columns whose values match the value patterns.
+/// 2. Stream the file statement by statement, replacing the values of every
+///    masked column in the `INSERT` and `REPLACE` statements and leaving the
+///    rest of the dump untouched.
+/// 3. Write the masked dump to `--output`, or to stdout.
+///
+/// With `--explain`, the masking plan is written in place of the masked
+/// dump.
+///
+/// Returns the number of statements that were rewritten.
+fn run_mask_pii_action(args: &MaskPIIArgs) -> ExtractResult<usize> {
     let sqlfile_path = Path::new(&args.sql_file);
     if !sqlfile_path.exists() {
         eprintln!("File {} does not exist", sqlfile_path.display());
         std::process::exit(1);
     }
 
-    let file_bytes = std::fs::read(sqlfile_path).expect("unable to read file");
-    let file_str = std::str::from_utf8(&file_bytes).expect("Invalid UTF-8 sequence");
-
     let masking_config = args.masking_config.clone().unwrap_or_default();
-    let config = parse_masking_config(&masking_config).expect("unable to load masking config");
-    // let parser = sql_script_parser(&file_bytes).map(|x| x.statement);
-    // let mut my_parser = MyParser::new();
-
-    // my_parser = my_parser.parse(file_str).unwrap();
-    // println!("my parser: {:?}", my_parser);
-
-    // let mut issues = Vec::new();
-    // let options = ParseOptions::new()
-    //     .dialect(SQLDialect::MariaDB)
-    //     .arguments(sql_parse::SQLArguments::QuestionMark)
-    //     .warn_unquoted_identifiers(true);
-
-    // parser.into_iter().for_each(|x| {
-    //     // take a string and parse it into a list of statements
-    //     // where each is a single SQL operation
-    //     let sql_str = std::str::from_utf8(x).expect("Invalid UTF-8 sequence");
-    // let ast = parse_statements(sql_str, &mut issues, &options);
-    //     // since we're only dealing with insert replace statements
-    //     // which are a single operation per statement
-    //     // we can assume there's only one. If there are more statements
-    //     // we are just going to ignore them as they aren't an insert or replace statement
-    //     if ast.len() == 1 {
-    //         match &ast[0] {
-    //             InsertReplace(sql_parse::InsertReplace {
-    //                 columns, values, ..
-    //             }) => {
-    //                 // In an insert replace statement, we are dealing with values and their columns that match
-    //                 // the value.
-    //                 // To create a masking application, we'll walk through each of the columns matched with their
-    //                 // values. If the value is a column that we are interested in given by the filter configuration
-    //                 // then we'll match up the appropriate rule and apply it to the value, replacing the original
-    //                 // value with the masked value.
-    //                 let values = values.as_ref().unwrap();
-    //                 let cols =
-    //                     columns
-    //                         .into_iter()
-    //                         .zip(values.clone().1.into_iter())
-    //                         .map(|(col, val)| {
-    //                             if config.filter_column(&col.value.to_string()) {
-    //                                 let masking_fn = get_struct_by_name(&col.value.to_string());
-    //                                 let new_val = masking_fn.fake();
-    //                                 let new_stmt = Expression::StringLiteral(new_val.to_string());
-    //                                 (col, new_stmt)
-    //                             } else {
-    //                                 (col, val)
-    //                             }
-    //                         });
-    //                 let cols =
-    //                     columns
-    //                         .into_iter()
-    //                                 .zip(values.clone().1.into_iter())
-    //                                 .filter(|(x, _val)| config.filter_column(&x.value.to_string()))
-    //                                 .collect::<Vec<(
-    //                                     &sql_parse::Identifier<'_>,
-    //                                     Vec<sql_parse::Expression<'_>>,
-    //                                 )>>();
-    //                 let mut new_values = Vec::new();
-    //                 for (col, _val) in cols {
-    //                     let fn_ = get_struct_by_name(&col.to_string());
-    //                     // let rule = MaskingRule(fn_);
-    //                     // let val = rule.fake();
-    //                     let val = fn_.fake();
-    //                     new_values.push(val);
-    //                 }
-    //             }
-    //             _ => {}
-    //         }
-    //     } else {
-    //         // ast.iter().for_each(|x| {
-    //         //     println!("{:?}", x);
-    //         // });
-    //     }
-    // });
-    Ok(vec![])
+    let mut config =
+        parse_masking_config(&masking_config).context("unable to load masking config")?;
+    if let Some(key) = args.masking_key.as_ref() {
+        config.masking_key = Some(key.clone());
+    }
+
+    let mut output: Box<dyn Write> = match args.output.as_ref() {
+        Some(path) => Box::new(BufWriter::new(
+            File::create(path).with_context(|| format!("unable to create {path}"))?,
+        )),
+        None => Box::new(BufWriter::new(std::io::stdout().lock())),
+    };
+    let mut masker = Masker::new(&config)?;
+    masker.prepare(File::open(sqlfile_path).context("unable to read sql dump")?)?;
+    if args.explain {
+        write!(output, "{}", masker.plan()?.to_markdown())?;
+        output.flush()?;
+        return Ok(0);
+    }
+    if let Some(path) = args.vault.as_ref() {
+        masker.set_vault(TokenVault::open(path, &vault_key(&args.vault_key)?)?);
+    }
+    let rewritten = masker.mask_dump(
+        File::open(sqlfile_path).context("unable to read sql dump")?,
+        &mut output,
+    )?;
+    output.flush()?;
+    if let (Some(path), Some(vault)) = (args.vault.as_ref(), masker.vault()) {
+        vault.save(path)?;
+    }
+
+    Ok(rewritten)
 }
 
-/// Run a single replacement
-fn create_new_statement_for_masking<'a>(
-    config: &crate::settings::MaskingConfig,
-    statements: Vec<Statement<'a>>,
-) -> ExtractResult<Vec<Statement<'a>>> {
-    statements.into_iter().for_each(|x| match x {
-        InsertReplace(sql_parse::InsertReplace {
-            columns, values, ..
-        }) => {
-            println!("{:?}", columns);
-            println!("{:?}", values);
+/// Restore the values tokenized by `mask-pii --vault`
+///
+/// 1. Open the token vault with its passphrase.
+/// 2. Stream the SQL file statement by statement, replacing the tokens of
+///    the selected columns and rows with their original values.
+/// 3. Write the restored dump to `--output`, or to stdout.
+///
+/// Returns the number of statements that were rewritten.
+fn run_detokenize_action(args: &DetokenizeArgs) -> ExtractResult<usize> {
+    let vault = TokenVault::open(&args.vault, &vault_key(&args.vault_key)?)?;
+    let columns = args
+        .columns
+        .iter()
+        .map(|selector| ColumnSelector::parse(selector))
+        .collect::<ExtractResult<Vec<ColumnSelector>>>()?;
+    let rows = args.r#where.as_deref().map(Predicate::parse).transpose()?;
+
+    let input =
+        File::open(&args.sql_file).with_context(|| format!("unable to read {}", args.sql_file))?;
+    let mut output: Box<dyn Write> = match args.output.as_ref() {
+        Some(path) => Box::new(BufWriter::new(
+            File::create(path).with_context(|| format!("unable to create {path}"))?,
+        )),
+        None => Box::new(BufWriter::new(std::io::stdout().lock())),
+    };
+    let rewritten = detokenize_dump(&vault, &columns, rows.as_ref(), input, &mut output)?;
+    output.flush()?;
+
+    Ok(rewritten)
+}
+
+/// Check a masked dump against the dump it was masked from
+///
+/// 1. Collect digests of every original value of the masked columns, those
+///    the masking config replaces the values of and those of `--columns`.
+/// 2. Scan every value of every statement of the masked dump, including
+///    free text, for those original values.
+/// 3. Write each hit, by column and row or statement, to `--output`, or to
+///    stdout.
+///
+/// Returns the number of hits.
+fn run_verify_mask_action(args: &VerifyMaskArgs) -> ExtractResult<usize> {
+    let open = |path: &str| File::open(path).with_context(|| format!("unable to read {path}"));
+    let selectors = |selectors: &[String]| {
+        selectors
+            .iter()
+            .map(|selector| ColumnSelector::parse(selector))
+            .collect::<ExtractResult<Vec<ColumnSelector>>>()
+    };
+    let columns = selectors(&args.columns)?;
+    let config = match args.masking_config.as_ref() {
+        Some(path) => Some(parse_masking_config(path).context("unable to load masking config")?),
+        None if columns.is_empty() => {
+            bail!("no masked columns to check, pass --masking-config or --columns")
         }
-        _ => {}
-    });
-    Ok(vec![])
+        None => None,
+    };
+    let mut masker = match config.as_ref() {
+        Some(config) => {
+            let mut masker = Masker::new(config)?;
+            masker.prepare(open(&args.original)?)?;
+            Some(masker)
+        }
+        None => None,
+    };
+
+    let mut check = LeakCheck::new(selectors(&args.exclude)?, args.min_length);
+    check.learn(open(&args.original)?, &mut |column| {
+        if columns.iter().any(|selector| selector.matches(column)) {
+            return Ok(true);
+        }
+        match masker.as_mut() {
+            Some(masker) => masker.replaces(column),
+            None => Ok(false),
+        }
+    })?;
+    let leaks = check.scan(open(&args.masked)?)?;
+
+    let mut output: Box<dyn Write> = match args.output.as_ref() {
+        Some(path) => Box::new(BufWriter::new(
+            File::create(path).with_context(|| format!("unable to create {path}"))?,
+        )),
+        None => Box::new(BufWriter::new(std::io::stdout().lock())),
+    };
+    for leak in leaks.iter() {
+        writeln!(output, "{leak}")?;
+    }
+    writeln!(
+        output,
+        "{} original values found, from {} masked columns",
+        leaks.len(),
+        check.masked_columns()
+    )?;
+    output.flush()?;
+
+    Ok(leaks.len())
+}
+
+/// The passphrase of a token vault, from the command line or else the
+/// `SQLEX_VAULT_KEY` environment variable.
+fn vault_key(key: &Option<String>) -> ExtractResult<String> {
+    key.clone()
+        .or_else(|| std::env::var("SQLEX_VAULT_KEY").ok())
+        .context("the token vault needs a key, pass --vault-key or set SQLEX_VAULT_KEY")
+}
+
+/// List the rules masking configs can use: the strategies, then every
+/// faker of the registry, with their parameters.
+fn run_rules_list_action<W: Write>(output: &mut W) -> ExtractResult<()> {
+    writeln!(output, "Strategies:")?;
+    for signature in Strategy::SIGNATURES {
+        writeln!(output, "  {signature}")?;
+    }
+
+    writeln!(output, "\nFakers:")?;
+    for faker in rules::registry().fakers() {
+        writeln!(output, "  {}", faker.signature())?;
+    }
+
+    Ok(())
+}
+
+/// Print the original of each value masked with an `fpe` rule, given the
+/// rule and the masking key it was masked with.
+fn run_rules_decrypt_action<W: Write>(args: &DecryptArgs, output: &mut W) -> ExtractResult<()> {
+    let rule = args.rule.parse::<MaskingRule>()?;
+    let Some(Strategy::Fpe(fpe)) = rule.strategy() else {
+        bail!("only `fpe` rules can be decrypted, not `{}`", rule.name());
+    };
+    let masking_key = args
+        .masking_key
+        .clone()
+        .or_else(|| std::env::var("SQLEX_MASKING_KEY").ok())
+        .context("decrypting needs the masking key, pass --masking-key or set SQLEX_MASKING_KEY")?;
+    for value in args.values.iter() {
+        writeln!(output, "{}", fpe.decrypt(&masking_key, value)?)?;
+    }
+
+    Ok(())
+}
+
+/// Report the columns of a SQL file that are likely to hold PII
+///
+/// 1. Score every column from its name, its data type and a sample of its
+///    values.
+/// 2. Write the report as JSON or Markdown to `--output`, or to stdout.
+/// 3. With `--starter-config`, write a masking config for the flagged columns.
+///
+/// Returns the number of flagged columns.
+fn run_discover_pii_action(args: &DiscoverPIIArgs) -> ExtractResult<usize> {
+    let sqlfile_path = Path::new(&args.sql_file);
+    if !sqlfile_path.exists() {
+        eprintln!("File {} does not exist", sqlfile_path.display());
+        std::process::exit(1);
+    }
+
+    let options = DiscoveryOptions {
+        sample_size: args.sample_size,
+        min_score: args.min_score,
+    };
+    let report = discover(
+        File::open(sqlfile_path).context("unable to read sql dump")?,
+        &options,
+    )?;
+
+    let rendered = match args.format {
+        ReportFormat::Json => serde_json::to_string_pretty(&report)?,
+        ReportFormat::Markdown => report.to_markdown(),
+    };
+    match args.output.as_ref() {
+        Some(path) => {
+            std::fs::write(path, rendered).with_context(|| format!("unable to write {path}"))?
+        }
+        None => println!("{rendered}"),
+    }
+    if let Some(path) = args.starter_config.as_ref() {
+        std::fs::write(path, report.starter_config())
+            .with_context(|| format!("unable to write {path}"))?;
+    }
+
+    Ok(report.flagged().count())
 }
 
 /// Default action.
 ///
 /// 1. Parse the SQL file and print the JSON representation of the SQL.
 /// 2. If the `--query` flag is provided, print the columns that contain the query string.
-/// 3. If the `--mask-pii` flag is provided, mask the PII in the SQL file.
 fn run_default_action(args: &Args) -> ExtractResult<Vec<String>> {
-    let sqlfile_path = Path::new(&args.sql_file);
+    let sqlfile_path = Path::new(args.sql_file.as_deref().unwrap_or_default());
     if !sqlfile_path.exists() {
         eprintln!("File {} does not exist", sqlfile_path.display());
         std::process::exit(1);
@@ -189,12 +504,12 @@ fn run_default_action(args: &Args) -> ExtractResult<Vec<String>> {
     if let Some(query) = args.query.as_ref() {
         let res = simple_parse(sqlfile_path).expect("unable to load input file");
         // let input = to_json(res.clone());
-        let result = find_pass_columns(&res, &query);
+        let result = find_pass_columns(&res, query);
         println!("{}", serde_json::to_string(&result).unwrap());
     } else {
         let res = simple_parse(sqlfile_path).expect("unable to load input file");
         let input = to_json(res.clone());
-        println!("{}", input.to_string());
+        println!("{}", input);
         vals.push(input.to_string());
     }
     Ok(vals)
@@ -214,11 +529,7 @@ fn find_pass_columns(databases: &Vec<Database>, query_str: &str) -> Vec<Result>
         let db_name = database.db_name.clone();
         for table in &database.tables {
             for column in &table.columns {
-                if column
-                    .name
-                    .to_lowercase()
-                    .contains(&query_str.to_lowercase())
-                {
+                if name_matches(&column.name, query_str) {
                     result.push(Result {
                         db_name: db_name.clone(),
                         table_name: table.name.clone(),
@@ -239,46 +550,235 @@ mod tests {
     use tempfile::TempDir;
 
     use super::*;
+    use crate::dump::split_statements;
+    use crate::masking::Masker;
 
     #[test]
     fn test_replace_single_statement() {
         let temp_dir = tempfile::tempdir().unwrap();
-        let test_sql_file = create_temp_sql_with_insert(&temp_dir);
         let test_config = create_test_masking_config(&temp_dir);
 
         let parsed_config =
-            parse_masking_config(&test_config.as_os_str().to_str().unwrap()).unwrap();
+            parse_masking_config(test_config.as_os_str().to_str().unwrap()).unwrap();
 
         let sql_single_insert = r#"INSERT INTO users (id, name, email, password) VALUES (1, 'John Doe', 'john.doe@example.com', 'password');"#;
 
-        let mut issues = Vec::new();
-        let options = ParseOptions::new()
-            .dialect(SQLDialect::MariaDB)
-            .arguments(sql_parse::SQLArguments::QuestionMark)
-            .warn_unquoted_identifiers(true);
-
-        let ast = parse_statements(&sql_single_insert, &mut issues, &options);
+        let mut masker = Masker::new(&parsed_config).unwrap();
+        let statement = &split_statements(sql_single_insert).unwrap()[0];
+        let masked = masker.mask_statement(statement).unwrap().unwrap();
 
-        let _ = create_new_statement_for_masking(&parsed_config, ast);
-
-        // let res = run_mask_pii_action(&MaskPIIArgs {
-        //     sql_file: "./tests/schema_dump.sql".to_string(),
-        //     masking_config: Some("./tests/more.yaml".to_string()),
-        // });
-        // assert!(res.is_ok());
+        assert!(masked.body.starts_with(
+            "INSERT INTO `users` (`id`, `name`, `email`, `password`) VALUES (1, 'John Doe', '"
+        ));
+        assert!(!masked.body.contains("john.doe@example.com"));
+        assert!(masked.body.ends_with("', 'password')"));
+        assert_eq!(masked.delimiter, ";");
     }
 
     #[test]
     fn test_can_extract_sql_from_file() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let output = temp_dir.path().join("masked.sql");
         let args = MaskPIIArgs {
             sql_file: "./tests/schema_dump.sql".to_string(),
             masking_config: Some("./tests/more.yaml".to_string()),
+            output: Some(output.to_str().unwrap().to_string()),
+            masking_key: None,
+            explain: false,
+            vault: None,
+            vault_key: None,
         };
         let res = run_mask_pii_action(&args);
         println!("{:?}", res);
         assert!(res.is_ok());
         let res = res.unwrap();
-        assert!(res.len() == 1);
+        assert!(res == 3);
+
+        let masked = std::fs::read_to_string(output).unwrap();
+        assert!(masked.contains("CREATE TABLE IF NOT EXISTS `users` ("));
+        assert!(!masked.contains("'admin','admin','admin'"));
+        assert!(!masked.contains("$2b$12$"));
+    }
+
+    #[test]
+    fn test_can_explain_masking_plan() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let output = temp_dir.path().join("plan.md");
+        let args = MaskPIIArgs {
+            sql_file: "./tests/schema_dump.sql".to_string(),
+            masking_config: Some("./tests/more.yaml".to_string()),
+            output: Some(output.to_str().unwrap().to_string()),
+            masking_key: None,
+            explain: true,
+            vault: None,
+            vault_key: None,
+        };
+        assert_eq!(run_mask_pii_action(&args).unwrap(), 0);
+
+        let plan = std::fs::read_to_string(output).unwrap();
+        assert!(plan.starts_with("| Column | Type | Rule | Selected by | Notes |\n"));
+        assert!(plan.contains("| `namedmanager`.`users`.`password` | VARCHAR (255) | internet::username() | column `password` | memoized |\n"));
+        assert!(plan.contains("| `namedmanager`.`users`.`id` | INT (11) |  |  |  |\n"));
+        assert!(!plan.contains("INSERT"));
+    }
+
+    #[test]
+    fn test_can_list_rules() {
+        let mut output = Vec::new();
+        run_rules_list_action(&mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.starts_with("Strategies:\n  constant(value)\n"));
+        for line in [
+            "  truncate(length)\n",
+            "\nFakers:\n  address::city()\n",
+            "  contact::email()\n",
+            "  datetime::date(min, max)\n",
+            "  lorem::paragraph(count, sentence_count, word_count, separator)\n",
+        ] {
+            assert!(output.contains(line), "{line}");
+        }
+    }
+
+    #[test]
+    fn test_can_tokenize_and_detokenize() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config = temp_dir.path().join("tokenize.yaml");
+        std::fs::write(
+            &config,
+            "columns:\n  - email\nrules:\n  email: tokenize()\n",
+        )
+        .unwrap();
+        let dump = temp_dir.path().join("dump.sql");
+        std::fs::write(
+            &dump,
+            "INSERT INTO `users` (`id`, `email`) VALUES (1, 'john@example.com'), (2, 'jane@example.com');\n",
+        )
+        .unwrap();
+        let path = |name: &str| temp_dir.path().join(name).to_str().unwrap().to_string();
+
+        let args = MaskPIIArgs {
+            sql_file: path("dump.sql"),
+            masking_config: Some(path("tokenize.yaml")),
+            output: Some(path("masked.sql")),
+            masking_key: None,
+            explain: false,
+            vault: Some(path("tokens.vault")),
+            vault_key: Some("secret".to_string()),
+        };
+        assert_eq!(run_mask_pii_action(&args).unwrap(), 1);
+        let masked = std::fs::read_to_string(path("masked.sql")).unwrap();
+        assert!(masked.contains("VALUES (1, 'tok_"));
+        assert!(!masked.contains("@example.com"));
+
+        let args = DetokenizeArgs {
+            sql_file: path("masked.sql"),
+            vault: path("tokens.vault"),
+            vault_key: Some("secret".to_string()),
+            columns: vec!["users.email".to_string()],
+            r#where: Some("id = 2".to_string()),
+            output: Some(path("restored.sql")),
+        };
+        assert_eq!(run_detokenize_action(&args).unwrap(), 1);
+        let restored = std::fs::read_to_string(path("restored.sql")).unwrap();
+        assert!(restored.contains("VALUES (1, 'tok_"));
+        assert!(restored.contains("(2, 'jane@example.com');"));
+
+        let args = DetokenizeArgs {
+            vault_key: Some("wrong".to_string()),
+            ..args
+        };
+        assert!(run_detokenize_action(&args).is_err());
+    }
+
+    #[test]
+    fn test_can_decrypt_fpe_values() {
+        let rule = "fpe('ff3-1', 'users')".parse::<MaskingRule>().unwrap();
+        let masked = rule.apply("+49 170 1234567", Some("key")).unwrap().unwrap();
+
+        let mut output = Vec::new();
+        let args = DecryptArgs {
+            rule: "fpe('ff3-1', 'users')".to_string(),
+            masking_key: Some("key".to_string()),
+            values: vec![masked],
+        };
+        run_rules_decrypt_action(&args, &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "+49 170 1234567\n");
+
+        let mut output = Vec::new();
+        std::env::set_var("SQLEX_MASKING_KEY", "key");
+        let decrypted = run_rules_decrypt_action(
+            &DecryptArgs {
+                masking_key: None,
+                values: args.values.clone(),
+                rule: args.rule.clone(),
+            },
+            &mut output,
+        );
+        std::env::remove_var("SQLEX_MASKING_KEY");
+        decrypted.unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "+49 170 1234567\n");
+
+        let args = DecryptArgs {
+            rule: "redact()".to_string(),
+            ..args
+        };
+        assert!(run_rules_decrypt_action(&args, &mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_can_verify_masked_dumps() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| temp_dir.path().join(name).to_str().unwrap().to_string();
+        std::fs::write(
+            path("dump.sql"),
+            "INSERT INTO `users` (`id`, `email`, `notes`) VALUES (1, 'john@example.com', 'Call me'), (2, 'jane@example.com', 'Mail john@example.com');\n",
+        )
+        .unwrap();
+        std::fs::write(path("masking.yaml"), "columns:\n  - email\n").unwrap();
+
+        let args = MaskPIIArgs {
+            sql_file: path("dump.sql"),
+            masking_config: Some(path("masking.yaml")),
+            output: Some(path("masked.sql")),
+            masking_key: None,
+            explain: false,
+            vault: None,
+            vault_key: None,
+        };
+        run_mask_pii_action(&args).unwrap();
+
+        let args = VerifyMaskArgs {
+            original: path("dump.sql"),
+            masked: path("masked.sql"),
+            masking_config: Some(path("masking.yaml")),
+            columns: Vec::new(),
+            exclude: Vec::new(),
+            min_length: DEFAULT_MIN_LENGTH,
+            output: Some(path("report.txt")),
+        };
+        assert_eq!(run_verify_mask_action(&args).unwrap(), 1);
+        assert_eq!(
+            std::fs::read_to_string(path("report.txt")).unwrap(),
+            "`users`.`notes` row 2: original value of `users`.`email`\n1 original values found, from 1 masked columns\n"
+        );
+
+        std::fs::write(
+            path("masking.yaml"),
+            "columns:\n  - email\n  - notes\nrules:\n  notes: scrub('email', 'redact')\n",
+        )
+        .unwrap();
+        run_mask_pii_action(&MaskPIIArgs {
+            sql_file: path("dump.sql"),
+            masking_config: Some(path("masking.yaml")),
+            output: Some(path("masked.sql")),
+            masking_key: None,
+            explain: false,
+            vault: None,
+            vault_key: None,
+        })
+        .unwrap();
+        assert_eq!(run_verify_mask_action(&args).unwrap(), 0);
     }
 
     fn create_test_masking_config(temp_dir: &TempDir) -> PathBuf {
@@ -286,6 +786,7 @@ mod tests {
         let test_config = r#"
 columns:
     - account
+    - email
 patterns:
     - name: email
       regex: ^[a-zA-Z0-9_.+-]+@[a-zA-Z0-9-]+\.[a-zA-Z0-9-.]+$
@@ -298,14 +799,4 @@ rules:
         file.sync_data().unwrap();
         temp_file_in_path
     }
-
-    fn create_temp_sql_with_insert(temp_dir: &TempDir) -> PathBuf {
-        let temp_file_in_path = temp_dir.path().join("test.sql");
-        let sql_single_insert = r#"USE `users`;\nINSERT INTO users (id, name, email, password) VALUES (1, 'John Doe', 'john.doe@example.com', 'password');"#;
-        let mut file = std::fs::File::create(temp_file_in_path.clone()).unwrap();
-        file.write_all(sql_single_insert.as_bytes()).unwrap();
-        file.flush().unwrap();
-        file.sync_data().unwrap();
-        temp_file_in_path
-    }
 }
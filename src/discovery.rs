@@ -0,0 +1,428 @@
+use std::{collections::BTreeMap, io::Read};
+
+use serde::Serialize;
+
+use crate::{
+    masking::{ColumnRef, Masker, DEFAULT_THRESHOLD},
+    parser::types::DataType,
+    settings::{DetectionScope, MaskingConfig, ValuePattern},
+    ExtractResult,
+};
+
+/// Built-in detectors run on sampled values, with the rule suggested for
+/// the columns they flag.
+const DETECTORS: &[(&str, &str)] = &[
+    ("email", "contact::email()"),
+    ("phone", "contact::phone()"),
+    ("iban", "preserve_format(2)"),
+    ("credit_card", "payment::credit_card_luhn_number()"),
+    ("ip", "internet::ipv4_address()"),
+    ("ssn", "person::ssn()"),
+];
+
+/// Column name fragments that hint at PII, most specific first, with how
+/// strongly they do and the rule suggested for them.
+const NAME_HINTS: &[(&str, f64, &str)] = &[
+    ("email", 0.8, "contact::email()"),
+    ("mail", 0.6, "contact::email()"),
+    ("phone", 0.8, "contact::phone()"),
+    ("mobile", 0.8, "contact::phone()"),
+    ("fax", 0.6, "contact::phone()"),
+    ("ssn", 0.8, "person::ssn()"),
+    ("social_security", 0.8, "person::ssn()"),
+    ("pass", 0.8, "words::word()"),
+    ("first_name", 0.8, "name::first()"),
+    ("firstname", 0.8, "name::first()"),
+    ("last_name", 0.8, "name::last()"),
+    ("lastname", 0.8, "name::last()"),
+    ("surname", 0.8, "name::last()"),
+    ("full_name", 0.8, "name::full()"),
+    ("fullname", 0.8, "name::full()"),
+    ("realname", 0.8, "name::full()"),
+    ("username", 0.6, "internet::username()"),
+    ("login", 0.6, "internet::username()"),
+    ("name", 0.5, "name::full()"),
+    ("birth", 0.8, "shift_date()"),
+    ("dob", 0.6, "shift_date()"),
+    ("gender", 0.6, "person::gender()"),
+    ("ip", 0.5, "internet::ipv4_address()"),
+    ("street", 0.8, "address::street()"),
+    ("address", 0.6, "address::street()"),
+    ("city", 0.5, "address::city()"),
+    ("zip", 0.6, "address::zip()"),
+    ("postal", 0.6, "address::zip()"),
+    ("iban", 0.8, "preserve_format(2)"),
+    ("card", 0.6, "payment::credit_card_luhn_number()"),
+];
+
+/// Whether a column name contains `query`, ignoring case.
+pub fn name_matches(column: &str, query: &str) -> bool {
+    column.to_lowercase().contains(&query.to_lowercase())
+}
+
+/// The first name hint for `column`. Hints of up to three letters only
+/// match the start of a `_`-separated part of the name, so that `ip`
+/// matches `ip_address` but not `description`.
+fn name_hint(column: &str) -> Option<&'static (&'static str, f64, &'static str)> {
+    let lowercased = column.to_lowercase();
+    NAME_HINTS.iter().find(|(hint, _, _)| match hint.len() {
+        0..=3 => lowercased.split('_').any(|part| part.starts_with(hint)),
+        _ => name_matches(column, hint),
+    })
+}
+
+/// How likely a column of this type is to hold PII that needs masking.
+fn type_score(data_type: &DataType) -> f64 {
+    match data_type {
+        DataType::Char { .. }
+        | DataType::Varchar { .. }
+        | DataType::Text { .. }
+        | DataType::TinyText { .. }
+        | DataType::MediumText { .. }
+        | DataType::LongText { .. } => 1.0,
+        DataType::Date
+        | DataType::DateTime { .. }
+        | DataType::Timestamp { .. }
+        | DataType::Json => 0.5,
+        DataType::Int { .. }
+        | DataType::BigInt { .. }
+        | DataType::Decimal { .. }
+        | DataType::Binary { .. }
+        | DataType::Varbinary { .. } => 0.3,
+        _ => 0.0,
+    }
+}
+
+/// Tuning for [`discover`].
+#[derive(Debug, Clone)]
+pub struct DiscoveryOptions {
+    /// How many values of each column are run through the detectors.
+    pub sample_size: usize,
+    /// Columns scoring at least this much go into the starter config.
+    pub min_score: f64,
+}
+
+impl Default for DiscoveryOptions {
+    fn default() -> Self {
+        Self {
+            sample_size: 1000,
+            min_score: 0.6,
+        }
+    }
+}
+
+/// The PII signals found for one column.
+#[derive(Debug, Clone, Serialize)]
+pub struct ColumnReport {
+    pub database: Option<String>,
+    pub table: String,
+    pub column: String,
+    /// `None` when no `CREATE TABLE` was found for the column.
+    pub data_type: Option<String>,
+    /// PII likelihood, from 0 to 1.
+    pub score: f64,
+    pub name_hint: Option<String>,
+    /// The detector matching the largest share of the sampled values.
+    pub detector: Option<String>,
+    pub value_ratio: f64,
+    pub sampled: usize,
+    pub suggested_rule: Option<String>,
+}
+
+impl ColumnReport {
+    fn path(&self) -> String {
+        match self.database {
+            Some(ref database) => format!("{database}.{}.{}", self.table, self.column),
+            None => format!("{}.{}", self.table, self.column),
+        }
+    }
+}
+
+/// A PII inventory of a dump, ordered by database, table and column.
+#[derive(Debug, Clone, Serialize)]
+pub struct DiscoveryReport {
+    pub min_score: f64,
+    pub columns: Vec<ColumnReport>,
+}
+
+impl DiscoveryReport {
+    /// The columns scoring at least the minimum score.
+    pub fn flagged(&self) -> impl Iterator<Item = &ColumnReport> {
+        self.columns
+            .iter()
+            .filter(|column| column.score >= self.min_score)
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut markdown = String::from(
+            "| Column | Type | Score | Name hint | Values | Suggested rule |\n|---|---|---|---|---|---|\n",
+        );
+
+        for column in self.columns.iter() {
+            markdown.push_str(&format!(
+                "| {}{} | {} | {:.2} | {} | {} | {} |\n",
+                column.path(),
+                if column.score >= self.min_score {
+                    " **PII**"
+                } else {
+                    ""
+                },
+                column.data_type.as_deref().unwrap_or("?"),
+                column.score,
+                column.name_hint.as_deref().unwrap_or(""),
+                match column.detector {
+                    Some(ref detector) => format!(
+                        "{detector} {:.0}% of {}",
+                        column.value_ratio * 100.0,
+                        column.sampled
+                    ),
+                    None => format!("{} sampled", column.sampled),
+                },
+                column.suggested_rule.as_deref().unwrap_or(""),
+            ));
+        }
+
+        markdown
+    }
+
+    /// A masking config selecting every flagged column by its table, to
+    /// start from.
+    ///
+    /// Rules are keyed by column name, so the first suggestion for a name
+    /// wins when several tables share it.
+    pub fn starter_config(&self) -> String {
+        let mut columns = Vec::new();
+        let mut rules = BTreeMap::new();
+
+        for column in self.flagged() {
+            let name = column.column.clone();
+            columns.push(column.path());
+            if let Some(ref rule) = column.suggested_rule {
+                rules.entry(name).or_insert_with(|| rule.clone());
+            }
+        }
+
+        let mut yaml = String::from("# Generated by `sqlex discover-pii`, review before use.\n");
+        if columns.is_empty() {
+            yaml.push_str("columns: []\n");
+        } else {
+            yaml.push_str("columns:\n");
+            for column in columns.iter() {
+                yaml.push_str(&format!("  - {}\n", yaml_string(column)));
+            }
+        }
+        if !rules.is_empty() {
+            yaml.push_str("rules:\n");
+            for (column, rule) in rules.iter() {
+                yaml.push_str(&format!(
+                    "  {}: {}\n",
+                    yaml_string(column),
+                    yaml_string(rule)
+                ));
+            }
+        }
+
+        yaml
+    }
+}
+
+/// JSON strings are valid YAML scalars, whatever they contain.
+fn yaml_string(value: &str) -> String {
+    serde_json::to_string(value).expect("strings serialize to JSON")
+}
+
+/// Score every column of a dump for PII from its name, its type and a
+/// sample of its values.
+pub fn discover<R: Read>(input: R, options: &DiscoveryOptions) -> ExtractResult<DiscoveryReport> {
+    let config = MaskingConfig {
+        value_patterns: DETECTORS
+            .iter()
+            .map(|(name, _)| ValuePattern {
+                name: name.to_string(),
+                detector: None,
+                regex: None,
+                threshold: None,
+                scope: DetectionScope::Column,
+            })
+            .collect(),
+        ..Default::default()
+    };
+    let mut masker = Masker::new(&config)?;
+    masker.sample_values(options.sample_size);
+    masker.prepare(input)?;
+
+    let mut columns = BTreeMap::new();
+    for (database, table) in masker.tables() {
+        for (position, column) in table.columns.iter().enumerate() {
+            columns.insert(
+                (
+                    database.map(str::to_string),
+                    table.name.clone(),
+                    position,
+                    column.name.to_lowercase(),
+                ),
+                (column.name.clone(), Some(&column.data_type)),
+            );
+        }
+    }
+    for column in masker.detection().stats.keys() {
+        let known = columns.keys().any(|(database, table, _, name)| {
+            database == &column.database && table == &column.table && name == &column.column
+        });
+        if !known {
+            columns.insert(
+                (
+                    column.database.clone(),
+                    column.table.clone(),
+                    usize::MAX,
+                    column.column.clone(),
+                ),
+                (column.column.clone(), None),
+            );
+        }
+    }
+
+    let detection = masker.detection();
+    let columns = columns
+        .into_iter()
+        .map(|((database, table, _, _), (name, data_type))| {
+            let stats = detection
+                .stats
+                .get(&ColumnRef::new(database.as_deref(), &table, &name));
+            let sampled = stats.map(|stats| stats.values).unwrap_or_default();
+            let best = stats.and_then(|stats| {
+                (0..DETECTORS.len())
+                    .map(|index| (index, stats.ratio(index)))
+                    .filter(|(_, ratio)| *ratio > 0.0)
+                    .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            });
+            let hint = name_hint(&name);
+
+            let name_score = hint.map(|(_, strength, _)| *strength).unwrap_or_default();
+            let value_score = best.map(|(_, ratio)| ratio).unwrap_or_default();
+            let agreement = if name_score > 0.0 && value_score >= DEFAULT_THRESHOLD {
+                0.2
+            } else {
+                0.0
+            };
+            let evidence = (name_score.max(value_score) + agreement).min(1.0);
+            let type_factor = data_type.map(type_score).unwrap_or(1.0);
+            let score = (evidence * (0.5 + 0.5 * type_factor) * 100.0).round() / 100.0;
+
+            // Values outweigh the name once enough of them match
+            let detected_rule = best
+                .filter(|(_, ratio)| *ratio >= DEFAULT_THRESHOLD)
+                .map(|(index, _)| DETECTORS[index].1);
+            let suggested_rule = detected_rule
+                .or_else(|| hint.map(|(_, _, rule)| *rule))
+                .or_else(|| best.map(|(index, _)| DETECTORS[index].1))
+                .map(str::to_string);
+
+            ColumnReport {
+                database,
+                table,
+                column: name,
+                data_type: data_type.map(DataType::to_string),
+                score,
+                name_hint: hint.map(|(hint, _, _)| hint.to_string()),
+                detector: best.map(|(index, _)| DETECTORS[index].0.to_string()),
+                value_ratio: best.map(|(_, ratio)| ratio).unwrap_or_default(),
+                sampled,
+                suggested_rule,
+            }
+        })
+        .collect();
+
+    Ok(DiscoveryReport {
+        min_score: options.min_score,
+        columns,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn can_match_name_hints() {
+        assert_eq!(name_hint("contact_email").unwrap().0, "email");
+        assert_eq!(name_hint("ipaddress").unwrap().0, "ip");
+        assert_eq!(name_hint("zip_code").unwrap().0, "zip");
+        assert!(name_hint("description").is_none());
+        assert!(name_hint("time").is_none());
+        assert!(name_matches("Password_Salt", "pass"));
+    }
+
+    #[test]
+    fn suggests_known_rules() {
+        let suggestions = NAME_HINTS
+            .iter()
+            .map(|(_, _, rule)| *rule)
+            .chain(DETECTORS.iter().map(|(_, rule)| *rule));
+
+        for rule in suggestions {
+            assert!(
+                rule.parse::<crate::settings::MaskingRule>().is_ok(),
+                "{rule}"
+            );
+        }
+    }
+
+    #[test]
+    fn can_discover_pii() {
+        let input = std::fs::File::open("./tests/schema_dump.sql").unwrap();
+        let report = discover(input, &DiscoveryOptions::default()).unwrap();
+        let column = |table: &str, name: &str| {
+            report
+                .columns
+                .iter()
+                .find(|column| column.table == table && column.column == name)
+                .unwrap()
+        };
+
+        let email = column("users", "contact_email");
+        assert_eq!(email.data_type.as_deref(), Some("VARCHAR (255)"));
+        assert_eq!(email.detector.as_deref(), Some("email"));
+        assert_eq!(email.sampled, 2);
+        assert_eq!(email.score, 1.0);
+        assert_eq!(email.suggested_rule.as_deref(), Some("contact::email()"));
+
+        let ip = column("users", "ipaddress");
+        assert_eq!(ip.detector.as_deref(), Some("ip"));
+        assert_eq!(ip.score, 1.0);
+
+        assert_eq!(column("users", "password").score, 0.8);
+        assert_eq!(column("users", "time").score, 0.0);
+        assert!(column("config", "value").score < report.min_score);
+
+        let markdown = report.to_markdown();
+        assert!(markdown.contains("| namedmanager.users.contact_email **PII** | VARCHAR (255) | 1.00 | email | email 100% of 2 | contact::email() |"));
+
+        let starter = report.starter_config();
+        let config: MaskingConfig = config::Config::builder()
+            .add_source(config::File::from_str(&starter, config::FileFormat::Yaml))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+        assert!(config
+            .columns
+            .contains(&"namedmanager.users.contact_email".to_string()));
+        assert!(config
+            .columns
+            .contains(&"namedmanager.users.password".to_string()));
+        assert!(!config
+            .columns
+            .iter()
+            .any(|column| column.ends_with(".time")));
+        assert_eq!(config.rules["contact_email"].name(), "contact::email()");
+
+        // Every flagged column has a rule, so the starter config masks
+        let mut masker = Masker::new(&config).unwrap();
+        let input = std::fs::File::open("./tests/schema_dump.sql").unwrap();
+        masker.mask_dump(input, &mut Vec::new()).unwrap();
+        assert!(report
+            .flagged()
+            .all(|column| config.rules.contains_key(&column.column)));
+    }
+}
@@ -0,0 +1,348 @@
+use std::{
+    fmt::{Display, Formatter, Result as FmtResult},
+    io::{ErrorKind, Read},
+};
+
+use anyhow::Context;
+
+use crate::ExtractResult;
+
+/// How many bytes are requested from the underlying reader at a time.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// A single statement from a SQL dump, together with the whitespace and
+/// comments that preceded it, so that the dump can be written back out
+/// byte-for-byte.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DumpStatement {
+    /// Whitespace and comments between the previous statement and this one.
+    pub leading: String,
+    /// The statement itself, without its delimiter.
+    pub body: String,
+    /// The delimiter that ended the statement, empty at the end of input and
+    /// for `DELIMITER` commands, which end at the end of their line.
+    pub delimiter: String,
+}
+
+impl DumpStatement {
+    /// The first keyword of the statement, uppercased.
+    pub fn keyword(&self) -> String {
+        self.body
+            .split(|c: char| !c.is_ascii_alphabetic())
+            .next()
+            .unwrap_or_default()
+            .to_ascii_uppercase()
+    }
+
+    /// Replace the body, keeping the surrounding comments and delimiter.
+    pub fn with_body(&self, body: String) -> Self {
+        Self {
+            leading: self.leading.clone(),
+            body,
+            delimiter: self.delimiter.clone(),
+        }
+    }
+}
+
+impl Display for DumpStatement {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}{}{}", self.leading, self.body, self.delimiter)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    Normal,
+    Quoted(u8),
+    LineComment,
+    BlockComment,
+}
+
+/// Reads a SQL dump one statement at a time from any [`Read`], so that
+/// memory use is bounded by the largest single statement rather than the
+/// size of the dump.
+///
+/// Delimiters inside quoted strings, quoted identifiers and comments are
+/// ignored, and `DELIMITER` commands change the delimiter for the
+/// statements that follow.
+pub struct StatementReader<R> {
+    reader: R,
+    buffer: Vec<u8>,
+    position: usize,
+    eof: bool,
+    delimiter: Vec<u8>,
+}
+
+impl<R: Read> StatementReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buffer: Vec::with_capacity(CHUNK_SIZE),
+            position: 0,
+            eof: false,
+            delimiter: b";".to_vec(),
+        }
+    }
+
+    /// The byte `offset` bytes past the current position, reading more
+    /// input when the buffer runs out.
+    fn peek(&mut self, offset: usize) -> ExtractResult<Option<u8>> {
+        while self.position + offset >= self.buffer.len() && !self.eof {
+            self.fill()?;
+        }
+
+        Ok(self.buffer.get(self.position + offset).copied())
+    }
+
+    fn fill(&mut self) -> ExtractResult<()> {
+        self.buffer.drain(..self.position);
+        self.position = 0;
+
+        let len = self.buffer.len();
+        self.buffer.resize(len + CHUNK_SIZE, 0);
+
+        let read = loop {
+            match self.reader.read(&mut self.buffer[len..]) {
+                Ok(read) => break read,
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err).context("unable to read sql dump"),
+            }
+        };
+
+        self.buffer.truncate(len + read);
+        self.eof = read == 0;
+
+        Ok(())
+    }
+
+    fn starts_with(&mut self, pattern: &[u8]) -> ExtractResult<bool> {
+        for (offset, expected) in pattern.iter().enumerate() {
+            match self.peek(offset)? {
+                Some(byte) if byte.eq_ignore_ascii_case(expected) => {}
+                _ => return Ok(false),
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// `--` only starts a comment when followed by whitespace or the end of input.
+    fn at_line_comment(&mut self) -> ExtractResult<bool> {
+        Ok(self.starts_with(b"--")?
+            && self
+                .peek(2)?
+                .map(|byte| byte.is_ascii_whitespace())
+                .unwrap_or(true))
+    }
+
+    /// `DELIMITER` is a client command that can only start a statement.
+    fn at_delimiter_command(&mut self) -> ExtractResult<bool> {
+        Ok(self.starts_with(b"DELIMITER")?
+            && self
+                .peek(9)?
+                .map(|byte| byte == b' ' || byte == b'\t')
+                .unwrap_or(false))
+    }
+
+    /// Consume `len` bytes into `target`.
+    fn take(&mut self, len: usize, target: &mut Vec<u8>) {
+        target.extend_from_slice(&self.buffer[self.position..self.position + len]);
+        self.position += len;
+    }
+
+    /// Read the next statement, or `None` once the input is exhausted.
+    pub fn read_statement(&mut self) -> ExtractResult<Option<DumpStatement>> {
+        let mut leading = Vec::new();
+        let mut body = Vec::new();
+        let mut delimiter = Vec::new();
+        let mut state = State::Normal;
+
+        while let Some(byte) = self.peek(0)? {
+            match state {
+                State::Normal => {
+                    if byte == self.delimiter[0] && self.starts_with(&self.delimiter.clone())? {
+                        self.take(self.delimiter.len(), &mut delimiter);
+                        break;
+                    }
+
+                    match byte {
+                        b'\'' | b'"' | b'`' => {
+                            state = State::Quoted(byte);
+                            self.take(1, &mut body);
+                            continue;
+                        }
+                        b'D' | b'd' if body.is_empty() && self.at_delimiter_command()? => {
+                            while !matches!(self.peek(0)?, None | Some(b'\n')) {
+                                self.take(1, &mut body);
+                            }
+                            self.delimiter = String::from_utf8_lossy(&body[9..])
+                                .trim()
+                                .as_bytes()
+                                .to_vec();
+                            if self.delimiter.is_empty() {
+                                self.delimiter = b";".to_vec();
+                            }
+                            break;
+                        }
+                        b'#' => state = State::LineComment,
+                        b'-' if self.at_line_comment()? => state = State::LineComment,
+                        b'/' if self.peek(1)? == Some(b'*') => {
+                            state = State::BlockComment;
+                            self.take(2, target(&mut leading, &mut body));
+                            continue;
+                        }
+                        byte if byte.is_ascii_whitespace() => {}
+                        _ => {
+                            self.take(1, &mut body);
+                            continue;
+                        }
+                    }
+                }
+                State::Quoted(quote) => {
+                    if byte == b'\\' && quote != b'`' && self.peek(1)?.is_some() {
+                        self.take(2, target(&mut leading, &mut body));
+                        continue;
+                    }
+                    if byte == quote {
+                        state = State::Normal;
+                    }
+                }
+                State::LineComment => {
+                    if byte == b'\n' {
+                        state = State::Normal;
+                    }
+                }
+                State::BlockComment => {
+                    if byte == b'*' && self.peek(1)? == Some(b'/') {
+                        state = State::Normal;
+                        self.take(2, target(&mut leading, &mut body));
+                        continue;
+                    }
+                }
+            }
+
+            self.take(1, target(&mut leading, &mut body));
+        }
+
+        if leading.is_empty() && body.is_empty() && delimiter.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(DumpStatement {
+            leading: String::from_utf8(leading).context("sql dump is not valid UTF-8")?,
+            body: String::from_utf8(body).context("sql dump is not valid UTF-8")?,
+            delimiter: String::from_utf8(delimiter).context("sql dump is not valid UTF-8")?,
+        }))
+    }
+}
+
+/// Comments and whitespace belong to the leading trivia until the body of
+/// the statement has started.
+fn target<'a>(leading: &'a mut Vec<u8>, body: &'a mut Vec<u8>) -> &'a mut Vec<u8> {
+    if body.is_empty() {
+        leading
+    } else {
+        body
+    }
+}
+
+impl<R: Read> Iterator for StatementReader<R> {
+    type Item = ExtractResult<DumpStatement>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_statement().transpose()
+    }
+}
+
+/// Split an in-memory SQL string into statements.
+pub fn split_statements(input: &str) -> ExtractResult<Vec<DumpStatement>> {
+    StatementReader::new(input.as_bytes()).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Hands out its input one byte per read, to exercise buffer boundaries.
+    struct ByteReader<'a>(&'a [u8]);
+
+    impl Read for ByteReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            match self.0.split_first() {
+                Some((byte, rest)) if !buf.is_empty() => {
+                    buf[0] = *byte;
+                    self.0 = rest;
+                    Ok(1)
+                }
+                _ => Ok(0),
+            }
+        }
+    }
+
+    #[test]
+    fn can_split_statements() {
+        let input =
+            "-- header\nUSE `db`;\n/* a; b */\nINSERT INTO `t` VALUES ('a;b', 'it\\'s;');\n";
+        let statements = split_statements(input).unwrap();
+
+        assert_eq!(statements.len(), 3);
+        assert_eq!(statements[0].leading, "-- header\n");
+        assert_eq!(statements[0].keyword(), "USE");
+        assert_eq!(statements[1].leading, "\n/* a; b */\n");
+        assert_eq!(
+            statements[1].body,
+            "INSERT INTO `t` VALUES ('a;b', 'it\\'s;')"
+        );
+        assert_eq!(statements[2].body, "");
+        assert_eq!(
+            statements.iter().map(|s| s.to_string()).collect::<String>(),
+            input
+        );
+    }
+
+    #[test]
+    fn can_split_statements_across_reads() {
+        let input = "/*!40101 SET NAMES utf8 */;\n# comment; here\nINSERT INTO `t` VALUES ('é;\\\\', \"x;\", 1--1);\n-- done;";
+        let statements = StatementReader::new(ByteReader(input.as_bytes()))
+            .collect::<ExtractResult<Vec<DumpStatement>>>()
+            .unwrap();
+
+        assert_eq!(statements, split_statements(input).unwrap());
+        assert_eq!(statements.len(), 3);
+        assert_eq!(statements[0].leading, "/*!40101 SET NAMES utf8 */");
+        assert_eq!(statements[0].body, "");
+        assert_eq!(
+            statements[1].body,
+            "INSERT INTO `t` VALUES ('é;\\\\', \"x;\", 1--1)"
+        );
+        assert_eq!(statements[2].leading, "\n-- done;");
+        assert_eq!(
+            statements.iter().map(|s| s.to_string()).collect::<String>(),
+            input
+        );
+    }
+
+    #[test]
+    fn can_change_delimiter() {
+        let input = "DELIMITER ;;\nCREATE TRIGGER `t` BEFORE INSERT ON `x` FOR EACH ROW BEGIN SET @a = 1; END ;;\nDELIMITER ;\nUSE `db`;";
+        let statements = StatementReader::new(ByteReader(input.as_bytes()))
+            .collect::<ExtractResult<Vec<DumpStatement>>>()
+            .unwrap();
+
+        assert_eq!(statements.len(), 4);
+        assert_eq!(statements[0].keyword(), "DELIMITER");
+        assert_eq!(statements[0].delimiter, "");
+        assert_eq!(
+            statements[1].body,
+            "CREATE TRIGGER `t` BEFORE INSERT ON `x` FOR EACH ROW BEGIN SET @a = 1; END "
+        );
+        assert_eq!(statements[1].delimiter, ";;");
+        assert_eq!(statements[2].body, "DELIMITER ;");
+        assert_eq!(statements[3].body, "USE `db`");
+        assert_eq!(statements[3].delimiter, ";");
+        assert_eq!(
+            statements.iter().map(|s| s.to_string()).collect::<String>(),
+            input
+        );
+    }
+}
@@ -1,4 +1,7 @@
 pub mod cmd;
+pub mod discovery;
+pub mod dump;
+pub mod masking;
 pub mod parser;
 pub mod rules;
 pub mod settings;
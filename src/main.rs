@@ -1,5 +1,8 @@
 use sqlex::cmd::{self};
 
 fn main() {
-    cmd::exec();
+    if let Err(err) = cmd::exec() {
+        eprintln!("{err:#}");
+        std::process::exit(1);
+    }
 }
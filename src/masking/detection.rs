@@ -0,0 +1,457 @@
+use std::{
+    collections::HashMap,
+    net::{Ipv4Addr, Ipv6Addr},
+    ops::Range,
+};
+
+use anyhow::{bail, Context};
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::{
+    settings::{DetectionScope, ValuePattern},
+    ExtractResult,
+};
+
+use super::ColumnRef;
+
+/// Share of the values of a column that must match a detector for the
+/// column to be flagged, unless the pattern sets its own threshold.
+pub const DEFAULT_THRESHOLD: f64 = 0.8;
+
+lazy_static! {
+    static ref EMAIL: Regex =
+        Regex::new(r"^[A-Za-z0-9._%+-]+@[A-Za-z0-9-]+(\.[A-Za-z0-9-]+)*\.[A-Za-z]{2,}$").unwrap();
+    static ref PHONE: Regex = Regex::new(r"^\+?[0-9][0-9 ().-]{5,}[0-9]$").unwrap();
+    static ref IBAN: Regex = Regex::new(r"^[A-Z]{2}[0-9]{2}[A-Z0-9]{11,30}$").unwrap();
+    static ref CREDIT_CARD: Regex = Regex::new(r"^[0-9][0-9 -]{11,22}[0-9]$").unwrap();
+    static ref SSN: Regex = Regex::new(r"^([0-9]{3})-([0-9]{2})-([0-9]{4})$").unwrap();
+    /// Where each detector may match inside longer text, before checking.
+    static ref EMAIL_IN_TEXT: Regex =
+        Regex::new(r"[A-Za-z0-9._%+-]+@[A-Za-z0-9-]+(\.[A-Za-z0-9-]+)*\.[A-Za-z]{2,}").unwrap();
+    static ref PHONE_IN_TEXT: Regex = Regex::new(r"(\+|\b)[0-9][0-9 ().-]{5,}[0-9]\b").unwrap();
+    static ref IBAN_IN_TEXT: Regex =
+        Regex::new(r"(?i)\b[A-Z]{2}[0-9]{2}( ?[A-Z0-9]){11,30}\b").unwrap();
+    static ref CREDIT_CARD_IN_TEXT: Regex = Regex::new(r"\b[0-9][0-9 -]{11,22}[0-9]\b").unwrap();
+    static ref IP_IN_TEXT: Regex = Regex::new(
+        r"(?i)\b([0-9]{1,3}\.){3}[0-9]{1,3}\b|(\b[0-9a-f]{1,4}|::)(:[0-9a-f]{0,4}){2,7}\b"
+    )
+    .unwrap();
+    static ref SSN_IN_TEXT: Regex = Regex::new(r"\b[0-9]{3}-[0-9]{2}-[0-9]{4}\b").unwrap();
+    static ref DATE: Regex = Regex::new(r"^[0-9]{4}-[0-9]{2}-[0-9]{2}$").unwrap();
+}
+
+/// The detectors that can be used by name in `value_patterns`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Builtin {
+    Email,
+    Phone,
+    Iban,
+    CreditCard,
+    Ip,
+    Ssn,
+}
+
+impl Builtin {
+    /// Every detector, the more specific ones first, which is the order
+    /// they claim the parts of a text in.
+    pub const ALL: [Self; 6] = [
+        Self::Email,
+        Self::Iban,
+        Self::CreditCard,
+        Self::Ssn,
+        Self::Ip,
+        Self::Phone,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "email" => Some(Self::Email),
+            "phone" => Some(Self::Phone),
+            "iban" => Some(Self::Iban),
+            "credit_card" => Some(Self::CreditCard),
+            "ip" => Some(Self::Ip),
+            "ssn" => Some(Self::Ssn),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Email => "email",
+            Self::Phone => "phone",
+            Self::Iban => "iban",
+            Self::CreditCard => "credit_card",
+            Self::Ip => "ip",
+            Self::Ssn => "ssn",
+        }
+    }
+
+    /// The byte ranges of `text` the detector matches. A candidate that
+    /// does not match as a whole is cut back to its longest start that
+    /// does, as trailing words or numbers may have been taken along.
+    pub fn find_in(&self, text: &str) -> Vec<Range<usize>> {
+        let candidates: &Regex = match self {
+            Self::Email => &EMAIL_IN_TEXT,
+            Self::Phone => &PHONE_IN_TEXT,
+            Self::Iban => &IBAN_IN_TEXT,
+            Self::CreditCard => &CREDIT_CARD_IN_TEXT,
+            Self::Ip => &IP_IN_TEXT,
+            Self::Ssn => &SSN_IN_TEXT,
+        };
+
+        candidates
+            .find_iter(text)
+            .filter_map(|candidate| {
+                let value = candidate.as_str();
+                let ends = value
+                    .char_indices()
+                    .filter(|(_, c)| c.is_ascii_alphanumeric())
+                    .map(|(index, c)| index + c.len_utf8())
+                    .filter(|&end| {
+                        value[end..]
+                            .chars()
+                            .next()
+                            .is_none_or(|next| !next.is_ascii_alphanumeric())
+                    })
+                    .collect::<Vec<usize>>();
+                let end = ends.into_iter().rev().find(|&end| {
+                    let value = &value[..end];
+                    // Dates look like phone numbers too
+                    self.matches(value) && !(*self == Self::Phone && DATE.is_match(value))
+                })?;
+                Some(candidate.start()..candidate.start() + end)
+            })
+            .collect()
+    }
+
+    pub fn matches(&self, value: &str) -> bool {
+        match self {
+            Self::Email => EMAIL.is_match(value),
+            // Bare digits are only taken for a phone number with a leading
+            // trunk prefix, as they are far more often ids or timestamps.
+            Self::Phone => {
+                PHONE.is_match(value)
+                    && (7..=15).contains(&value.chars().filter(char::is_ascii_digit).count())
+                    && (value.starts_with('0') || !value.chars().all(|c| c.is_ascii_digit()))
+            }
+            Self::Iban => {
+                let compact = value.replace(' ', "").to_ascii_uppercase();
+                IBAN.is_match(&compact) && iban_checksum(&compact)
+            }
+            Self::CreditCard => {
+                let digits = value
+                    .chars()
+                    .filter(char::is_ascii_digit)
+                    .collect::<String>();
+                CREDIT_CARD.is_match(value) && (13..=19).contains(&digits.len()) && luhn(&digits)
+            }
+            Self::Ip => value.parse::<Ipv4Addr>().is_ok() || value.parse::<Ipv6Addr>().is_ok(),
+            Self::Ssn => SSN.captures(value).is_some_and(|captures| {
+                let area = &captures[1];
+                area != "000"
+                    && area != "666"
+                    && !area.starts_with('9')
+                    && &captures[2] != "00"
+                    && &captures[3] != "0000"
+            }),
+        }
+    }
+}
+
+/// The Luhn checksum used by payment card numbers.
+pub fn luhn(digits: &str) -> bool {
+    let sum = digits
+        .bytes()
+        .rev()
+        .enumerate()
+        .map(|(position, digit)| {
+            let digit = u32::from(digit - b'0');
+            match position % 2 {
+                0 => digit,
+                _ if digit * 2 > 9 => digit * 2 - 9,
+                _ => digit * 2,
+            }
+        })
+        .sum::<u32>();
+
+    !digits.is_empty() && sum % 10 == 0
+}
+
+/// The ISO 13616 mod-97 check of an IBAN without spaces.
+fn iban_checksum(iban: &str) -> bool {
+    iban_remainder(iban) == 1
+}
+
+/// The remainder of an IBAN without spaces, with its first four characters
+/// moved to the end, divided by 97.
+pub(crate) fn iban_remainder(iban: &str) -> u32 {
+    let (head, tail) = iban.split_at(4);
+    let remainder = tail
+        .chars()
+        .chain(head.chars())
+        .filter_map(|c| c.to_digit(36))
+        .fold(0u32, |remainder, digit| match digit {
+            0..=9 => (remainder * 10 + digit) % 97,
+            _ => (remainder * 100 + digit) % 97,
+        });
+
+    remainder
+}
+
+#[derive(Debug)]
+enum Check {
+    Builtin(Builtin),
+    Regex(Regex),
+}
+
+/// A compiled `value_patterns` entry, which recognises PII from the values
+/// of a column rather than from its name.
+#[derive(Debug)]
+pub struct Detector {
+    /// Also the name of the rule used for the values it detects.
+    pub name: String,
+    pub threshold: f64,
+    pub scope: DetectionScope,
+    check: Check,
+}
+
+impl Detector {
+    pub fn new(pattern: &ValuePattern) -> ExtractResult<Self> {
+        let check = match (&pattern.regex, &pattern.detector) {
+            (Some(_), Some(_)) => bail!(
+                "value pattern `{}` sets both a regex and a detector",
+                pattern.name
+            ),
+            (Some(regex), None) => {
+                Check::Regex(Regex::new(regex).with_context(|| {
+                    format!("invalid regex for value pattern `{}`", pattern.name)
+                })?)
+            }
+            (None, detector) => {
+                let detector = detector.as_deref().unwrap_or(&pattern.name);
+                Check::Builtin(Builtin::from_name(detector).with_context(|| {
+                    format!(
+                        "unknown detector `{detector}` for value pattern `{}`, expected one of email, phone, iban, credit_card, ip or ssn",
+                        pattern.name
+                    )
+                })?)
+            }
+        };
+        let threshold = pattern.threshold.unwrap_or(DEFAULT_THRESHOLD);
+        if !(0.0..=1.0).contains(&threshold) {
+            bail!(
+                "threshold of value pattern `{}` must be between 0 and 1",
+                pattern.name
+            );
+        }
+
+        Ok(Self {
+            name: pattern.name.clone(),
+            threshold,
+            scope: pattern.scope,
+            check,
+        })
+    }
+
+    pub fn matches(&self, value: &str) -> bool {
+        let value = value.trim();
+        match &self.check {
+            Check::Builtin(builtin) => builtin.matches(value),
+            Check::Regex(regex) => regex.is_match(value),
+        }
+    }
+}
+
+/// How many values of a column were seen, and how many of them each
+/// detector matched.
+#[derive(Debug, Clone, Default)]
+pub struct ColumnStats {
+    pub values: usize,
+    pub matches: Vec<usize>,
+}
+
+impl ColumnStats {
+    /// The share of values matched by the detector at `index`.
+    pub fn ratio(&self, index: usize) -> f64 {
+        match self.values {
+            0 => 0.0,
+            values => self.matches.get(index).copied().unwrap_or_default() as f64 / values as f64,
+        }
+    }
+}
+
+/// Collects [`ColumnStats`] for every column a set of detectors is run on.
+#[derive(Debug)]
+pub struct ValueDetection {
+    pub detectors: Vec<Detector>,
+    pub stats: HashMap<ColumnRef, ColumnStats>,
+    /// Stop recording the values of a column after this many.
+    pub sample_size: Option<usize>,
+}
+
+impl ValueDetection {
+    pub fn new(patterns: &[ValuePattern]) -> ExtractResult<Self> {
+        Ok(Self {
+            detectors: patterns
+                .iter()
+                .map(Detector::new)
+                .collect::<ExtractResult<Vec<Detector>>>()?,
+            stats: HashMap::new(),
+            sample_size: None,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.detectors.is_empty()
+    }
+
+    /// Record a non-NULL value of `column`.
+    pub fn record(&mut self, column: &ColumnRef, value: &str) {
+        let stats = self
+            .stats
+            .entry(column.clone())
+            .or_insert_with(|| ColumnStats {
+                values: 0,
+                matches: vec![0; self.detectors.len()],
+            });
+
+        if self
+            .sample_size
+            .is_some_and(|sample_size| stats.values >= sample_size)
+        {
+            return;
+        }
+
+        stats.values += 1;
+        for (index, detector) in self.detectors.iter().enumerate() {
+            if detector.matches(value) {
+                stats.matches[index] += 1;
+            }
+        }
+    }
+
+    /// The first column-scoped detector whose threshold the values of
+    /// `column` reach.
+    pub fn flagged(&self, column: &ColumnRef) -> Option<&Detector> {
+        let stats = self.stats.get(column)?;
+        self.detectors
+            .iter()
+            .enumerate()
+            .filter(|(_, detector)| detector.scope == DetectionScope::Column)
+            .find(|(index, detector)| stats.values > 0 && stats.ratio(*index) >= detector.threshold)
+            .map(|(_, detector)| detector)
+    }
+
+    /// The first cell-scoped detector that matches `value`.
+    pub fn matching_cell(&self, value: &str) -> Option<&Detector> {
+        self.detectors
+            .iter()
+            .filter(|detector| detector.scope == DetectionScope::Cell)
+            .find(|detector| detector.matches(value))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn pattern(name: &str) -> ValuePattern {
+        ValuePattern {
+            name: name.to_string(),
+            detector: None,
+            regex: None,
+            threshold: None,
+            scope: DetectionScope::Column,
+        }
+    }
+
+    #[test]
+    fn can_detect_builtin_pii() {
+        let cases = [
+            (Builtin::Email, "john.doe@example.co.uk", "john.doe@"),
+            (Builtin::Phone, "+1 (555) 010-9999", "12-34"),
+            (Builtin::Phone, "0612345678", "1675855555"),
+            (
+                Builtin::Iban,
+                "GB82 WEST 1234 5698 7654 32",
+                "GB83WEST12345698765432",
+            ),
+            (
+                Builtin::CreditCard,
+                "4111 1111 1111 1111",
+                "4111 1111 1111 1112",
+            ),
+            (Builtin::Ip, "192.168.0.1", "192.168.0.256"),
+            (Builtin::Ip, "2001:db8::1", "2001:db8::g"),
+            (Builtin::Ssn, "123-45-6789", "666-45-6789"),
+        ];
+
+        for (builtin, matching, not_matching) in cases {
+            assert!(builtin.matches(matching), "{builtin:?} {matching}");
+            assert!(!builtin.matches(not_matching), "{builtin:?} {not_matching}");
+        }
+    }
+
+    #[test]
+    fn can_find_pii_in_text() {
+        let text = "Mail john@example.com or call +1 (555) 010-9999 before 2024-01-05. \
+            Card 4111 1111 1111 1111 10 times, IBAN GB82 WEST 1234 5698 7654 32 NOW, \
+            host 10.0.0.1, SSN 123-45-6789, order 1234567.";
+        let found = |builtin: Builtin| {
+            builtin
+                .find_in(text)
+                .into_iter()
+                .map(|range| &text[range])
+                .collect::<Vec<&str>>()
+        };
+
+        assert_eq!(found(Builtin::Email), ["john@example.com"]);
+        // Phone numbers overlap the more specific detectors
+        assert_eq!(found(Builtin::Phone)[0], "+1 (555) 010-9999");
+        assert!(!found(Builtin::Phone).contains(&"2024-01-05"));
+        assert_eq!(found(Builtin::CreditCard), ["4111 1111 1111 1111"]);
+        assert_eq!(found(Builtin::Iban), ["GB82 WEST 1234 5698 7654 32"]);
+        assert_eq!(found(Builtin::Ip), ["10.0.0.1"]);
+        assert_eq!(found(Builtin::Ssn), ["123-45-6789"]);
+    }
+
+    #[test]
+    fn can_flag_columns_above_threshold() {
+        let mut detection = ValueDetection::new(&[
+            pattern("email"),
+            ValuePattern {
+                regex: Some("^E[0-9]{4}$".to_string()),
+                threshold: Some(0.5),
+                ..pattern("employee")
+            },
+        ])
+        .unwrap();
+        let contact = ColumnRef::new(None, "users", "contact");
+        let code = ColumnRef::new(None, "users", "code");
+
+        for value in ["a@example.com", "b@example.com", "n/a", "c@example.com"] {
+            detection.record(&contact, value);
+        }
+        for value in ["E1234", "x", "E0001", "y", "z"] {
+            detection.record(&code, value);
+        }
+
+        assert!(detection.flagged(&contact).is_none());
+        detection.record(&contact, "d@example.com");
+        assert_eq!(detection.flagged(&contact).unwrap().name, "email");
+        assert!(detection.flagged(&code).is_none());
+        detection.record(&code, "E0002");
+        assert_eq!(detection.flagged(&code).unwrap().name, "employee");
+    }
+
+    #[test]
+    fn rejects_unknown_detectors() {
+        assert!(Detector::new(&pattern("passport")).is_err());
+        assert!(Detector::new(&ValuePattern {
+            threshold: Some(2.0),
+            ..pattern("email")
+        })
+        .is_err());
+    }
+}
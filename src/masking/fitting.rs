@@ -0,0 +1,475 @@
+use sha2::{Digest, Sha256};
+
+use crate::parser::{parse_utils::unescape_str, types::DataType, types::InsertValue};
+
+/// Seconds in a day.
+pub(crate) const DAY: i64 = 86_400;
+
+/// Make `value`, the replacement for a value of a column of type
+/// `data_type`, fit that column so the masked dump loads under strict mode.
+///
+/// Values that already fit are kept as they are. Values that are too long
+/// are truncated, numbers are brought into the range of the column, and
+/// anything that cannot be read as the type at all is replaced by a value
+/// derived from it, so the same replacement always fits to the same value.
+pub fn fit(value: &str, data_type: &DataType) -> InsertValue {
+    match data_type {
+        DataType::TinyInt { unsigned, .. } => integer(value, 8, *unsigned),
+        DataType::SmallInt { unsigned, .. } => integer(value, 16, *unsigned),
+        DataType::MediumInt { unsigned, .. } => integer(value, 24, *unsigned),
+        DataType::Int { unsigned, .. } => integer(value, 32, *unsigned),
+        DataType::BigInt { unsigned, .. } => integer(value, 64, *unsigned),
+        DataType::Decimal { m, d, unsigned, .. } => {
+            decimal(value, m.unwrap_or(10), d.unwrap_or(0), *unsigned)
+        }
+        DataType::Float { m, d, unsigned, .. } | DataType::Double { m, d, unsigned, .. } => match m
+        {
+            Some(m) => decimal(value, *m, d.unwrap_or(0), *unsigned),
+            None => float(value, *unsigned),
+        },
+        DataType::Bit { m } => integer(value, m.unwrap_or(1).min(64), true),
+        DataType::Date => InsertValue::text(&date(value)),
+        DataType::DateTime { fsp } => InsertValue::text(&datetime(
+            value,
+            fsp.unwrap_or(0),
+            (MIN_DATETIME, MAX_DATETIME),
+        )),
+        DataType::Timestamp { fsp } => InsertValue::text(&datetime(
+            value,
+            fsp.unwrap_or(0),
+            (MIN_TIMESTAMP, MAX_TIMESTAMP),
+        )),
+        DataType::Time { fsp } => InsertValue::text(&time(value, fsp.unwrap_or(0))),
+        DataType::Year { .. } => year(value),
+        DataType::Char { m, .. } => InsertValue::text(&truncate_chars(value, m.unwrap_or(1))),
+        DataType::Varchar { m, .. } => {
+            InsertValue::text(&truncate_chars(value, m.unwrap_or(u32::MAX)))
+        }
+        DataType::Binary { m } => InsertValue::text(&truncate_bytes(value, m.unwrap_or(1))),
+        DataType::Varbinary { m } => InsertValue::text(&truncate_bytes(value, *m)),
+        DataType::Blob { m } | DataType::Text { m, .. } => {
+            InsertValue::text(&truncate_bytes(value, m.unwrap_or(65_535)))
+        }
+        DataType::TinyBlob | DataType::TinyText { .. } => {
+            InsertValue::text(&truncate_bytes(value, 255))
+        }
+        DataType::MediumBlob | DataType::MediumText { .. } => {
+            InsertValue::text(&truncate_bytes(value, 16_777_215))
+        }
+        DataType::LongBlob | DataType::LongText { .. } => InsertValue::text(value),
+        DataType::Enum { values, .. } => InsertValue::text(&member(value, values)),
+        DataType::Set { values, .. } => InsertValue::text(&members(value, values)),
+        DataType::Json => match serde_json::from_str::<serde_json::Value>(value) {
+            Ok(_) => InsertValue::text(value),
+            Err(_) => InsertValue::text(&serde_json::Value::from(value).to_string()),
+        },
+    }
+}
+
+/// The value used instead of `NULL` in a `NOT NULL` column.
+pub fn not_null(data_type: &DataType) -> InsertValue {
+    match data_type {
+        DataType::TinyInt { .. }
+        | DataType::SmallInt { .. }
+        | DataType::MediumInt { .. }
+        | DataType::Int { .. }
+        | DataType::BigInt { .. }
+        | DataType::Decimal { .. }
+        | DataType::Float { .. }
+        | DataType::Double { .. }
+        | DataType::Bit { .. } => fit("0", data_type),
+        DataType::Date => fit("1970-01-01", data_type),
+        DataType::DateTime { .. } | DataType::Timestamp { .. } => {
+            fit("1970-01-02 00:00:00", data_type)
+        }
+        DataType::Time { .. } => fit("00:00:00", data_type),
+        DataType::Year { .. } => fit("1970", data_type),
+        DataType::Enum { values, .. } => fit(
+            values
+                .first()
+                .map(|value| unescape_str(value))
+                .as_deref()
+                .unwrap_or_default(),
+            data_type,
+        ),
+        DataType::Json => fit("null", data_type),
+        _ => fit("", data_type),
+    }
+}
+
+/// A number derived from `value`, the same for the same value on every run.
+fn digest(value: &str) -> u64 {
+    let hash = Sha256::digest(value.as_bytes());
+    u64::from_be_bytes(hash[..8].try_into().unwrap())
+}
+
+/// `value` read as a number, else the digits in it, else a number derived
+/// from it.
+fn number(value: &str) -> f64 {
+    let value = value.trim();
+    if let Some(number) = value
+        .parse::<f64>()
+        .ok()
+        .filter(|number| number.is_finite())
+    {
+        return number;
+    }
+
+    let digits = value
+        .chars()
+        .filter(char::is_ascii_digit)
+        .take(18)
+        .collect::<String>();
+    match digits.parse::<u64>() {
+        Ok(number) => number as f64,
+        Err(_) => digest(value) as f64,
+    }
+}
+
+fn integer(value: &str, bits: u32, unsigned: bool) -> InsertValue {
+    let (min, max) = match unsigned {
+        true => (0, (1i128 << bits) - 1),
+        false => (-(1i128 << (bits - 1)), (1i128 << (bits - 1)) - 1),
+    };
+    let trimmed = value.trim();
+    let mut number = match trimmed.parse::<i128>() {
+        Ok(number) => number,
+        Err(_) => number(trimmed).round() as i128,
+    };
+    if unsigned {
+        number = number.abs();
+    }
+    if !(min..=max).contains(&number) {
+        number = match unsigned {
+            true => number.rem_euclid(max + 1),
+            false => number % (max + 1),
+        };
+    }
+
+    InsertValue::Number {
+        value: number.to_string(),
+    }
+}
+
+/// `DECIMAL(m, d)`: at most `m` digits, of which `d` after the point.
+fn decimal(value: &str, m: u32, d: u32, unsigned: bool) -> InsertValue {
+    let d = d.min(m);
+    let limit = 10f64.powi((m - d) as i32);
+    let mut number = number(value);
+    if unsigned {
+        number = number.abs();
+    }
+    if number.abs() >= limit {
+        number %= limit;
+    }
+
+    let scale = 10f64.powi(d as i32);
+    let max = limit - 1.0 / scale;
+    number = ((number * scale).round() / scale).clamp(-max, max);
+
+    InsertValue::Number {
+        value: format!("{number:.0$}", d as usize),
+    }
+}
+
+fn float(value: &str, unsigned: bool) -> InsertValue {
+    let number = number(value);
+
+    InsertValue::Number {
+        value: match unsigned {
+            true => number.abs(),
+            false => number,
+        }
+        .to_string(),
+    }
+}
+
+const MIN_DATETIME: i64 = -30_610_224_000;
+const MAX_DATETIME: i64 = 253_402_300_799;
+const MIN_TIMESTAMP: i64 = 1;
+const MAX_TIMESTAMP: i64 = 2_147_483_647;
+
+/// Days since 1970-01-01 of a proleptic Gregorian date.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// The date `days` after 1970-01-01.
+pub(crate) fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Seconds since the epoch of a `YYYY-MM-DD` date, if it is a valid one.
+pub(crate) fn parse_date(value: &str) -> Option<i64> {
+    let mut parts = value.splitn(3, '-');
+    let year = parts.next()?.parse::<i64>().ok()?;
+    let month = parts.next()?.parse::<i64>().ok()?;
+    let day = parts.next()?.parse::<i64>().ok()?;
+    let days = days_from_civil(year, month, day);
+
+    (value.len() == 10 && civil_from_days(days) == (year, month, day)).then_some(days * DAY)
+}
+
+/// Seconds of an `HH:MM:SS` time of day, if it is a valid one.
+fn parse_time_of_day(value: &str) -> Option<i64> {
+    let parts = value
+        .split(':')
+        .map(|part| part.parse::<i64>().ok().filter(|_| part.len() == 2))
+        .collect::<Option<Vec<i64>>>()?;
+
+    match parts.as_slice() {
+        [hours, minutes, seconds] if *hours < 24 && *minutes < 60 && *seconds < 60 => {
+            Some(hours * 3600 + minutes * 60 + seconds)
+        }
+        _ => None,
+    }
+}
+
+fn format_datetime(seconds: i64) -> String {
+    let (year, month, day) = civil_from_days(seconds.div_euclid(DAY));
+    let time = seconds.rem_euclid(DAY);
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02}",
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+/// Fractional seconds cut to `fsp` digits, with their point.
+fn fraction(fraction: Option<&str>, fsp: u32) -> String {
+    match fraction.filter(|fraction| fraction.chars().all(|c| c.is_ascii_digit())) {
+        Some(fraction) if fsp > 0 && !fraction.is_empty() => {
+            format!(".{}", &fraction[..fraction.len().min(fsp as usize)])
+        }
+        _ => String::new(),
+    }
+}
+
+fn date(value: &str) -> String {
+    let value = value.trim();
+    let date = value.get(..10).unwrap_or(value);
+    let seconds =
+        parse_date(date).filter(|seconds| (MIN_DATETIME..=MAX_DATETIME).contains(seconds));
+
+    match seconds {
+        Some(_) => date.to_string(),
+        None => format_datetime(derived(value, (MIN_TIMESTAMP, MAX_TIMESTAMP)))[..10].to_string(),
+    }
+}
+
+fn datetime(value: &str, fsp: u32, (min, max): (i64, i64)) -> String {
+    let value = value.trim();
+    let (date, time) = value.split_once([' ', 'T']).unwrap_or((value, "00:00:00"));
+    let (time, fractional) = match time.split_once('.') {
+        Some((time, fractional)) => (time, Some(fractional)),
+        None => (time, None),
+    };
+    let seconds = parse_date(date)
+        .zip(parse_time_of_day(time))
+        .map(|(date, time)| date + time)
+        .filter(|seconds| (min..=max).contains(seconds));
+
+    match seconds {
+        Some(seconds) => format!("{}{}", format_datetime(seconds), fraction(fractional, fsp)),
+        None => format_datetime(derived(value, (min.max(MIN_TIMESTAMP), max))),
+    }
+}
+
+fn time(value: &str, fsp: u32) -> String {
+    let value = value.trim();
+    let (time, fractional) = match time_part(value).split_once('.') {
+        Some((time, fractional)) => (time, Some(fractional)),
+        None => (time_part(value), None),
+    };
+    let (negative, unsigned) = match time.strip_prefix('-') {
+        Some(unsigned) => ("-", unsigned),
+        None => ("", time),
+    };
+    let parts = unsigned
+        .split(':')
+        .map(|part| part.parse::<u32>().ok())
+        .collect::<Option<Vec<u32>>>();
+
+    match parts.as_deref() {
+        Some([hours, minutes, seconds]) if *hours <= 838 && *minutes < 60 && *seconds < 60 => {
+            format!(
+                "{negative}{hours:02}:{minutes:02}:{seconds:02}{}",
+                fraction(fractional, fsp)
+            )
+        }
+        _ => format_datetime(derived(value, (0, DAY - 1)))[11..].to_string(),
+    }
+}
+
+/// The time of a value that may also hold a date.
+fn time_part(value: &str) -> &str {
+    value.split_once([' ', 'T']).map_or(value, |(_, time)| time)
+}
+
+fn year(value: &str) -> InsertValue {
+    let year = match value.trim().parse::<u32>() {
+        Ok(year) if year == 0 || (1901..=2155).contains(&year) => year,
+        _ => 1901 + (digest(value) % 255) as u32,
+    };
+
+    InsertValue::Number {
+        value: year.to_string(),
+    }
+}
+
+/// Seconds between `min` and `max` derived from `value`.
+fn derived(value: &str, (min, max): (i64, i64)) -> i64 {
+    min + (digest(value) % (max - min + 1) as u64) as i64
+}
+
+fn truncate_chars(value: &str, length: u32) -> String {
+    value.chars().take(length as usize).collect()
+}
+
+fn truncate_bytes(value: &str, length: u32) -> String {
+    let mut end = value.len().min(length as usize);
+    while !value.is_char_boundary(end) {
+        end -= 1;
+    }
+    value[..end].to_string()
+}
+
+/// `value` when it is one of the `ENUM` members, compared as MySQL does,
+/// or else a member derived from it.
+fn member(value: &str, members: &[String]) -> String {
+    let members = members
+        .iter()
+        .map(|member| unescape_str(member))
+        .collect::<Vec<String>>();
+
+    members
+        .iter()
+        .find(|member| member.trim_end().eq_ignore_ascii_case(value.trim_end()))
+        .or_else(|| members.get((digest(value) % members.len().max(1) as u64) as usize))
+        .cloned()
+        .unwrap_or_else(|| value.to_string())
+}
+
+/// `value` when all of its comma-separated members are `SET` members, or
+/// else a single member derived from it.
+fn members(value: &str, members: &[String]) -> String {
+    if value.is_empty() || members.is_empty() {
+        return value.to_string();
+    }
+
+    let unescaped = members
+        .iter()
+        .map(|member| unescape_str(member))
+        .collect::<Vec<String>>();
+    let found = value
+        .split(',')
+        .map(|part| {
+            unescaped
+                .iter()
+                .find(|member| member.eq_ignore_ascii_case(part))
+                .cloned()
+        })
+        .collect::<Option<Vec<String>>>();
+
+    match found {
+        Some(found) => found.join(","),
+        None => member(value, members),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn fitted(value: &str, data_type: &str) -> String {
+        let column = format!("CREATE TABLE t (c {data_type})");
+        let table = crate::parser::parse_rule::<crate::parser::statements::CreateTable>(
+            crate::parser::Rule::CREATE_TABLE,
+            &column,
+        )
+        .unwrap();
+        fit(value, &table.columns[0].data_type).to_string()
+    }
+
+    #[test]
+    fn can_fit_numbers() {
+        assert_eq!(fitted("42", "INT"), "42");
+        assert_eq!(fitted("300", "TINYINT"), "44");
+        assert_eq!(fitted("-5", "INT UNSIGNED"), "5");
+        assert_eq!(fitted("555-0199", "BIGINT"), "5550199");
+        assert_eq!(fitted("john", "INT"), fitted("john", "INT"));
+        assert!(fitted("john", "SMALLINT UNSIGNED").parse::<u16>().is_ok());
+        assert_eq!(fitted("1234.567", "DECIMAL(5,2)"), "234.57");
+        assert_eq!(fitted("999.999", "DECIMAL(5,2)"), "999.99");
+        assert_eq!(fitted("-3.5", "DECIMAL(4,1) UNSIGNED"), "3.5");
+        assert_eq!(fitted("2", "YEAR"), fitted("2", "YEAR"));
+        assert_eq!(fitted("2024", "YEAR"), "2024");
+    }
+
+    #[test]
+    fn can_fit_strings() {
+        assert_eq!(fitted("john@example.com", "VARCHAR(4)"), "'john'");
+        assert_eq!(fitted("ab", "CHAR"), "'a'");
+        assert_eq!(fitted("héé", "VARBINARY(2)"), "'h'");
+        assert_eq!(fitted("it's", "VARCHAR(10)"), "'it\\'s'");
+        assert_eq!(fitted("Blue", "ENUM('red','blue')"), "'blue'");
+        assert!(["'red'", "'blue'"].contains(&fitted("green", "ENUM('red','blue')").as_str()));
+        assert_eq!(fitted("a,c", "SET('a','b','c')"), "'a,c'");
+        assert_eq!(fitted("x,a", "SET('a')"), "'a'");
+        assert_eq!(fitted("{\"a\": 1}", "JSON"), "'{\\\"a\\\": 1}'");
+        assert_eq!(fitted("plain", "JSON"), "'\\\"plain\\\"'");
+    }
+
+    #[test]
+    fn can_fit_dates() {
+        assert_eq!(fitted("2024-02-29", "DATE"), "'2024-02-29'");
+        assert_eq!(fitted("2024-02-29 10:11:12", "DATE"), "'2024-02-29'");
+        assert_ne!(fitted("2023-02-29", "DATE"), "'2023-02-29'");
+        assert_eq!(
+            fitted("2024-01-02 03:04:05.678", "DATETIME(2)"),
+            "'2024-01-02 03:04:05.67'"
+        );
+        assert_eq!(fitted("2024-01-02", "DATETIME"), "'2024-01-02 00:00:00'");
+        assert_eq!(
+            fitted("1960-01-01", "TIMESTAMP"),
+            fitted("1960-01-01", "TIMESTAMP")
+        );
+        assert!(fitted("1960-01-01", "TIMESTAMP").as_str() > "'1970-01-01 00:00:00'");
+        assert!(fitted("1960-01-01", "TIMESTAMP").as_str() < "'2038-01-19 03:14:08'");
+        assert_eq!(fitted("-12:30:00", "TIME"), "'-12:30:00'");
+        assert_eq!(fitted("2024-01-02 03:04:05", "TIME"), "'03:04:05'");
+        assert_eq!(fitted("nope", "TIME").len(), 10);
+    }
+
+    #[test]
+    fn can_convert_dates() {
+        for days in [-719_468, -1, 0, 11_016, 19_782, 2_932_896] {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+        assert_eq!(civil_from_days(19_782), (2024, 2, 29));
+        assert_eq!(format_datetime(MAX_TIMESTAMP), "2038-01-19 03:14:07");
+        assert_eq!(format_datetime(MIN_DATETIME), "1000-01-01 00:00:00");
+        assert_eq!(format_datetime(MAX_DATETIME), "9999-12-31 23:59:59");
+    }
+}
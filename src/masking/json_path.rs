@@ -0,0 +1,397 @@
+use std::{
+    fmt::{Display, Formatter, Result as FmtResult},
+    ops::Range,
+};
+
+use anyhow::{bail, Context};
+use serde_json::Number;
+
+use crate::ExtractResult;
+
+/// One step of a [`JsonPath`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+    /// Every member of an object or element of an array.
+    Any,
+}
+
+/// The values to mask inside JSON documents, written as keys separated by
+/// dots with optional array indexes, such as `profile.address.street`,
+/// `$.phones[*].number` or `$['first name']`. A `*` matches every member
+/// of an object and `[*]` every element of an array.
+#[derive(Debug, Clone)]
+pub struct JsonPath {
+    path: String,
+    segments: Vec<Segment>,
+}
+
+impl JsonPath {
+    pub fn parse(path: &str) -> ExtractResult<Self> {
+        let segments =
+            segments(path.trim()).with_context(|| format!("invalid JSON path `{path}`"))?;
+        Ok(Self {
+            path: path.trim().to_string(),
+            segments,
+        })
+    }
+
+    /// The last key of the path, which names the rule used when none is
+    /// configured.
+    pub fn last_key(&self) -> Option<&str> {
+        self.segments
+            .iter()
+            .rev()
+            .find_map(|segment| match segment {
+                Segment::Key(key) => Some(key.as_str()),
+                _ => None,
+            })
+    }
+
+    /// Replace the strings and numbers at this path in `document`, which
+    /// must be valid JSON, with what `mask` gives for them, `None` making
+    /// them `null`. Objects, arrays, booleans and nulls at the path are
+    /// kept, and so is the rest of the text, as it is written.
+    ///
+    /// Returns the number of values replaced.
+    pub fn mask(
+        &self,
+        document: &mut String,
+        mask: &mut dyn FnMut(&str) -> ExtractResult<Option<String>>,
+    ) -> ExtractResult<usize> {
+        let mut spans = Vec::new();
+        Scanner::new(document).leaves(&self.segments, &mut spans);
+
+        // From the end, so the spans before stay where they are
+        for span in spans.iter().rev() {
+            let leaf = &document[span.clone()];
+            let replacement = match leaf.starts_with('"') {
+                true => mask(&serde_json::from_str::<String>(leaf)?)?,
+                false => mask(leaf)?,
+            };
+            let replacement = match replacement {
+                // Numbers stay numbers when the replacement is one
+                Some(replacement) if !leaf.starts_with('"') => {
+                    match serde_json::from_str::<Number>(&replacement) {
+                        Ok(number) => number.to_string(),
+                        Err(_) => serde_json::to_string(&replacement)?,
+                    }
+                }
+                Some(replacement) => serde_json::to_string(&replacement)?,
+                None => "null".to_string(),
+            };
+            document.replace_range(span.clone(), &replacement);
+        }
+
+        Ok(spans.len())
+    }
+}
+
+impl Display for JsonPath {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}", self.path)
+    }
+}
+
+fn segments(path: &str) -> ExtractResult<Vec<Segment>> {
+    let mut rest = path.strip_prefix('$').unwrap_or(path);
+    let mut segments = Vec::new();
+
+    while !rest.is_empty() {
+        if let Some(bracketed) = rest.strip_prefix('[') {
+            let (inner, after) = match bracketed.chars().next() {
+                Some(quote @ ('\'' | '"')) => {
+                    let end = bracketed[1..]
+                        .find(quote)
+                        .context("unterminated quoted key")?;
+                    let after = bracketed[end + 2..]
+                        .strip_prefix(']')
+                        .context("missing `]` after quoted key")?;
+                    segments.push(Segment::Key(bracketed[1..end + 1].to_string()));
+                    rest = after;
+                    continue;
+                }
+                _ => bracketed.split_once(']').context("missing `]`")?,
+            };
+            segments.push(match inner.trim() {
+                "*" => Segment::Any,
+                index => Segment::Index(
+                    index
+                        .parse()
+                        .with_context(|| format!("invalid array index `{index}`"))?,
+                ),
+            });
+            rest = after;
+            continue;
+        }
+
+        let key = match rest.strip_prefix('.') {
+            Some(key) => key,
+            None if segments.is_empty() => rest,
+            None => bail!("expected `.` or `[` before `{rest}`"),
+        };
+        let end = key.find(['.', '[']).unwrap_or(key.len());
+        segments.push(match &key[..end] {
+            "" => bail!("empty key"),
+            "*" => Segment::Any,
+            name => Segment::Key(name.to_string()),
+        });
+        rest = &key[end..];
+    }
+
+    if segments.is_empty() {
+        bail!("the path selects the whole document");
+    }
+    Ok(segments)
+}
+
+/// Walks the text of a valid JSON document.
+struct Scanner<'a> {
+    text: &'a str,
+    position: usize,
+}
+
+impl<'a> Scanner<'a> {
+    fn new(text: &'a str) -> Self {
+        Self { text, position: 0 }
+    }
+
+    /// Collect the spans of the strings and numbers at `segments` from the
+    /// value at the current position, moving past it.
+    fn leaves(&mut self, segments: &[Segment], spans: &mut Vec<Range<usize>>) {
+        self.skip_whitespace();
+        let Some((segment, rest)) = segments.split_first() else {
+            let span = self.value();
+            if !matches!(
+                self.text.as_bytes()[span.start],
+                b'{' | b'[' | b't' | b'f' | b'n'
+            ) {
+                spans.push(span);
+            }
+            return;
+        };
+
+        match (self.peek(), segment) {
+            (Some(b'{'), Segment::Key(_) | Segment::Any) => {
+                self.members(|scanner, key| match segment {
+                    Segment::Key(name) if name != key => {
+                        scanner.value();
+                    }
+                    _ => scanner.leaves(rest, spans),
+                });
+            }
+            (Some(b'['), Segment::Index(_) | Segment::Any) => {
+                self.elements(|scanner, index| match segment {
+                    Segment::Index(wanted) if *wanted != index => {
+                        scanner.value();
+                    }
+                    _ => scanner.leaves(rest, spans),
+                });
+            }
+            _ => {
+                self.value();
+            }
+        }
+    }
+
+    /// Move past the value at the current position, returning its span.
+    fn value(&mut self) -> Range<usize> {
+        self.skip_whitespace();
+        let start = self.position;
+        match self.peek() {
+            Some(b'{') => self.members(|scanner, _| {
+                scanner.value();
+            }),
+            Some(b'[') => self.elements(|scanner, _| {
+                scanner.value();
+            }),
+            Some(b'"') => {
+                self.string();
+            }
+            _ => {
+                while self
+                    .peek()
+                    .is_some_and(|byte| !b",]} \t\r\n".contains(&byte))
+                {
+                    self.position += 1;
+                }
+            }
+        }
+        start..self.position
+    }
+
+    /// Move past an object, calling `member` with each key when at its
+    /// value, which it must move past.
+    fn members(&mut self, mut member: impl FnMut(&mut Self, &str)) {
+        self.position += 1;
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b'}') | None => break,
+                Some(b',') => self.position += 1,
+                _ => {
+                    let key = self.string();
+                    let key = serde_json::from_str::<String>(&self.text[key]).unwrap_or_default();
+                    self.skip_whitespace();
+                    // The `:`
+                    self.position += 1;
+                    member(self, &key);
+                }
+            }
+        }
+        self.position += 1;
+    }
+
+    /// Move past an array, calling `element` with the index of each element
+    /// when at it, which it must move past.
+    fn elements(&mut self, mut element: impl FnMut(&mut Self, usize)) {
+        self.position += 1;
+        let mut index = 0;
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b']') | None => break,
+                Some(b',') => self.position += 1,
+                _ => {
+                    element(self, index);
+                    index += 1;
+                }
+            }
+        }
+        self.position += 1;
+    }
+
+    /// Move past the string at the current position, returning its span
+    /// with the quotes.
+    fn string(&mut self) -> Range<usize> {
+        let start = self.position;
+        self.position += 1;
+        while let Some(byte) = self.peek() {
+            self.position += match byte {
+                b'\\' => 2,
+                b'"' => {
+                    self.position += 1;
+                    break;
+                }
+                _ => 1,
+            };
+        }
+        start..self.position
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(|byte| byte.is_ascii_whitespace()) {
+            self.position += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.position).copied()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    fn mask(path: &str, document: Value) -> (usize, Value) {
+        let mut document = document.to_string();
+        let count = JsonPath::parse(path)
+            .unwrap()
+            .mask(&mut document, &mut |value| Ok(Some(format!("<{value}>"))))
+            .unwrap();
+        (count, serde_json::from_str(&document).unwrap())
+    }
+
+    #[test]
+    fn can_parse_json_paths() {
+        let path = JsonPath::parse("$.phones[*].number").unwrap();
+        assert_eq!(
+            path.segments,
+            [
+                Segment::Key("phones".to_string()),
+                Segment::Any,
+                Segment::Key("number".to_string())
+            ]
+        );
+        assert_eq!(path.last_key(), Some("number"));
+        assert_eq!(
+            JsonPath::parse("profile.address['zip code'][0]")
+                .unwrap()
+                .segments,
+            [
+                Segment::Key("profile".to_string()),
+                Segment::Key("address".to_string()),
+                Segment::Key("zip code".to_string()),
+                Segment::Index(0)
+            ]
+        );
+        assert_eq!(
+            JsonPath::parse("$.tags[1]").unwrap().last_key(),
+            Some("tags")
+        );
+        for invalid in ["", "$", "a..b", "a[", "a[x]", "a['b]", "a b[0]c"] {
+            assert!(JsonPath::parse(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn can_mask_json_paths() {
+        let document = json!({
+            "profile": {"address": {"street": "1 Main St", "zip": 12345}},
+            "phones": [{"number": "555-0100"}, {"number": "555-0199", "type": "home"}],
+            "tags": ["a", "b"],
+            "active": true
+        });
+
+        let (count, masked) = mask("profile.address.street", document.clone());
+        assert_eq!(count, 1);
+        assert_eq!(masked["profile"]["address"]["street"], "<1 Main St>");
+        assert_eq!(masked["profile"]["address"]["zip"], 12345);
+
+        let (count, masked) = mask("$.phones[*].number", document.clone());
+        assert_eq!(count, 2);
+        assert_eq!(masked["phones"][1]["number"], "<555-0199>");
+        assert_eq!(masked["phones"][1]["type"], "home");
+
+        let (count, masked) = mask("profile.address.*", document.clone());
+        assert_eq!(count, 2);
+        assert_eq!(masked["profile"]["address"]["zip"], "<12345>");
+
+        assert_eq!(
+            mask("tags[1]", document.clone()).1["tags"],
+            json!(["a", "<b>"])
+        );
+        assert_eq!(mask("active", document.clone()).0, 0);
+        assert_eq!(mask("profile.missing.street", document.clone()).0, 0);
+        assert_eq!(mask("profile", document).0, 0);
+    }
+
+    #[test]
+    fn keeps_the_text_of_unmasked_values() {
+        let mut document = r#"{ "id": 123456789012345678901234567890, "price": 10.50,
+  "weight": 1e3, "name" : "Ann \u00e9", "tags": [ "a",  "b" ], "n": 7 }"#
+            .to_string();
+        let path = JsonPath::parse("$.tags[1]").unwrap();
+        let mut masked = |value: &str| Ok(Some(format!("<{value}>")));
+
+        assert_eq!(path.mask(&mut document, &mut masked).unwrap(), 1);
+        let count = JsonPath::parse("name")
+            .unwrap()
+            .mask(&mut document, &mut |_| Ok(None))
+            .unwrap();
+        assert_eq!(count, 1);
+        JsonPath::parse("n")
+            .unwrap()
+            .mask(&mut document, &mut |_| Ok(Some("8".to_string())))
+            .unwrap();
+        assert_eq!(
+            document,
+            r#"{ "id": 123456789012345678901234567890, "price": 10.50,
+  "weight": 1e3, "name" : null, "tags": [ "a",  "<b>" ], "n": 8 }"#
+        );
+    }
+}
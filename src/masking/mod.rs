@@ -0,0 +1,2595 @@
+mod detection;
+pub(crate) mod fitting;
+mod json_path;
+mod plan;
+mod predicate;
+mod relations;
+mod selector;
+mod vault;
+mod verify;
+
+use std::{
+    collections::{hash_map::RandomState, HashMap, HashSet},
+    hash::{BuildHasher, Hasher},
+    io::{Read, Write},
+    rc::Rc,
+};
+
+use anyhow::{bail, Context};
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::{
+    dump::{DumpStatement, StatementReader},
+    parser::{
+        parse_rule,
+        parse_utils::{strip_quotes, unescape_str},
+        statements::{CreateTable, Insert, UseDatabase},
+        types::{AssignmentValue, Column, InsertValue, Update},
+        Rule,
+    },
+    rules::{self, CellContext, Strategy},
+    settings::{DetectionScope, MaskingConfig, MaskingRule},
+    ExtractResult,
+};
+
+pub(crate) use detection::iban_remainder;
+pub use detection::{luhn, Builtin, ColumnStats, Detector, ValueDetection, DEFAULT_THRESHOLD};
+pub use json_path::JsonPath;
+pub use plan::{
+    ColumnMasker, ColumnPlan, JsonPathMasker, MaskingPlan, PlannedRule, Selection, TablePlan,
+};
+pub use predicate::Predicate;
+pub use relations::{ColumnRef, Relations};
+pub use selector::ColumnSelector;
+pub use vault::{detokenize_dump, TokenVault};
+pub use verify::{Leak, LeakCheck, DEFAULT_MIN_LENGTH};
+
+/// Rewrites the `INSERT`s and `UPDATE`s of a SQL dump, replacing the
+/// values of the columns a [`MaskingConfig`] selects or its value patterns
+/// flag, and of the columns linked to them, consistently and fitted to
+/// their column types. How each table is masked is compiled into a
+/// [`TablePlan`].
+pub struct Masker<'a> {
+    config: &'a MaskingConfig,
+    selectors: Vec<ColumnSelector>,
+    exclusions: Vec<ColumnSelector>,
+    json_paths: Vec<(ColumnSelector, JsonPath, Option<&'a MaskingRule>)>,
+    database: Option<String>,
+    tables: HashMap<(Option<String>, String), CreateTable>,
+    relationships: Vec<(ColumnRef, ColumnRef)>,
+    relations: Relations,
+    plans: HashMap<(Option<String>, String), Rc<TablePlan<'a>>>,
+    /// The rules of the cell-scoped value patterns, by name.
+    cell_rules: HashMap<String, PlannedRule<'a>>,
+    /// Replacements handed out for memoized columns whose rule is not
+    /// deterministic, keyed by the root of their group.
+    replacements: HashMap<(ColumnRef, String), Option<String>>,
+    /// The shuffled values of the columns masked with `shuffle`, collected
+    /// by [`Masker::prepare`].
+    shuffled: HashMap<ColumnRef, std::vec::IntoIter<String>>,
+    /// Replacements handed out for groups with a column in a unique index,
+    /// keyed by the root of the group.
+    unique_values: HashMap<ColumnRef, UniqueValues>,
+    /// Columns assigned or compared by `UPDATE`s in the prepared dump.
+    updated: HashSet<ColumnRef>,
+    detection: ValueDetection,
+    /// Where the rules using `tokenize` record the values they replace.
+    vault: Option<TokenVault>,
+    /// Whether the whole dump was scanned by [`Masker::prepare`] already.
+    prepared: bool,
+}
+
+impl<'a> Masker<'a> {
+    pub fn new(config: &'a MaskingConfig) -> ExtractResult<Self> {
+        let relationships = config
+            .relationships
+            .iter()
+            .map(|relationship| {
+                Ok((
+                    ColumnRef::parse(&relationship.column)?,
+                    ColumnRef::parse(&relationship.references)?,
+                ))
+            })
+            .collect::<ExtractResult<Vec<(ColumnRef, ColumnRef)>>>()
+            .context("invalid relationship in masking config")?;
+        let detection = ValueDetection::new(&config.value_patterns)
+            .context("invalid value pattern in masking config")?;
+        let selectors = config
+            .columns
+            .iter()
+            .map(|selector| ColumnSelector::parse(selector))
+            .collect::<ExtractResult<Vec<ColumnSelector>>>()
+            .context("invalid column in masking config")?;
+        let exclusions = config
+            .exclude
+            .iter()
+            .map(|selector| ColumnSelector::parse(selector))
+            .collect::<ExtractResult<Vec<ColumnSelector>>>()
+            .context("invalid exclusion in masking config")?;
+        let json_paths = config
+            .json_paths
+            .iter()
+            .map(|json_path| {
+                let path = JsonPath::parse(&json_path.path)?;
+                if let Some(ref rule) = json_path.rule {
+                    check_json_path_rule(&path, rule)?;
+                }
+                Ok((
+                    ColumnSelector::parse(&json_path.column)?,
+                    path,
+                    json_path.rule.as_ref(),
+                ))
+            })
+            .collect::<ExtractResult<Vec<_>>>()
+            .context("invalid JSON path in masking config")?;
+
+        let mut masker = Self {
+            config,
+            selectors,
+            exclusions,
+            json_paths,
+            database: None,
+            tables: HashMap::new(),
+            relationships,
+            relations: Relations::default(),
+            plans: HashMap::new(),
+            cell_rules: HashMap::new(),
+            replacements: HashMap::new(),
+            shuffled: HashMap::new(),
+            unique_values: HashMap::new(),
+            updated: HashSet::new(),
+            detection,
+            vault: None,
+            prepared: false,
+        };
+        masker.link_relationships();
+        masker.cell_rules = masker
+            .detection
+            .detectors
+            .iter()
+            .filter(|detector| detector.scope == DetectionScope::Cell)
+            .map(|detector| Ok((detector.name.clone(), masker.rule_for(&detector.name)?)))
+            .collect::<ExtractResult<_>>()?;
+
+        Ok(masker)
+    }
+
+    /// Scan a dump without masking it, so that relationships and the
+    /// columns flagged by value patterns are known before the first
+    /// `INSERT`, wherever the tables are created in the dump.
+    pub fn prepare<R: Read>(&mut self, input: R) -> ExtractResult<()> {
+        let shuffles = self
+            .config
+            .rules
+            .values()
+            .any(|rule| is_shuffle(Some(rule)));
+        let mut shuffled = HashMap::<ColumnRef, Vec<String>>::new();
+
+        for (index, statement) in StatementReader::new(input).enumerate() {
+            let statement = statement?;
+            let context = || format!("unable to mask statement {}", index + 1);
+            match statement.keyword().as_str() {
+                "USE" | "CREATE" => {
+                    self.mask_statement(&statement).with_context(context)?;
+                }
+                "UPDATE" => {
+                    if let Ok(update) =
+                        parse_rule::<Update>(Rule::UPDATE_STATEMENT, &statement.body)
+                    {
+                        self.record_updated(&update);
+                    }
+                }
+                "INSERT" | "REPLACE" if shuffles || !self.detection.is_empty() => {
+                    // Those that can't be masked fail when masked, if they have to be
+                    let Ok(insert) = parse_rule::<Insert>(Rule::INSERT_STATEMENT, &statement.body)
+                    else {
+                        continue;
+                    };
+                    if self.column_names(&insert).is_err() {
+                        continue;
+                    }
+                    self.record_values(&insert).with_context(context)?;
+                    if shuffles {
+                        self.record_shuffled(&insert, &mut shuffled)
+                            .with_context(context)?;
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.database = None;
+        self.prepared = true;
+        self.plans.clear();
+
+        for (column, mut values) in shuffled {
+            rules::shuffle(&mut values, self.shuffle_seed(&column));
+            self.shuffled.insert(column, values.into_iter());
+        }
+
+        Ok(())
+    }
+
+    /// The detection statistics gathered so far.
+    pub fn detection(&self) -> &ValueDetection {
+        &self.detection
+    }
+
+    /// Only run value patterns on the first `sample_size` values of each
+    /// column.
+    pub fn sample_values(&mut self, sample_size: usize) {
+        self.detection.sample_size = Some(sample_size);
+    }
+
+    /// Record the values replaced by `tokenize` rules in `vault`.
+    pub fn set_vault(&mut self, vault: TokenVault) {
+        self.vault = Some(vault);
+    }
+
+    /// The token vault, with the values tokenized so far.
+    pub fn vault(&self) -> Option<&TokenVault> {
+        self.vault.as_ref()
+    }
+
+    /// Whether every value of `column` is replaced, so none of its
+    /// originals should be left after masking.
+    ///
+    /// Columns masked only under a condition, with `scrub`, which keeps the
+    /// text around the PII it finds, or with `shuffle` or `id_remap`, whose
+    /// replacements are other original values, are not.
+    pub fn replaces(&mut self, column: &ColumnRef) -> ExtractResult<bool> {
+        let plan = self.table_plan(
+            column.database.clone(),
+            &column.table,
+            std::slice::from_ref(&column.column),
+        )?;
+        let masker = plan
+            .index(&column.column)
+            .and_then(|index| plan.columns[index].masker.as_ref());
+
+        Ok(masker.is_some_and(|masker| {
+            masker.rule.when.is_none()
+                && !matches!(
+                    masker.rule.strategy(),
+                    Some(Strategy::Scrub { .. } | Strategy::Shuffle | Strategy::IdRemap)
+                )
+        }))
+    }
+
+    /// The plan of every table created so far, with the value patterns
+    /// that mask single cells.
+    pub fn plan(&mut self) -> ExtractResult<MaskingPlan<'a>> {
+        let mut tables = self.tables.keys().cloned().collect::<Vec<_>>();
+        tables.sort();
+
+        Ok(MaskingPlan {
+            tables: tables
+                .into_iter()
+                .map(|(database, table)| self.table_plan(database, &table, &[]))
+                .collect::<ExtractResult<_>>()?,
+            cells: self
+                .detection
+                .detectors
+                .iter()
+                .filter_map(|detector| {
+                    let rule = self.cell_rules.get(&detector.name)?;
+                    Some((detector.name.clone(), rule.name().to_string()))
+                })
+                .collect(),
+        })
+    }
+
+    /// The tables created so far, with the database they were created in.
+    pub fn tables(&self) -> impl Iterator<Item = (Option<&str>, &CreateTable)> {
+        self.tables
+            .iter()
+            .map(|((database, _), table)| (database.as_deref(), table))
+    }
+
+    /// Mask every statement read from `input`, writing the resulting dump
+    /// to `output` as it goes.
+    ///
+    /// Returns the number of statements that were rewritten.
+    pub fn mask_dump<R: Read, W: Write>(
+        &mut self,
+        input: R,
+        output: &mut W,
+    ) -> ExtractResult<usize> {
+        let mut rewritten = 0;
+
+        for (index, statement) in StatementReader::new(input).enumerate() {
+            let statement = statement?;
+            let masked = self
+                .mask_statement(&statement)
+                .with_context(|| format!("unable to mask statement {}", index + 1))?;
+            match masked {
+                Some(masked) => {
+                    rewritten += 1;
+                    write!(output, "{masked}")?;
+                }
+                None => write!(output, "{statement}")?,
+            }
+        }
+
+        Ok(rewritten)
+    }
+
+    /// Mask a single statement, returning `None` when it is left unchanged.
+    pub fn mask_statement(
+        &mut self,
+        statement: &DumpStatement,
+    ) -> ExtractResult<Option<DumpStatement>> {
+        match statement.keyword().as_str() {
+            "USE" => {
+                if let Ok(use_database) =
+                    parse_rule::<UseDatabase>(Rule::USE_DATABASE, &statement.body)
+                {
+                    self.database = Some(use_database.name);
+                    self.link_relationships();
+                    self.plans.clear();
+                }
+                Ok(None)
+            }
+            "CREATE" => {
+                if let Ok(create_table) =
+                    parse_rule::<CreateTable>(Rule::CREATE_TABLE, &statement.body)
+                {
+                    self.link_foreign_keys(&create_table);
+                    let name = create_table.name.clone();
+                    self.tables
+                        .insert((self.database.clone(), name.clone()), create_table);
+                    self.plans.clear();
+                    return Ok(self
+                        .remapped_auto_increment(&name, &statement.body)?
+                        .map(|body| statement.with_body(body)));
+                }
+                Ok(None)
+            }
+            "INSERT" | "REPLACE" => Ok(self
+                .mask_insert(&statement.body)?
+                .map(|body| statement.with_body(body))),
+            "UPDATE" => Ok(self
+                .mask_update(&statement.body)?
+                .map(|body| statement.with_body(body))),
+            _ => Ok(None),
+        }
+    }
+
+    /// The `CREATE TABLE` of `table` with its `AUTO_INCREMENT` option moved
+    /// past the keys `id_remap` gives its auto-increment column, which keep
+    /// their number of digits, or `None` when it is not remapped.
+    fn remapped_auto_increment(
+        &mut self,
+        table: &str,
+        body: &str,
+    ) -> ExtractResult<Option<String>> {
+        let plan = self.table_plan(self.database.clone(), table, &[])?;
+        Ok(self.remap_auto_increment(&plan, body))
+    }
+
+    fn remap_auto_increment(&self, plan: &TablePlan<'a>, body: &str) -> Option<String> {
+        let column = plan.columns.iter().find(|column| {
+            column
+                .definition
+                .as_ref()
+                .is_some_and(|definition| definition.auto_increment)
+        })?;
+        let masker = column.masker.as_ref()?;
+        if masker.rule.strategy() != Some(&Strategy::IdRemap) {
+            return None;
+        }
+
+        // Table options come after the closing parenthesis of the columns
+        let options_start = body.rfind(')')?;
+        let captures = AUTO_INCREMENT.captures(&body[options_start..])?;
+        let value = captures.get(1)?;
+        let next = value
+            .as_str()
+            .parse::<u64>()
+            .ok()
+            .filter(|next| *next > 1)?;
+        let max = column.definition.as_ref().map_or(u64::MAX, |definition| {
+            rules::integer_max(&definition.data_type)
+        });
+        let remapped = 10u64
+            .checked_pow((next - 1).ilog10() + 1)
+            .unwrap_or(u64::MAX)
+            .min(max);
+
+        let range = options_start + value.start()..options_start + value.end();
+        let mut body = body.to_string();
+        body.replace_range(range, &remapped.to_string());
+        Some(body)
+    }
+
+    /// Relationships from the config apply to the current database unless
+    /// they name their own.
+    fn link_relationships(&mut self) {
+        let database = self.database.as_deref();
+        for (column, references) in self.relationships.iter() {
+            self.relations.link(
+                column.in_database(database),
+                references.in_database(database),
+            );
+        }
+    }
+
+    fn link_foreign_keys(&mut self, create_table: &CreateTable) {
+        let database = self.database.as_deref();
+        for foreign_key in create_table.foreign_keys.iter() {
+            for (local, foreign) in foreign_key
+                .local_column_names
+                .iter()
+                .zip(foreign_key.foreign_column_names.iter())
+            {
+                self.relations.link(
+                    ColumnRef::new(database, &create_table.name, local),
+                    ColumnRef::new(database, &foreign_key.foreign_table_name, foreign),
+                );
+            }
+        }
+    }
+
+    /// The plan of `table` in `database`, with a column for each of `names`
+    /// on top of the ones from its `CREATE TABLE`.
+    fn table_plan(
+        &mut self,
+        database: Option<String>,
+        table: &str,
+        names: &[String],
+    ) -> ExtractResult<Rc<TablePlan<'a>>> {
+        let key = (database, table.to_string());
+        let cached = self.plans.get(&key);
+        if let Some(plan) =
+            cached.filter(|plan| names.iter().all(|name| plan.index(name).is_some()))
+        {
+            return Ok(plan.clone());
+        }
+
+        let mut columns = self
+            .tables
+            .get(&key)
+            .iter()
+            .flat_map(|table| table.columns.iter())
+            .map(|column| column.name.to_lowercase())
+            .collect::<Vec<String>>();
+        let known = cached
+            .iter()
+            .flat_map(|plan| plan.columns.iter())
+            .map(|column| column.column.column.clone());
+        for name in known.chain(names.iter().map(|name| name.to_lowercase())) {
+            if !columns.contains(&name) {
+                columns.push(name);
+            }
+        }
+
+        let plan = Rc::new(TablePlan {
+            columns: columns
+                .iter()
+                .map(|name| self.column_plan(ColumnRef::new(key.0.as_deref(), table, name)))
+                .collect::<ExtractResult<_>>()?,
+            database: key.0.clone(),
+            table: key.1.clone(),
+        });
+        self.plans.insert(key, plan.clone());
+
+        Ok(plan)
+    }
+
+    fn column_plan(&self, column: ColumnRef) -> ExtractResult<ColumnPlan<'a>> {
+        Ok(ColumnPlan {
+            definition: self.column_definition(&column).cloned(),
+            excluded: self.is_excluded(&column),
+            masker: self.column_masker(&column)?,
+            json_paths: self.json_path_maskers(&column)?,
+            column,
+        })
+    }
+
+    /// The paths masked inside the JSON documents of `column`, unless it is
+    /// excluded.
+    fn json_path_maskers(&self, column: &ColumnRef) -> ExtractResult<Vec<JsonPathMasker<'a>>> {
+        if self.is_excluded(column) {
+            return Ok(Vec::new());
+        }
+
+        self.json_paths
+            .iter()
+            .filter(|(selector, _, _)| selector.matches(column))
+            .map(|(_, path, rule)| {
+                let rule = match rule {
+                    Some(rule) => PlannedRule::Configured(rule),
+                    None => self
+                        .rule_for(path.last_key().unwrap_or(&column.column))
+                        .and_then(|rule| check_json_path_rule(path, &rule).map(|_| rule))
+                        .with_context(|| format!("cannot mask {path} in {column}"))?,
+                };
+                Ok(JsonPathMasker {
+                    rule,
+                    path: path.clone(),
+                })
+            })
+            .collect()
+    }
+
+    /// How a column is masked, or `None` when neither it nor any column
+    /// linked to it is selected by the config or flagged by its values.
+    fn column_masker(&self, column: &ColumnRef) -> ExtractResult<Option<ColumnMasker<'a>>> {
+        if self.is_excluded(column) {
+            return Ok(None);
+        }
+        let group = self.relations.group(column);
+
+        let Some((selected, selection)) = group
+            .iter()
+            .find_map(|member| Some((member, self.selection(member)?)))
+            .or_else(|| {
+                group
+                    .iter()
+                    .filter(|member| !self.is_excluded(member))
+                    .find_map(|member| {
+                        let detector = self.detection.flagged(member)?;
+                        Some((member, Selection::Detected(detector.name.clone())))
+                    })
+            })
+        else {
+            return Ok(None);
+        };
+        let rule = match selection {
+            Selection::Detected(ref name) => self.rule_for(name),
+            _ => self.rule_for(&selected.column),
+        }
+        .with_context(|| format!("cannot mask {column}"))?;
+        if rule.strategy() == Some(&Strategy::Tokenize) {
+            check_holds_tokens(column, self.column_definition(column))?;
+        }
+
+        let mut masker = ColumnMasker::new(
+            selected.clone(),
+            selection,
+            rule,
+            group[0].clone(),
+            self.config.masking_key.as_deref(),
+        );
+        masker.unique = group.iter().any(|member| self.is_unique(member));
+        masker.memoized = group.len() > 1
+            || !self.prepared
+            || group.iter().any(|member| self.updated.contains(member));
+
+        Ok(Some(masker))
+    }
+
+    /// The rule for the column or value pattern called `name`, falling back
+    /// to the faker named after it, and failing when there is none.
+    fn rule_for(&self, name: &str) -> ExtractResult<PlannedRule<'a>> {
+        let config = self.config;
+        Ok(match config.rule_for(name) {
+            Some(rule) => PlannedRule::Configured(rule),
+            None => PlannedRule::Fallback(MaskingRule::for_column(name)?),
+        })
+    }
+
+    /// The selector or pattern of the config selecting `column`, unless it
+    /// is excluded.
+    fn selection(&self, column: &ColumnRef) -> Option<Selection> {
+        if self.is_excluded(column) {
+            return None;
+        }
+
+        self.selectors
+            .iter()
+            .find(|selector| selector.matches(column))
+            .map(|selector| Selection::Selector(selector.to_string()))
+            .or_else(|| {
+                self.config
+                    .patterns
+                    .iter()
+                    .find(|pattern| pattern.regex.is_match(&column.column))
+                    .map(|pattern| Selection::Pattern(pattern.regex.to_string()))
+            })
+    }
+
+    fn is_excluded(&self, column: &ColumnRef) -> bool {
+        self.exclusions
+            .iter()
+            .any(|exclusion| exclusion.matches(column))
+    }
+
+    /// Whether `column` is part of the primary key or of a unique index of
+    /// its table.
+    fn is_unique(&self, column: &ColumnRef) -> bool {
+        let Some(table) = self
+            .tables
+            .get(&(column.database.clone(), column.table.clone()))
+        else {
+            return false;
+        };
+
+        table
+            .primary_key
+            .iter()
+            .flat_map(|primary_key| primary_key.column_names.iter())
+            .chain(
+                table
+                    .indexes
+                    .iter()
+                    .filter(|index| index.unique)
+                    .flat_map(|index| index.columns.iter()),
+            )
+            .any(|name| name.eq_ignore_ascii_case(&column.column))
+    }
+
+    /// A replacement for `original` that no other value of the group has
+    /// been given, as the column would otherwise reject it as a duplicate.
+    ///
+    /// Fakers are retried until they give an unused value, other rules
+    /// fail as soon as they repeat one.
+    fn unique_replacement(
+        &mut self,
+        masker: &ColumnMasker<'a>,
+        column: &ColumnRef,
+        definition: Option<&Column>,
+        original: String,
+        context: &CellContext,
+    ) -> ExtractResult<Option<String>> {
+        if let Some(replacement) = self
+            .unique_values
+            .get(&masker.root)
+            .and_then(|values| values.replacements.get(&original))
+        {
+            return Ok(replacement.clone());
+        }
+
+        let retries = match masker.rule.faker() {
+            Some(_) => MAX_UNIQUE_ATTEMPTS,
+            None => 1,
+        };
+        for attempt in 0..retries {
+            let replacement = match attempt {
+                0 => self.replacement(masker, original.clone(), context)?,
+                _ => masker.mask(&format!("{original}\u{0}{attempt}"), context)?,
+            };
+            let fitted = match (&replacement, definition) {
+                (Some(replacement), Some(definition)) => {
+                    fitting::fit(replacement, &definition.data_type).unescaped()
+                }
+                (None, Some(definition)) if !definition.nullable => {
+                    fitting::not_null(&definition.data_type).unescaped()
+                }
+                (replacement, _) => replacement.clone(),
+            };
+
+            let values = self.unique_values.entry(masker.root.clone()).or_default();
+            // Unique indexes compare with the default collation of the
+            // column, which ignores case and trailing spaces.
+            if fitted.is_none_or(|fitted| values.used.insert(fitted.trim_end().to_lowercase())) {
+                values.replacements.insert(original, replacement.clone());
+                return Ok(replacement);
+            }
+        }
+
+        bail!(
+            "unable to mask {column} with unique values: rule `{}` ran out of distinct values after {}",
+            masker.rule.name(),
+            self.unique_values[&masker.root].used.len()
+        )
+    }
+
+    fn replacement(
+        &mut self,
+        masker: &ColumnMasker<'a>,
+        original: String,
+        context: &CellContext,
+    ) -> ExtractResult<Option<String>> {
+        if !masker.memoized || masker.deterministic {
+            return masker.mask(&original, context);
+        }
+
+        let key = (masker.root.clone(), original);
+        if let Some(replacement) = self.replacements.get(&key) {
+            return Ok(replacement.clone());
+        }
+        let replacement = masker.mask(&key.1, context)?;
+        self.replacements.insert(key, replacement.clone());
+        Ok(replacement)
+    }
+
+    /// The same seed for a column on every run with the same masking key.
+    fn shuffle_seed(&self, column: &ColumnRef) -> u64 {
+        match self.config.masking_key.as_deref() {
+            Some(key) => rules::keyed_seed(key, "shuffle", &column.to_string()) as u64,
+            None => RandomState::new().build_hasher().finish(),
+        }
+    }
+
+    /// Collect the values of the columns selected by name for `shuffle`.
+    fn record_shuffled(
+        &self,
+        insert: &Insert,
+        shuffled: &mut HashMap<ColumnRef, Vec<String>>,
+    ) -> ExtractResult<()> {
+        let columns = self.columns(insert)?;
+
+        for (index, column) in columns.iter().enumerate() {
+            if self
+                .column_masker(column)?
+                .is_some_and(|masker| masker.shuffle)
+            {
+                shuffled.entry(column.clone()).or_default().extend(
+                    insert
+                        .values
+                        .iter()
+                        .filter_map(|row| row.0[index].unescaped()),
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    fn record_updated(&mut self, update: &Update) {
+        let database = self.database.as_deref();
+        let columns = update
+            .set_clauses
+            .iter()
+            .flat_map(|assignment| assignment.kv_pairs.iter())
+            .map(|pair| pair.key.to_string())
+            .chain(
+                update
+                    .where_clauses
+                    .iter()
+                    .map(|condition| condition.column.trim_matches('`').to_string()),
+            );
+
+        for column in columns {
+            self.updated
+                .insert(ColumnRef::new(database, &update.table_name, &column));
+        }
+    }
+
+    /// The columns of an insert, in the current database.
+    fn columns(&self, insert: &Insert) -> ExtractResult<Vec<ColumnRef>> {
+        Ok(self
+            .checked_column_names(insert)?
+            .iter()
+            .map(|name| ColumnRef::new(self.database.as_deref(), &insert.table_name, name))
+            .collect())
+    }
+
+    /// The column names of an insert, checking that every row has a value
+    /// for each of them.
+    fn checked_column_names(&self, insert: &Insert) -> ExtractResult<Vec<String>> {
+        let column_names = self.column_names(insert)?;
+
+        if let Some(row) = insert
+            .values
+            .iter()
+            .find(|row| row.0.len() != column_names.len())
+        {
+            bail!(
+                "INSERT into `{}` has {} values for {} columns",
+                insert.table_name,
+                row.0.len(),
+                column_names.len()
+            );
+        }
+
+        Ok(column_names)
+    }
+
+    fn record_values(&mut self, insert: &Insert) -> ExtractResult<()> {
+        let columns = self.columns(insert)?;
+
+        for row in insert.values.iter() {
+            for (column, value) in columns.iter().zip(row.0.iter()) {
+                if let Some(value) = value.unescaped() {
+                    self.detection.record(column, &value);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Mask the values of an `INSERT` or `REPLACE`.
+    ///
+    /// Those into tables with nothing to mask are passed through without
+    /// being parsed, when no value pattern has to look at their values, and
+    /// so are the ones that can't be parsed or whose columns are unknown,
+    /// when none of their values match a pattern masking single cells.
+    fn mask_insert(&mut self, body: &str) -> ExtractResult<Option<String>> {
+        let unmasked = self.unmasked_insert(body)?;
+        if unmasked && self.cell_rules.is_empty() && (self.prepared || self.detection.is_empty()) {
+            return Ok(None);
+        }
+
+        let mut insert = match parse_rule::<Insert>(Rule::INSERT_STATEMENT, body) {
+            Ok(insert) => insert,
+            Err(_) if unmasked && self.cell_match(body).is_none() => return Ok(None),
+            Err(err) => {
+                return Err(match INSERT_HEAD.captures(body) {
+                    Some(head) => {
+                        err.context(format!("INSERT into `{}`", head[1].trim_matches('`')))
+                    }
+                    None => err,
+                })
+            }
+        };
+        let names = match self.checked_column_names(&insert) {
+            Ok(names) => names,
+            Err(_) if unmasked && self.cell_match(body).is_none() => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        if !self.prepared && !self.detection.is_empty() {
+            self.record_values(&insert)?;
+            // Columns may have just been flagged
+            self.plans.clear();
+        }
+
+        let plan = self.table_plan(self.database.clone(), &insert.table_name, &names)?;
+        let columns = names
+            .iter()
+            .map(|name| &plan.columns[plan.index(name).expect("planned with every name")])
+            .collect::<Vec<&ColumnPlan>>();
+
+        if columns
+            .iter()
+            .all(|column| column.masker.is_none() && column.json_paths.is_empty())
+            && self.cell_rules.is_empty()
+        {
+            return Ok(None);
+        }
+
+        // Without a prepared pool, values are shuffled within the statement
+        let mut statement_shuffled = HashMap::new();
+        for (index, column) in columns.iter().enumerate() {
+            if column.masker.as_ref().is_some_and(|masker| masker.shuffle)
+                && !self.shuffled.contains_key(&column.column)
+            {
+                let mut values = insert
+                    .values
+                    .iter()
+                    .filter_map(|row| row.0[index].unescaped())
+                    .collect::<Vec<String>>();
+                rules::shuffle(&mut values, self.shuffle_seed(&column.column));
+                statement_shuffled.insert(index, values.into_iter());
+            }
+        }
+
+        // Scripts, `shift_date` and `when` predicates get the original
+        // values of the whole row, templates the values of the columns
+        // masked before them
+        let row_aware = columns.iter().any(|column| {
+            column.masker.as_ref().is_some_and(|masker| {
+                masker.reads_row || masker.template().is_some() || masker.rule.when.is_some()
+            }) || column
+                .json_paths
+                .iter()
+                .any(|json_path| json_path.rule.reads_row() || json_path.rule.when.is_some())
+        }) || self.cell_rules.values().any(|rule| rule.when.is_some());
+        let order = masking_order(&columns)?;
+
+        let mut changed = false;
+        for row in insert.values.iter_mut() {
+            let originals = match row_aware {
+                true => names
+                    .iter()
+                    .map(|name| name.trim_matches('`').to_string())
+                    .zip(row.0.iter().map(InsertValue::unescaped))
+                    .collect(),
+                false => Vec::new(),
+            };
+            let mut masked = originals.clone();
+            for index in order.iter().copied() {
+                let (value, column) = (&mut row.0[index], columns[index]);
+                let Some(original) = value.unescaped() else {
+                    continue;
+                };
+
+                let replacement = match column.masker {
+                    Some(ref masker) if masker.shuffle => typed(
+                        self.shuffled
+                            .get_mut(&column.column)
+                            .or_else(|| statement_shuffled.get_mut(&index))
+                            .and_then(Iterator::next)
+                            .or(Some(original)),
+                        column.definition.as_ref(),
+                    ),
+                    _ => match self.masked_value(column, original, &originals, &masked)? {
+                        Some(replacement) => replacement,
+                        None => continue,
+                    },
+                };
+                if row_aware {
+                    masked[index].1 = replacement.unescaped();
+                }
+                *value = replacement;
+                changed = true;
+            }
+        }
+
+        Ok(changed.then(|| insert.to_string()))
+    }
+
+    /// Whether none of the columns of the `INSERT` in `body` are masked,
+    /// judging from the statement up to its `VALUES` alone, so the values
+    /// are not parsed for nothing. Without a column list or a `CREATE
+    /// TABLE`, whether the config selects no columns of the table by name.
+    fn unmasked_insert(&mut self, body: &str) -> ExtractResult<bool> {
+        let Some(head) = INSERT_HEAD.captures(body) else {
+            return Ok(false);
+        };
+        let table = head[1].trim_matches('`').to_string();
+        let names = match head.get(2) {
+            Some(columns) => columns
+                .as_str()
+                .split(',')
+                .map(|name| name.trim().trim_matches('`').to_string())
+                .collect(),
+            None => match self.tables.get(&(self.database.clone(), table.clone())) {
+                Some(create_table) => create_table
+                    .columns
+                    .iter()
+                    .map(|column| column.name.clone())
+                    .collect::<Vec<String>>(),
+                None => return Ok(!self.selects_table(&table)),
+            },
+        };
+
+        let plan = self.table_plan(self.database.clone(), &table, &names)?;
+        Ok(names.iter().all(|name| {
+            plan.index(name).is_some_and(|index| {
+                let column = &plan.columns[index];
+                column.masker.is_none() && column.json_paths.is_empty()
+            })
+        }))
+    }
+
+    /// The replacement for a value of `column` in `row`, typed for its
+    /// definition, or `None` when the value is left alone. `masked` is the
+    /// row as masked so far.
+    fn masked_value(
+        &mut self,
+        column: &ColumnPlan<'a>,
+        original: String,
+        row: &[(String, Option<String>)],
+        masked: &[(String, Option<String>)],
+    ) -> ExtractResult<Option<InsertValue>> {
+        let definition = column.definition.as_ref();
+        let context = CellContext {
+            column: Some(&column.column),
+            data_type: definition.map(|definition| &definition.data_type),
+            row,
+            masked,
+        };
+        let replacement = match column.masker {
+            Some(ref masker) if !masker.rule.applies_to(row) => return Ok(None),
+            Some(ref masker) if masker.rule.strategy() == Some(&Strategy::Tokenize) => {
+                Some(tokenize(&mut self.vault, &masker.rule, &original)?)
+            }
+            Some(ref masker) if masker.unique => {
+                self.unique_replacement(masker, &column.column, definition, original, &context)?
+            }
+            Some(ref masker) => self.replacement(masker, original, &context)?,
+            None if column.excluded => return Ok(None),
+            None if !column.json_paths.is_empty() => {
+                match self.masked_document(column, &original, &context)? {
+                    Some(document) => return Ok(Some(InsertValue::text(&document))),
+                    None => return Ok(None),
+                }
+            }
+            None => match self
+                .detection
+                .matching_cell(&original)
+                .and_then(|detector| self.cell_rules.get(&detector.name))
+                .filter(|rule| rule.applies_to(row))
+            {
+                Some(rule) if rule.strategy() == Some(&Strategy::Tokenize) => {
+                    check_holds_tokens(&column.column, definition)?;
+                    Some(tokenize(&mut self.vault, rule, &original)?)
+                }
+                Some(rule) => {
+                    rule.apply_in(&original, self.config.masking_key.as_deref(), &context)?
+                }
+                None => return Ok(None),
+            },
+        };
+
+        Ok(Some(typed(replacement, definition)))
+    }
+
+    /// `original` with the values at the JSON paths of `column` masked, or
+    /// `None` when it is not valid JSON or has nothing at those paths.
+    fn masked_document(
+        &mut self,
+        column: &ColumnPlan<'a>,
+        original: &str,
+        context: &CellContext,
+    ) -> ExtractResult<Option<String>> {
+        if serde_json::from_str::<serde::de::IgnoredAny>(original).is_err() {
+            return Ok(None);
+        }
+        let mut document = original.to_string();
+        // Values inside a document are not of the type of the column
+        let context = CellContext {
+            data_type: None,
+            ..*context
+        };
+
+        let mut masked = 0;
+        for json_path in column
+            .json_paths
+            .iter()
+            .filter(|json_path| json_path.rule.applies_to(context.row))
+        {
+            let rule = &json_path.rule;
+            masked += json_path
+                .path
+                .mask(&mut document, &mut |value| {
+                    rule.apply_in(value, self.config.masking_key.as_deref(), &context)
+                })
+                .with_context(|| {
+                    format!("unable to mask {} at `{}`", column.column, json_path.path)
+                })?;
+        }
+
+        Ok((masked > 0).then_some(document))
+    }
+
+    /// Mask the assigned values and the values compared in the `WHERE`
+    /// clause of an `UPDATE`, with the same replacements as in `INSERT`s,
+    /// so it still applies to the rows it was meant for.
+    ///
+    /// Columns masked with `shuffle` are left alone, as their values are
+    /// not replaced one for one, and so are the values compared by range or
+    /// pattern, which are not values of the column. `UPDATE`s that can't be
+    /// parsed are passed through, unless they touch what is masked.
+    fn mask_update(&mut self, body: &str) -> ExtractResult<Option<String>> {
+        let mut update = match parse_rule::<Update>(Rule::UPDATE_STATEMENT, body) {
+            Ok(update) => update,
+            Err(err) => {
+                return match self.masked_update_target(body) {
+                    Some(target) => Err(err.context(format!("cannot mask UPDATE of {target}"))),
+                    None => Ok(None),
+                }
+            }
+        };
+        let row = self.ordered_update_row(&update);
+        let mut changed = false;
+
+        for pair in update
+            .set_clauses
+            .iter_mut()
+            .flat_map(|assignment| assignment.kv_pairs.iter_mut())
+        {
+            let original = match pair.value {
+                AssignmentValue::String(ref value) => unescape_str(value),
+                AssignmentValue::Number(ref value) => value.clone(),
+                _ => continue,
+            };
+            if let Some(replacement) =
+                self.mask_update_value(&update.table_name, &pair.key.to_string(), original, &row)?
+            {
+                pair.value = match replacement {
+                    InsertValue::Text { value } => AssignmentValue::String(value),
+                    InsertValue::Number { value } => AssignmentValue::Number(value),
+                    _ => AssignmentValue::Null,
+                };
+                changed = true;
+            }
+        }
+
+        for condition in update.where_clauses.iter_mut().filter(|condition| {
+            EQUALITY_OPERATORS.contains(&condition.operator.to_uppercase().as_str())
+        }) {
+            if condition.values.is_empty() {
+                if let Some(replacement) = self.mask_literal(
+                    &update.table_name,
+                    &condition.column,
+                    &condition.value,
+                    &row,
+                )? {
+                    condition.value = replacement;
+                    changed = true;
+                }
+                continue;
+            }
+
+            // Each element of an `IN (...)` list
+            let mut masked = false;
+            for value in condition.values.iter_mut() {
+                if let Some(replacement) =
+                    self.mask_literal(&update.table_name, &condition.column, value, &row)?
+                {
+                    *value = replacement;
+                    masked = true;
+                }
+            }
+            if masked {
+                condition.value = format!("({})", condition.values.join(", "));
+                changed = true;
+            }
+        }
+
+        Ok(changed.then(|| {
+            let update = update.to_string();
+            update.strip_suffix(';').unwrap_or(&update).to_string()
+        }))
+    }
+
+    /// What the `UPDATE` in `body`, which could not be parsed, touches that
+    /// is masked: its table when it has a masked column, a masked column it
+    /// names, or a value a cell pattern masks. `None` when there is nothing.
+    fn masked_update_target(&self, body: &str) -> Option<String> {
+        let table = UPDATE_HEAD.captures(body)?[1].trim_matches('`').to_string();
+        let database = self.database.as_deref();
+
+        if self
+            .tables
+            .get(&(self.database.clone(), table.clone()))
+            .into_iter()
+            .flat_map(|create_table| create_table.columns.iter())
+            .any(|column| self.is_masked(&ColumnRef::new(database, &table, &column.name)))
+        {
+            return Some(format!("table `{table}`"));
+        }
+
+        let text = LITERAL.replace_all(body, "''");
+        if let Some(column) = IDENTIFIER
+            .find_iter(&text)
+            .map(|name| ColumnRef::new(database, &table, name.as_str().trim_matches('`')))
+            .find(|column| self.is_masked(column))
+        {
+            return Some(column.to_string());
+        }
+
+        self.cell_match(body)
+            .map(|name| format!("a value matching `{name}`"))
+    }
+
+    /// The name of the first value pattern masking single cells that a
+    /// string in `body` matches.
+    fn cell_match(&self, body: &str) -> Option<String> {
+        LITERAL
+            .find_iter(body)
+            .filter(|literal| literal.as_str().starts_with('\''))
+            .find_map(|literal| {
+                let value = unescape_str(strip_quotes(literal.as_str()));
+                let detector = self.detection.matching_cell(&value)?;
+                self.cell_rules
+                    .contains_key(&detector.name)
+                    .then(|| detector.name.clone())
+            })
+    }
+
+    /// Whether a column selector or JSON path of the config names `table`,
+    /// rather than only columns.
+    fn selects_table(&self, table: &str) -> bool {
+        let database = self.database.as_deref();
+        self.selectors
+            .iter()
+            .chain(self.json_paths.iter().map(|(selector, _, _)| selector))
+            .any(|selector| !selector.is_unscoped() && selector.matches_table(database, table))
+    }
+
+    /// Whether values of `column` or inside its documents are masked, or it
+    /// is selected without a way to mask it.
+    fn is_masked(&self, column: &ColumnRef) -> bool {
+        self.column_masker(column)
+            .map_or(true, |masker| masker.is_some())
+            || self
+                .json_path_maskers(column)
+                .map_or(true, |json_paths| !json_paths.is_empty())
+    }
+
+    /// The row an `UPDATE` applies to, as far as it tells, see
+    /// [`update_row`], with the first column of its table first, as in the
+    /// rows of `INSERT`s.
+    fn ordered_update_row(&self, update: &Update) -> Vec<(String, Option<String>)> {
+        let mut row = update_row(update);
+
+        if let Some(first) = self.first_column(&update.table_name) {
+            if let Some(position) = row
+                .iter()
+                .position(|(name, _)| name.eq_ignore_ascii_case(first))
+            {
+                let value = row.remove(position);
+                row.insert(0, value);
+            }
+        }
+
+        row
+    }
+
+    /// The first column of `table` in its `CREATE TABLE`.
+    fn first_column(&self, table: &str) -> Option<&str> {
+        let create_table = self
+            .tables
+            .get(&(self.database.clone(), table.to_string()))?;
+        Some(create_table.columns.first()?.name.as_str())
+    }
+
+    /// The replacement for a string or number literal compared to `column`
+    /// in a `WHERE` clause, written as a literal.
+    fn mask_literal(
+        &mut self,
+        table: &str,
+        column: &str,
+        literal: &str,
+        row: &[(String, Option<String>)],
+    ) -> ExtractResult<Option<String>> {
+        let Some(original) = literal_value(literal) else {
+            return Ok(None);
+        };
+        Ok(self
+            .mask_update_value(table, column, original, row)?
+            .map(|replacement| replacement.to_string()))
+    }
+
+    /// The replacement for a value of `column` in an `UPDATE` of `row`, see
+    /// [`Masker::ordered_update_row`].
+    ///
+    /// `shift_date` fails unless the `UPDATE` gives the value of its entity,
+    /// or of the first column of the table without one, as the date would
+    /// otherwise be shifted apart from the rest of its row.
+    fn mask_update_value(
+        &mut self,
+        table: &str,
+        column: &str,
+        original: String,
+        row: &[(String, Option<String>)],
+    ) -> ExtractResult<Option<InsertValue>> {
+        let name = column.trim_matches('`').to_string();
+        let plan = self.table_plan(self.database.clone(), table, std::slice::from_ref(&name))?;
+        let column = &plan.columns[plan.index(&name).expect("planned with the name")];
+        let Some(ref masker) = column.masker else {
+            return self.masked_value(column, original, row, &[]);
+        };
+        if masker.shuffle {
+            return Ok(None);
+        }
+
+        if let Some(Strategy::ShiftDate { entity, .. }) = masker.rule.strategy() {
+            let Some(entity) = entity.as_deref().or_else(|| self.first_column(table)) else {
+                bail!(
+                    "rule `{}` shifts {} by its first column, which is unknown without the CREATE TABLE of `{table}`",
+                    masker.rule.name(),
+                    column.column
+                );
+            };
+            if !row
+                .iter()
+                .any(|(name, _)| name.eq_ignore_ascii_case(entity))
+            {
+                bail!(
+                    "rule `{}` needs `{entity}` to shift {} in an UPDATE, which neither assigns it nor compares it with `=`",
+                    masker.rule.name(),
+                    column.column
+                );
+            }
+        }
+
+        self.masked_value(column, original, row, &[])
+    }
+
+    /// The definition of `column` in the `CREATE TABLE` seen earlier in the
+    /// dump.
+    fn column_definition(&self, column: &ColumnRef) -> Option<&Column> {
+        self.tables
+            .get(&(column.database.clone(), column.table.clone()))?
+            .columns
+            .iter()
+            .find(|definition| definition.name.eq_ignore_ascii_case(&column.column))
+    }
+
+    /// The column names of an insert, taken from the `CREATE TABLE` seen
+    /// earlier in the dump when the statement does not list them.
+    fn column_names(&self, insert: &Insert) -> ExtractResult<Vec<String>> {
+        if !insert.column_names.is_empty() {
+            return Ok(insert.column_names.clone());
+        }
+
+        let table = self
+            .tables
+            .get(&(self.database.clone(), insert.table_name.clone()))
+            .with_context(|| {
+                format!(
+                    "INSERT into `{}` has no column list and no CREATE TABLE was found for it",
+                    insert.table_name
+                )
+            })?;
+
+        Ok(table.columns.iter().map(|col| col.name.clone()).collect())
+    }
+}
+
+lazy_static! {
+    /// The `AUTO_INCREMENT` option of a `CREATE TABLE`.
+    static ref AUTO_INCREMENT: Regex = Regex::new(r"(?i)\bAUTO_INCREMENT\s*=?\s*(\d+)").unwrap();
+    /// The table and the column list of an `INSERT` or `REPLACE`.
+    static ref INSERT_HEAD: Regex = Regex::new(
+        r"(?is)^\s*(?:INSERT|REPLACE)\s+(?:(?:LOW_PRIORITY|DELAYED|HIGH_PRIORITY)\s+)?(?:IGNORE\s+)?INTO\s+(`[^`]+`|\w+)\s*(?:\(([^()]*)\))?\s*VALUES?\b"
+    )
+    .unwrap();
+    /// The table of an `UPDATE`.
+    static ref UPDATE_HEAD: Regex =
+        Regex::new(r"(?is)^\s*UPDATE\s+(?:LOW_PRIORITY\s+)?(?:IGNORE\s+)?(`[^`]+`|\w+)").unwrap();
+    /// The string and number literals of a statement.
+    pub(super) static ref LITERAL: Regex = Regex::new(r"'(?:[^'\\]|\\.|'')*'|\b-?\d+(?:\.\d+)?\b").unwrap();
+    /// The quoted and unquoted names of a statement, once its literals are
+    /// taken out.
+    static ref IDENTIFIER: Regex = Regex::new(r"`[^`]+`|\b[A-Za-z_]\w*\b").unwrap();
+}
+
+/// The operators of the `WHERE` conditions whose values are masked, as they
+/// compare with values of the column.
+const EQUALITY_OPERATORS: [&str; 4] = ["=", "!=", "<>", "IN"];
+
+/// How many values a faker may generate for a column in a unique index
+/// before its value space is considered exhausted.
+const MAX_UNIQUE_ATTEMPTS: usize = 1000;
+
+/// The values of a group with a column in a unique index.
+#[derive(Default)]
+struct UniqueValues {
+    replacements: HashMap<String, Option<String>>,
+    used: HashSet<String>,
+}
+
+/// The order to mask the columns of an `INSERT` in, so that templates come
+/// after the columns they refer to, and otherwise the order of the columns.
+fn masking_order(columns: &[&ColumnPlan]) -> ExtractResult<Vec<usize>> {
+    let dependencies = columns
+        .iter()
+        .map(|column| {
+            let Some(template) = column.masker.as_ref().and_then(ColumnMasker::template) else {
+                return Vec::new();
+            };
+            template
+                .masked_columns()
+                .filter_map(|name| {
+                    columns
+                        .iter()
+                        .position(|column| column.column.column.eq_ignore_ascii_case(name))
+                })
+                .collect()
+        })
+        .collect::<Vec<Vec<usize>>>();
+
+    let mut order = Vec::with_capacity(columns.len());
+    let mut done = vec![false; columns.len()];
+    while order.len() < columns.len() {
+        let Some(next) = (0..columns.len()).find(|&index| {
+            !done[index]
+                && dependencies[index]
+                    .iter()
+                    .all(|&dependency| done[dependency])
+        }) else {
+            let cycle = (0..columns.len())
+                .filter(|&index| !done[index])
+                .map(|index| columns[index].column.to_string())
+                .collect::<Vec<String>>();
+            bail!("the templates of {} refer to each other", cycle.join(", "));
+        };
+        done[next] = true;
+        order.push(next);
+    }
+
+    Ok(order)
+}
+
+/// The original values an `UPDATE` assigns and compares with `=`, by
+/// column name.
+fn update_row(update: &Update) -> Vec<(String, Option<String>)> {
+    let assigned = update
+        .set_clauses
+        .iter()
+        .flat_map(|assignment| assignment.kv_pairs.iter())
+        .filter_map(|pair| {
+            let value = match pair.value {
+                AssignmentValue::String(ref value) => Some(unescape_str(value)),
+                AssignmentValue::Number(ref value) => Some(value.clone()),
+                AssignmentValue::Null => None,
+                _ => return None,
+            };
+            Some((pair.key.to_string().trim_matches('`').to_string(), value))
+        });
+    let compared = update
+        .where_clauses
+        .iter()
+        .filter(|condition| condition.operator == "=")
+        .filter_map(|condition| {
+            let value = literal_value(&condition.value)?;
+            Some((condition.column.trim_matches('`').to_string(), Some(value)))
+        });
+
+    assigned.chain(compared).collect()
+}
+
+/// The value of a string or number literal.
+fn literal_value(literal: &str) -> Option<String> {
+    match literal.strip_prefix('\'') {
+        Some(_) => Some(unescape_str(strip_quotes(literal))),
+        None if literal.parse::<f64>().is_ok() => Some(literal.to_string()),
+        None => None,
+    }
+}
+
+/// A replacement as a value for a column with `definition`, which is
+/// `NULL` for `None` unless the column is `NOT NULL`.
+fn typed(replacement: Option<String>, definition: Option<&Column>) -> InsertValue {
+    match (replacement, definition) {
+        (Some(replacement), Some(definition)) => fitting::fit(&replacement, &definition.data_type),
+        (Some(replacement), None) => InsertValue::text(&replacement),
+        (None, Some(definition)) if !definition.nullable => {
+            fitting::not_null(&definition.data_type)
+        }
+        (None, _) => InsertValue::Null,
+    }
+}
+
+/// The token of `original` from `vault`, which `rule` needs.
+fn tokenize(
+    vault: &mut Option<TokenVault>,
+    rule: &MaskingRule,
+    original: &str,
+) -> ExtractResult<String> {
+    match vault {
+        Some(vault) => vault.tokenize(original),
+        None => bail!("rule `{}` needs a token vault", rule.name()),
+    }
+}
+
+/// Fail when `column` cannot hold tokens whole, as they would be fitted
+/// into values that detokenizing no longer restores.
+fn check_holds_tokens(column: &ColumnRef, definition: Option<&Column>) -> ExtractResult<()> {
+    match definition {
+        Some(definition) if !vault::holds_tokens(&definition.data_type) => bail!(
+            "{column} is a {} column, which cannot hold the tokens of `tokenize`",
+            definition.data_type
+        ),
+        _ => Ok(()),
+    }
+}
+
+/// Fail for the rules that can't mask values inside JSON documents:
+/// `shuffle`, which takes its replacements from whole columns, and
+/// `tokenize`, whose tokens detokenizing only finds as whole values.
+fn check_json_path_rule(path: &JsonPath, rule: &MaskingRule) -> ExtractResult<()> {
+    let name = match rule.strategy() {
+        Some(Strategy::Shuffle) => "shuffle",
+        Some(Strategy::Tokenize) => "tokenize",
+        _ => return Ok(()),
+    };
+    bail!("`{name}` cannot mask the values at JSON path `{path}`")
+}
+
+fn is_shuffle(rule: Option<&MaskingRule>) -> bool {
+    rule.and_then(MaskingRule::strategy) == Some(&Strategy::Shuffle)
+}
+
+/// Mask every statement read from `input` in a single pass, writing the
+/// resulting dump to `output` as it goes.
+///
+/// Foreign keys only link columns from the point their `CREATE TABLE` is
+/// read, and value patterns only flag a column once enough of the values
+/// read so far match. Use [`Masker::prepare`] first when the input can be
+/// read twice.
+///
+/// Returns the number of statements that were rewritten.
+pub fn mask_dump<R: Read, W: Write>(
+    config: &MaskingConfig,
+    input: R,
+    output: &mut W,
+) -> ExtractResult<usize> {
+    Masker::new(config)?.mask_dump(input, output)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{dump::split_statements, parser::types::DataType, settings::parse_masking_config};
+
+    #[test]
+    fn can_mask_dump() {
+        let config = parse_masking_config("./tests/more.yaml").unwrap();
+        let input = std::fs::read_to_string("./tests/schema_dump.sql").unwrap();
+        let mut output = Vec::new();
+
+        let rewritten = mask_dump(&config, input.as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert_eq!(rewritten, 3);
+        assert!(output.starts_with("--\n-- NAMEDMANAGER APPLICATION\n"));
+        assert!(output.contains(
+            "INSERT INTO `config` (`name`, `value`) VALUES('ZONE_DB_PASSWORD', 'sdr05ynw4tuj');"
+        ));
+        assert!(!output.contains("'admin','admin','admin'"));
+        assert!(!output.contains("$2b$12$"));
+        assert_eq!(output.lines().count(), input.lines().count());
+
+        let statements = split_statements(&input)
+            .unwrap()
+            .into_iter()
+            .zip(split_statements(&output).unwrap())
+            .filter(|(original, _)| !["INSERT", "UPDATE"].contains(&original.keyword().as_str()))
+            .collect::<Vec<_>>();
+        assert!(statements
+            .iter()
+            .all(|(original, masked)| original == masked));
+    }
+
+    #[test]
+    fn can_mask_insert_without_column_list() {
+        let config = parse_masking_config("./tests/more.yaml").unwrap();
+        let input = "USE `app`;\nCREATE TABLE `users` (\n  `id` int NOT NULL,\n  `password` varchar(255) DEFAULT NULL\n);\nINSERT INTO `users` VALUES (1, 'hunter2'), (2, NULL);\n";
+        let mut output = Vec::new();
+
+        mask_dump(&config, input.as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(!output.contains("hunter2"));
+        assert!(output.contains("INSERT INTO `users` VALUES (1, '"));
+        assert!(output.contains("(2, NULL);\n"));
+    }
+
+    #[test]
+    fn can_mask_binary_literals() {
+        let config = parse_masking_config("./tests/more.yaml").unwrap();
+        let input = "USE `app`;\nCREATE TABLE `blobs` (\n  `id` int NOT NULL,\n  `data` blob\n);\nINSERT INTO `blobs` VALUES (1,_binary 'a\\'b'),(2,X'0A1B') ;\nCREATE TABLE `users` (\n  `id` int NOT NULL,\n  `avatar` blob,\n  `flags` bit(4),\n  `password` varchar(255) DEFAULT NULL\n);\nINSERT INTO `users` VALUES (1,_binary '\\0\\Z',b'0101','hunter2');\n";
+        let mut output = Vec::new();
+
+        mask_dump(&config, input.as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.contains("INSERT INTO `blobs` VALUES (1,_binary 'a\\'b'),(2,X'0A1B') ;"));
+        assert!(!output.contains("hunter2"));
+        assert!(output.contains("INSERT INTO `users` VALUES (1, _binary '\\0\\Z', b'0101', '"));
+    }
+
+    #[test]
+    fn passes_through_inserts_into_tables_with_nothing_to_mask() {
+        let config = config_from_yaml(
+            "columns: [email, accounts.name]\nvalue_patterns:\n  - name: card\n    detector: credit_card\n    scope: cell\nrules:\n  card: payment::credit_card_luhn_number()\n",
+        );
+        let input =
+            "INSERT INTO `log` (`id`, `at`) VALUES (1, NOW()) ON DUPLICATE KEY UPDATE `at` = NOW();
+INSERT INTO `log` VALUES (2, '2024-01-01');
+INSERT INTO `users` (`id`, `email`) VALUES (1, 'john@example.com');
+";
+        let mut masker = Masker::new(&config).unwrap();
+        masker.prepare(input.as_bytes()).unwrap();
+        let mut output = Vec::new();
+        assert_eq!(masker.mask_dump(input.as_bytes(), &mut output).unwrap(), 1);
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(
+            output.lines().take(2).collect::<Vec<&str>>(),
+            input.lines().take(2).collect::<Vec<&str>>()
+        );
+        assert!(!output.contains("john@example.com"));
+
+        for (insert, error) in [
+            (
+                "INSERT INTO `log` (`id`, `note`) VALUES (3, '4111 1111 1111 1111'), (4, NOW());",
+                "unable to mask statement 4: INSERT into `log`: unable to parse INSERT_STATEMENT",
+            ),
+            (
+                "INSERT INTO `users` (`id`, `email`) VALUES (2, LOWER('jane@example.com'));",
+                "unable to mask statement 4: INSERT into `users`: unable to parse INSERT_STATEMENT",
+            ),
+            (
+                "INSERT INTO `accounts` VALUES (1, 'Ann');",
+                "unable to mask statement 4: INSERT into `accounts` has no column list",
+            ),
+        ] {
+            let input = format!("{input}{insert}\n");
+            let err = mask_dump(&config, input.as_bytes(), &mut Vec::new()).unwrap_err();
+            assert!(format!("{err:#}").contains(error), "{err:#}");
+        }
+    }
+
+    #[test]
+    fn can_mask_deterministically_with_key() {
+        let mut config = parse_masking_config("./tests/more.yaml").unwrap();
+        config.masking_key = Some("secret".to_string());
+        let input = "INSERT INTO `users` (`id`, `account`) VALUES (1, 'jdoe'), (2, 'jdoe'), (3, 'asmith');\n";
+
+        let mask = || {
+            let mut output = Vec::new();
+            mask_dump(&config, input.as_bytes(), &mut output).unwrap();
+            String::from_utf8(output).unwrap()
+        };
+        let output = mask();
+        let insert = parse_rule::<Insert>(
+            Rule::INSERT_STATEMENT,
+            output.trim_end().trim_end_matches(';'),
+        )
+        .unwrap();
+        let accounts = insert
+            .values
+            .iter()
+            .map(|row| row.0[1].unescaped().unwrap())
+            .collect::<Vec<String>>();
+
+        assert_eq!(output, mask());
+        assert_eq!(accounts[0], accounts[1]);
+        assert_ne!(accounts[0], "jdoe");
+    }
+
+    const LINKED_DUMP: &str = "CREATE TABLE `orders` (
+  `id` int NOT NULL,
+  `customer_email` varchar(255) NOT NULL,
+  CONSTRAINT `fk_customer` FOREIGN KEY (`customer_email`) REFERENCES `users` (`email`) ON DELETE CASCADE
+);
+INSERT INTO `orders` VALUES (1, 'john@example.com'), (2, 'jane@example.com'), (3, 'john@example.com');
+CREATE TABLE `users` (
+  `email` varchar(255) NOT NULL,
+  `backup_email` varchar(255) DEFAULT NULL
+);
+INSERT INTO `users` VALUES ('john@example.com', 'jane@example.com'), ('jane@example.com', NULL);
+";
+
+    /// The values of every `INSERT` in a masked dump, by table.
+    fn inserted_values(output: &str) -> HashMap<String, Vec<Vec<String>>> {
+        split_statements(output)
+            .unwrap()
+            .into_iter()
+            .filter(|statement| statement.keyword() == "INSERT")
+            .fold(HashMap::new(), |mut values, statement| {
+                let insert = parse_rule::<Insert>(Rule::INSERT_STATEMENT, &statement.body).unwrap();
+                let rows = insert.values.iter().map(|row| {
+                    row.0
+                        .iter()
+                        .map(|value| value.unescaped().unwrap_or_default())
+                        .collect()
+                });
+                values
+                    .entry(insert.table_name)
+                    .or_insert_with(Vec::new)
+                    .extend(rows);
+                values
+            })
+    }
+
+    #[test]
+    fn can_mask_along_foreign_keys() {
+        let config = MaskingConfig {
+            columns: vec!["email".to_string()],
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+
+        mask_dump(&config, LINKED_DUMP.as_bytes(), &mut output).unwrap();
+        let values = inserted_values(&String::from_utf8(output).unwrap());
+        let (orders, users) = (&values["orders"], &values["users"]);
+
+        assert!(!orders[0][1].contains("@example.com"));
+        assert!(!users[1][0].contains("@example.com"));
+        assert_eq!(orders[0][1], users[0][0]);
+        assert_eq!(orders[1][1], users[1][0]);
+        assert_eq!(orders[0][1], orders[2][1]);
+        assert_ne!(orders[0][1], orders[1][1]);
+        assert_eq!(users[0][1], "jane@example.com");
+    }
+
+    #[test]
+    fn rejects_columns_without_rule_or_faker() {
+        let config = config_from_yaml("columns: [nickname, email]\n");
+        let input =
+            "CREATE TABLE `users` (\n  `nickname` varchar(20),\n  `email` varchar(50)\n);\n";
+
+        let err = mask_dump(&config, input.as_bytes(), &mut Vec::new()).unwrap_err();
+        assert!(format!("{err:#}").contains(
+            "cannot mask `users`.`nickname`: `nickname` has no masking rule and no faker is named after it"
+        ), "{err:#}");
+
+        let mut masker = Masker::new(&config).unwrap();
+        assert!(masker.prepare(input.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn rejects_tokenizing_columns_too_short_for_tokens() {
+        let config = config_from_yaml(
+            "columns: [email, code]\nrules:\n  email: tokenize()\n  code: tokenize()\n",
+        );
+        let tokenized = |definition: &str| {
+            let input = format!("CREATE TABLE `users` (\n  `email` varchar(64),\n  `code` {definition}\n);\nINSERT INTO `users` VALUES ('john@example.com', '12');\n");
+            let mut masker = Masker::new(&config).unwrap();
+            masker.set_vault(TokenVault::new("secret").unwrap());
+            let mut output = Vec::new();
+            masker.mask_dump(input.as_bytes(), &mut output).map(|_| {
+                let output = String::from_utf8(output).unwrap();
+                inserted_values(&output)["users"][0].clone()
+            })
+        };
+
+        let values = tokenized("varchar(20)").unwrap();
+        assert!(values
+            .iter()
+            .all(|value| value.starts_with("tok_") && value.len() == 20));
+        for definition in ["varchar(10)", "int", "char(19)"] {
+            let err = format!("{:#}", tokenized(definition).unwrap_err());
+            assert!(
+                err.contains("cannot hold the tokens of `tokenize`"),
+                "{err}"
+            );
+        }
+    }
+
+    #[test]
+    fn can_detokenize_what_it_tokenized() {
+        let config = config_from_yaml("columns: [email]\nrules:\n  email: tokenize()\n");
+        let input = "CREATE TABLE `users` (\n  `id` int NOT NULL,\n  `email` varchar(64)\n);
+INSERT INTO `users` VALUES (1, 'john@example.com'), (2, 'ann@example.com');
+UPDATE `users` SET `email` = 'jane@example.com' WHERE `email` = 'john@example.com';
+UPDATE `users` SET `id` = 3 WHERE `email` IN ('jane@example.com', 'ann@example.com');
+";
+        let mut masker = Masker::new(&config).unwrap();
+        masker.set_vault(TokenVault::new("secret").unwrap());
+        let mut masked = Vec::new();
+        assert_eq!(masker.mask_dump(input.as_bytes(), &mut masked).unwrap(), 3);
+        let masked = String::from_utf8(masked).unwrap();
+        assert!(!masked.contains("example.com"), "{masked}");
+
+        let mut output = Vec::new();
+        let vault = masker.vault().unwrap();
+        assert_eq!(
+            detokenize_dump(vault, &[], None, masked.as_bytes(), &mut output).unwrap(),
+            3
+        );
+        assert_eq!(String::from_utf8(output).unwrap(), input);
+    }
+
+    #[test]
+    fn can_mask_along_configured_relationships() {
+        let mut config: MaskingConfig = config_from_yaml(
+            "columns: [customer_email]\nrules:\n  customer_email: contact::email()\nrelationships:\n  - column: users.backup_email\n    references: users.email\n",
+        );
+        config.masking_key = Some("secret".to_string());
+        let mut masker = Masker::new(&config).unwrap();
+        let mut output = Vec::new();
+
+        masker.prepare(LINKED_DUMP.as_bytes()).unwrap();
+        masker
+            .mask_dump(LINKED_DUMP.as_bytes(), &mut output)
+            .unwrap();
+        let values = inserted_values(&String::from_utf8(output).unwrap());
+        let (orders, users) = (&values["orders"], &values["users"]);
+
+        assert!(!values
+            .values()
+            .flatten()
+            .flatten()
+            .any(|value| value.contains("@example.com")));
+        assert_eq!(orders[0][1], users[0][0]);
+        assert_eq!(orders[1][1], users[1][0]);
+        assert_eq!(users[0][1], users[1][0]);
+    }
+
+    #[test]
+    fn can_mask_columns_flagged_by_values() {
+        let config = config_from_yaml(
+            "value_patterns:\n  - name: email\n  - name: card\n    detector: credit_card\n    scope: cell\nrules:\n  card: payment::credit_card_luhn_number()\n",
+        );
+        let input = "INSERT INTO `t` (`id`, `contact`, `notes`) VALUES (1, 'john@example.com', 'hello'), (2, 'n/a', '4111 1111 1111 1111');
+INSERT INTO `t` (`id`, `contact`, `notes`) VALUES (3, 'jane@example.com', '4111 1111 1111 1112'), (4, 'bob@example.com', NULL), (5, 'amy@example.com', 'ok');
+";
+        let mut masker = Masker::new(&config).unwrap();
+        let mut output = Vec::new();
+
+        masker.prepare(input.as_bytes()).unwrap();
+        let rewritten = masker.mask_dump(input.as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let contact = ColumnRef::new(None, "t", "contact");
+
+        assert_eq!(rewritten, 2);
+        assert_eq!(masker.detection().stats[&contact].values, 5);
+        assert_eq!(masker.detection().stats[&contact].matches, vec![4, 0]);
+        assert!(!output.contains("@example.com"));
+        assert!(!output.contains("'n/a'"));
+        assert!(output.contains("'hello'"));
+        assert!(!output.contains("'4111 1111 1111 1111'"));
+        assert!(output.contains("'4111 1111 1111 1112'"));
+        assert!(output.contains("(1, '"));
+    }
+
+    #[test]
+    fn can_mask_with_strategies() {
+        let config = config_from_yaml(
+            "columns: [email, phone, bio, country, city, token]
+rules:
+  email: redact
+  phone: set_null
+  bio: truncate(3)
+  country: \"constant('NL')\"
+  city: shuffle
+  token: sha256('pepper')
+",
+        );
+        let input = "INSERT INTO `t` (`email`, `phone`, `bio`, `country`, `city`, `token`) VALUES ('john@example.com', '555', 'Hello', 'DE', 'Berlin', 'abc'), ('amy@test.org', NULL, 'Hi', 'FR', 'Paris', 'def');
+INSERT INTO `t` (`email`, `phone`, `bio`, `country`, `city`, `token`) VALUES ('bob@x.io', '556', 'Yo', 'BE', 'Ghent', 'ghi'), ('eve@y.io', '557', 'Hey', 'IT', 'Rome', 'jkl');
+";
+        let mut masker = Masker::new(&config).unwrap();
+        let mut output = Vec::new();
+
+        masker.prepare(input.as_bytes()).unwrap();
+        masker.mask_dump(input.as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let values = inserted_values(&output);
+        let rows = &values["t"];
+
+        assert!(output.contains("VALUES ('j***@***.com', NULL, 'Hel', 'NL', '"));
+        assert_eq!(
+            rows[0][5],
+            Strategy::Hash {
+                salt: Some("pepper".to_string())
+            }
+            .apply("abc", None)
+            .unwrap()
+        );
+
+        let mut cities = rows
+            .iter()
+            .map(|row| row[4].clone())
+            .collect::<Vec<String>>();
+        cities.sort();
+        assert_eq!(cities, vec!["Berlin", "Ghent", "Paris", "Rome"]);
+    }
+
+    #[test]
+    fn can_fit_replacements_to_column_types() {
+        let config = config_from_yaml(
+            "columns: [name, age, status, email, born]
+rules:
+  name: name::full()
+  age: name::first()
+  status: words::word()
+  email: set_null
+  born: sha256
+",
+        );
+        let input = "CREATE TABLE `people` (
+  `name` varchar(5) NOT NULL,
+  `age` tinyint unsigned DEFAULT NULL,
+  `status` enum('active','blocked') NOT NULL,
+  `email` varchar(255) NOT NULL,
+  `born` date DEFAULT NULL
+);
+INSERT INTO `people` VALUES ('Ann','42','active','ann@example.com','1990-01-01'),('Bob','37','blocked','bob@example.com','1985-06-15');
+";
+        let mut output = Vec::new();
+
+        mask_dump(&config, input.as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        for row in &inserted_values(&output)["people"] {
+            assert!(row[0].chars().count() <= 5, "{row:?}");
+            assert!(row[1].parse::<u8>().is_ok(), "{row:?}");
+            assert!(["active", "blocked"].contains(&row[2].as_str()), "{row:?}");
+            assert_eq!(row[3], "");
+            assert_eq!(
+                fitting::fit(&row[4], &DataType::Date).to_string(),
+                format!("'{}'", row[4])
+            );
+        }
+        assert!(!output.lines().last().unwrap().contains("NULL"));
+    }
+
+    const UNIQUE_DUMP: &str = "CREATE TABLE `months` (
+  `id` int NOT NULL,
+  `name` varchar(20) NOT NULL,
+  `code` char(2) DEFAULT NULL,
+  PRIMARY KEY (`id`),
+  UNIQUE KEY `name` (`name`)
+);
+";
+
+    fn unique_dump(rows: usize) -> String {
+        let values = (0..rows)
+            .map(|id| format!("({id}, 'month {id}', 'm{id}')"))
+            .collect::<Vec<String>>()
+            .join(", ");
+        format!("{UNIQUE_DUMP}INSERT INTO `months` VALUES {values};\n")
+    }
+
+    #[test]
+    fn can_mask_unique_columns_with_unique_values() {
+        let config = config_from_yaml(
+            "columns: [name, id]
+rules:
+  name: datetime::month()
+  id: name::first()
+",
+        );
+        let mut output = Vec::new();
+
+        mask_dump(&config, unique_dump(11).as_bytes(), &mut output).unwrap();
+        let rows = &inserted_values(&String::from_utf8(output).unwrap())["months"];
+
+        for column in [0, 1] {
+            let values = rows
+                .iter()
+                .map(|row| row[column].clone())
+                .collect::<HashSet<String>>();
+            assert_eq!(values.len(), 11);
+        }
+        assert!(rows.iter().all(|row| row[0].parse::<i32>().is_ok()));
+    }
+
+    #[test]
+    fn fails_when_unique_values_run_out() {
+        let config = config_from_yaml(
+            "columns: [name]
+rules:
+  name: datetime::month()
+",
+        );
+        let err = mask_dump(&config, unique_dump(12).as_bytes(), &mut Vec::new()).unwrap_err();
+        assert!(format!("{err:#}").contains("`months`.`name` with unique values"));
+
+        let config = config_from_yaml("columns: [name]\nrules:\n  name: \"constant('x')\"\n");
+        assert!(mask_dump(&config, unique_dump(2).as_bytes(), &mut Vec::new()).is_err());
+
+        // `code` has no unique index, so its values may repeat
+        let config = config_from_yaml("columns: [code]\nrules:\n  code: \"constant('x')\"\n");
+        assert!(mask_dump(&config, unique_dump(2).as_bytes(), &mut Vec::new()).is_ok());
+    }
+
+    #[test]
+    fn can_mask_updates_like_inserts() {
+        let config = config_from_yaml("columns: [email]\n");
+        let input = "CREATE TABLE `users` (
+  `id` int NOT NULL,
+  `email` varchar(255) NOT NULL,
+  `name` varchar(255) DEFAULT NULL
+);
+INSERT INTO `users` VALUES (1,'john@example.com','John');
+UPDATE `users` SET `email` = 'jane@example.com' WHERE `email` = 'john@example.com';
+UPDATE `users` SET `name` = 'Jo' WHERE `id` = 1;
+UPDATE `users` SET `name` = NULL WHERE `email` IN ('john@example.com', 'ann@example.com', NULL);
+";
+
+        let mut masker = Masker::new(&config).unwrap();
+        masker.prepare(input.as_bytes()).unwrap();
+        let mut prepared = Vec::new();
+        masker.mask_dump(input.as_bytes(), &mut prepared).unwrap();
+        let mut single_pass = Vec::new();
+        mask_dump(&config, input.as_bytes(), &mut single_pass).unwrap();
+
+        for output in [prepared, single_pass] {
+            let output = String::from_utf8(output).unwrap();
+            let email = &inserted_values(&output)["users"][0][1];
+            let updates = output
+                .lines()
+                .filter(|line| line.starts_with("UPDATE"))
+                .collect::<Vec<&str>>();
+
+            assert!(!output.contains("example.com"), "{output}");
+            assert!(updates[0].ends_with(&format!("WHERE `email` = '{email}';")));
+            assert_eq!(
+                updates[1],
+                "UPDATE `users` SET `name` = 'Jo' WHERE `id` = 1;"
+            );
+            assert!(
+                updates[2].contains(&format!("WHERE `email` IN ('{email}', '")),
+                "{}",
+                updates[2]
+            );
+            assert!(updates[2].ends_with(", NULL);"));
+        }
+    }
+
+    #[test]
+    fn masks_only_values_compared_for_equality() {
+        let config = config_from_yaml("columns: [email]\n");
+        let input = "UPDATE `users` SET `name` = 'x' WHERE `email` LIKE '%@corp.com';
+UPDATE `users` SET `name` = 'x' WHERE `email` >= 'a@corp.com' AND `email` < 'm@corp.com';
+UPDATE `users` SET `name` = 'x' WHERE `email` > 'n@corp.com';
+UPDATE `users` SET `name` = 'x' WHERE `email` != 'john@example.com';
+UPDATE `users` SET `name` = 'x' WHERE `email` <> 'john@example.com';
+";
+        let mut output = Vec::new();
+
+        mask_dump(&config, input.as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines = output.lines().collect::<Vec<&str>>();
+
+        assert_eq!(lines[..3], input.lines().take(3).collect::<Vec<&str>>()[..]);
+        for (line, operator) in lines[3..].iter().zip(["!=", "<>"]) {
+            assert!(
+                line.contains(&format!("WHERE `email` {operator} '")),
+                "{line}"
+            );
+            assert!(!line.contains("john@example.com"), "{line}");
+        }
+    }
+
+    #[test]
+    fn passes_through_updates_it_cannot_parse() {
+        let config = config_from_yaml("columns: [email]\n");
+        let input = "CREATE TABLE `users` (\n  `id` int NOT NULL,\n  `email` varchar(255)\n);
+CREATE TABLE `stats` (\n  `id` int NOT NULL,\n  `hits` int NOT NULL\n);
+UPDATE `stats` SET `hits` = `hits` + 1 WHERE `id` = 1;
+UPDATE `stats` SET `hits` = 0 WHERE id = 1 LIMIT 1;
+";
+        let mut masker = Masker::new(&config).unwrap();
+        masker.prepare(input.as_bytes()).unwrap();
+        let mut output = Vec::new();
+        assert_eq!(masker.mask_dump(input.as_bytes(), &mut output).unwrap(), 0);
+        assert_eq!(String::from_utf8(output).unwrap(), input);
+
+        for (update, target) in [
+            (
+                "UPDATE `users` SET `id` = `id` + 1 WHERE `id` = 1;",
+                "table `users`",
+            ),
+            (
+                "UPDATE `accounts` SET `email` = 'a@b.c' WHERE `id` = 1 LIMIT 1;",
+                "`accounts`.`email`",
+            ),
+        ] {
+            let input = format!("{input}{update}\n");
+            let err = mask_dump(&config, input.as_bytes(), &mut Vec::new()).unwrap_err();
+            assert!(
+                format!("{err:#}").contains(&format!("cannot mask UPDATE of {target}: ")),
+                "{err:#}"
+            );
+        }
+    }
+
+    #[test]
+    fn can_mask_columns_selected_by_table() {
+        let config = config_from_yaml(
+            "columns: [users.name, \"app.*.*email\"]
+exclude: [admins.backup_email]
+",
+        );
+        let input = "USE `app`;
+INSERT INTO `users` (`name`, `email`) VALUES ('John', 'john@example.com');
+INSERT INTO `config` (`name`, `value`) VALUES ('site', 'example');
+INSERT INTO `admins` (`email`, `backup_email`) VALUES ('root@example.com', 'backup@example.com');
+USE `other`;
+INSERT INTO `users` (`name`, `email`) VALUES ('Jane', 'jane@example.com');
+";
+        let mut output = Vec::new();
+
+        mask_dump(&config, input.as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        for unmasked in ["'site'", "backup@example.com", "jane@example.com"] {
+            assert!(output.contains(unmasked), "{unmasked}");
+        }
+        for masked in ["'John'", "john@example.com", "root@example.com", "'Jane'"] {
+            assert!(!output.contains(masked), "{masked}");
+        }
+        assert!(Masker::new(&config_from_yaml("columns: [a.b.c.d]\n")).is_err());
+    }
+
+    #[test]
+    fn can_plan_masking() {
+        let config = config_from_yaml(
+            "columns: [users.email, \"*_at\"]
+exclude: [audit.*]
+rules:
+  email: redact
+  created_at: constant('2000-01-01')
+value_patterns:
+  - name: phone
+  - name: ssn
+    scope: cell
+",
+        );
+        let input = "CREATE TABLE `users` (
+  `id` int NOT NULL,
+  `email` varchar(64) NOT NULL,
+  `mobile` varchar(32) DEFAULT NULL,
+  `created_at` date DEFAULT NULL,
+  PRIMARY KEY (`id`),
+  UNIQUE KEY `email` (`email`)
+);
+CREATE TABLE `orders` (
+  `id` int NOT NULL,
+  `email` varchar(64) NOT NULL,
+  CONSTRAINT `orders_email` FOREIGN KEY (`email`) REFERENCES `users` (`email`)
+);
+CREATE TABLE `audit` (
+  `updated_at` date DEFAULT NULL
+);
+INSERT INTO `users` VALUES (1, 'john@example.com', '+1 555 010 9999', '2024-01-01');
+";
+        let mut masker = Masker::new(&config).unwrap();
+        masker.prepare(input.as_bytes()).unwrap();
+        let plan = masker.plan().unwrap();
+
+        assert_eq!(
+            plan.tables
+                .iter()
+                .map(|table| table.table.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["audit", "orders", "users"]
+        );
+        let users = &plan.tables[2];
+        let email = users.columns[users.index("`EMAIL`").unwrap()]
+            .masker
+            .as_ref()
+            .unwrap();
+        assert_eq!(email.rule.name(), "redact");
+        assert_eq!(
+            email.selection,
+            Selection::Selector("users.email".to_string())
+        );
+        assert!(email.unique && email.memoized && email.deterministic);
+        assert_eq!(
+            email
+                .mask("john@example.com", &CellContext::default())
+                .unwrap()
+                .unwrap(),
+            "j***@***.com"
+        );
+        assert!(users.columns[0].masker.is_none());
+
+        let markdown = plan.to_markdown();
+        for row in [
+            "| `orders`.`email` | VARCHAR (64) | redact | column `users.email` | linked to `users`.`email`, unique, deterministic |",
+            "| `users`.`mobile` | VARCHAR (32) | phone | value pattern `phone` | random |",
+            "| `users`.`created_at` | DATE | constant('2000-01-01') | column `*_at` | deterministic |",
+            "| `audit`.`updated_at` | DATE |  |  | excluded |",
+            "| value pattern `ssn` | ssn |",
+        ] {
+            assert!(markdown.contains(row), "{row}\n{markdown}");
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_strategy_arguments() {
+        let config = config::Config::builder()
+            .add_source(config::File::from_str(
+                "rules:\n  bio: truncate(lots)\n",
+                config::FileFormat::Yaml,
+            ))
+            .build()
+            .unwrap()
+            .try_deserialize::<MaskingConfig>();
+
+        assert!(format!("{:#}", config.unwrap_err()).contains("truncate"));
+    }
+
+    #[test]
+    fn rejects_invalid_relationships() {
+        let config: MaskingConfig =
+            config_from_yaml("relationships:\n  - column: email\n    references: users.email\n");
+
+        assert!(Masker::new(&config).is_err());
+    }
+
+    #[test]
+    fn can_mask_with_scripts() {
+        let mut config = config_from_yaml(
+            r#"
+columns: [addresses.postal_code, accounts.number]
+rules:
+  postal_code:
+    script: |
+      if row.country == "NL" { value.sub_string(0, 4) + " XX" } else { value.sub_string(0, 2) + "00" }
+  number:
+    script: |
+      let digits = [];
+      let rest = seed;
+      for i in 0..8 { digits.push(rest % 10); rest /= 10; }
+      let check = digits.reduce(|sum, digit| sum + digit, 0) % 10;
+      digits.reduce(|number, digit| number + digit, "") + check
+"#,
+        );
+        config.masking_key = Some("secret".to_string());
+        let input = "INSERT INTO `addresses` (`id`, `country`, `postal_code`) VALUES (1, 'NL', '1017 CT'), (2, 'BE', '2018');
+INSERT INTO `accounts` (`id`, `number`) VALUES (1, '123456789');
+";
+        let mut output = Vec::new();
+        mask_dump(&config, input.as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.contains("VALUES (1, 'NL', '1017 XX'), (2, 'BE', '2000');"));
+        let number = output
+            .split("VALUES (1, '")
+            .nth(2)
+            .unwrap()
+            .split('\'')
+            .next()
+            .unwrap()
+            .chars()
+            .map(|digit| digit.to_digit(10).unwrap())
+            .collect::<Vec<u32>>();
+        assert_eq!(number.len(), 9);
+        assert_eq!(number[..8].iter().sum::<u32>() % 10, number[8]);
+    }
+
+    #[test]
+    fn can_shift_dates_and_add_noise() {
+        let mut config = config_from_yaml(
+            r#"
+columns: [users.created_at, orders.ordered_at, orders.shipped_at, orders.total]
+rules:
+  created_at: shift_date(days=20, entity=id)
+  ordered_at: shift_date(20, user_id)
+  shipped_at: shift_date(20, user_id)
+  total: noise(5)
+"#,
+        );
+        config.masking_key = Some("secret".to_string());
+        let input = "CREATE TABLE `orders` (
+  `id` int NOT NULL,
+  `user_id` int NOT NULL,
+  `ordered_at` datetime NOT NULL,
+  `shipped_at` date DEFAULT NULL,
+  `total` decimal(6,2) unsigned NOT NULL
+);
+INSERT INTO `users` (`id`, `created_at`) VALUES (7, '2024-01-01');
+INSERT INTO `orders` VALUES (1, 7, '2024-01-10 12:30:00', '2024-01-12', 100.00), (2, 8, '2024-01-10 12:30:00', NULL, 9999.99);
+";
+        let mut output = Vec::new();
+        mask_dump(&config, input.as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        let values = |row: &str| {
+            output
+                .split(row)
+                .nth(1)
+                .unwrap()
+                .split(')')
+                .next()
+                .unwrap()
+                .split(", ")
+                .map(|value| value.trim_matches('\'').to_string())
+                .collect::<Vec<String>>()
+        };
+        let days = |date: &str| fitting::parse_date(&date[..10]).unwrap() / fitting::DAY;
+        let created = values("VALUES (7, ");
+        let first = values("VALUES (1, 7, ");
+        let second = values("(2, 8, ");
+
+        let offset = days(&created[0]) - days("2024-01-01");
+        assert!(offset != 0 && offset.abs() <= 20);
+        assert_eq!(days(&first[0]) - days("2024-01-10"), offset);
+        assert_eq!(days(&first[1]) - days(&first[0]), 2);
+        assert!(first[0].ends_with(" 12:30:00"));
+        assert_eq!(second[1], "NULL");
+
+        let total = first[2].parse::<f64>().unwrap();
+        assert!((95.0..=105.0).contains(&total) && first[2].len() <= 6);
+        assert!(second[2].parse::<f64>().unwrap() <= 9999.99);
+
+        config.rules.insert(
+            "created_at".to_string(),
+            "shift_date(entity=account)".parse().unwrap(),
+        );
+        let error = mask_dump(&config, input.as_bytes(), &mut Vec::new()).unwrap_err();
+        assert!(
+            format!("{error:#}").contains("needs `account`"),
+            "{error:#}"
+        );
+    }
+
+    #[test]
+    fn can_shift_dates_in_updates_with_their_rows() {
+        let mut config = config_from_yaml(
+            r#"
+columns: [created_at, orders.ordered_at]
+rules:
+  created_at: shift_date(20)
+  ordered_at: shift_date(20, user_id)
+"#,
+        );
+        config.masking_key = Some("secret".to_string());
+        let input = "CREATE TABLE `users` (\n  `id` int NOT NULL,\n  `created_at` date NOT NULL\n);
+CREATE TABLE `orders` (\n  `id` int NOT NULL,\n  `user_id` int NOT NULL,\n  `ordered_at` date NOT NULL\n);
+INSERT INTO `users` VALUES (7,'2024-01-01');
+INSERT INTO `orders` VALUES (1,7,'2024-01-10');
+UPDATE `users` SET `created_at` = '2024-03-01' WHERE `id` = 7;
+UPDATE `orders` SET `ordered_at` = '2024-02-01' WHERE `id` = 1 AND `user_id` = 7;
+";
+        let mut output = Vec::new();
+        mask_dump(&config, input.as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        let days = |date: &str| fitting::parse_date(date).unwrap() / fitting::DAY;
+        let values = inserted_values(&output);
+        let updated = output
+            .lines()
+            .filter_map(|line| Some(line.split(" = '").nth(1)?.split('\'').next()?.to_string()))
+            .collect::<Vec<String>>();
+        assert_eq!(
+            days(&updated[0]) - days("2024-03-01"),
+            days(&values["users"][0][1]) - days("2024-01-01")
+        );
+        assert_eq!(
+            days(&updated[1]) - days("2024-02-01"),
+            days(&values["orders"][0][2]) - days("2024-01-10")
+        );
+
+        for (update, error) in [
+            (
+                "UPDATE `users` SET `created_at` = '2024-03-01' WHERE `created_at` < '2024-02-01';",
+                "needs `id` to shift `users`.`created_at` in an UPDATE",
+            ),
+            (
+                "UPDATE `orders` SET `ordered_at` = '2024-02-01' WHERE `id` = 1;",
+                "needs `user_id` to shift `orders`.`ordered_at` in an UPDATE",
+            ),
+            (
+                "UPDATE `admins` SET `created_at` = '2024-03-01' WHERE `id` = 1;",
+                "unknown without the CREATE TABLE of `admins`",
+            ),
+        ] {
+            let input = format!("{input}{update}\n");
+            let err = mask_dump(&config, input.as_bytes(), &mut Vec::new()).unwrap_err();
+            assert!(format!("{err:#}").contains(error), "{err:#}");
+        }
+    }
+
+    #[test]
+    fn can_remap_ids_with_their_references() {
+        let config = config_from_yaml(
+            r#"
+columns: [customers.id]
+relationships:
+  - column: invoices.customer_ref
+    references: customers.id
+rules:
+  id: id_remap()
+"#,
+        );
+        let input = "CREATE TABLE `customers` (
+  `id` int unsigned NOT NULL AUTO_INCREMENT,
+  `name` varchar(20) NOT NULL,
+  PRIMARY KEY (`id`)
+) ENGINE=InnoDB AUTO_INCREMENT=1235 DEFAULT CHARSET=utf8mb4;
+CREATE TABLE `orders` (
+  `id` int NOT NULL AUTO_INCREMENT,
+  `customer_id` int unsigned NOT NULL,
+  PRIMARY KEY (`id`),
+  CONSTRAINT `fk_customer` FOREIGN KEY (`customer_id`) REFERENCES `customers` (`id`)
+) ENGINE=InnoDB AUTO_INCREMENT=3;
+INSERT INTO `customers` VALUES (7, 'Ann'), (1234, 'Bob');
+INSERT INTO `orders` VALUES (1, 1234), (2, 7);
+INSERT INTO `invoices` (`id`, `customer_ref`) VALUES (5, 7);
+";
+        let mut masker = Masker::new(&config).unwrap();
+        masker.prepare(input.as_bytes()).unwrap();
+        let mut output = Vec::new();
+        masker.mask_dump(input.as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.contains(") ENGINE=InnoDB AUTO_INCREMENT=10000 DEFAULT CHARSET=utf8mb4;"));
+        assert!(output.contains("`id` int unsigned NOT NULL AUTO_INCREMENT,"));
+        assert!(output.contains(") ENGINE=InnoDB AUTO_INCREMENT=3;"));
+
+        let key = rules::run_key(None);
+        let int = DataType::Int {
+            m: None,
+            unsigned: true,
+            zerofill: false,
+        };
+        let remap = |id| rules::remap_id(key, id, Some(&int)).unwrap();
+        let (ann, bob) = (remap("7"), remap("1234"));
+        assert_eq!(ann.len(), 1);
+        assert_eq!(bob.len(), 4);
+        assert!(output.contains(&format!("VALUES ({ann}, 'Ann'), ({bob}, 'Bob');")));
+        assert!(output.contains(&format!("VALUES (1, {bob}), (2, {ann});")));
+        assert!(output.contains(&format!("VALUES (5, '{ann}');")));
+    }
+
+    #[test]
+    fn can_mask_values_at_json_paths() {
+        let config = config_from_yaml(
+            r#"
+json_paths:
+  - column: users.profile
+    path: address.street
+    rule: set_null
+  - column: users.*
+    path: $.contacts[*].email
+  - column: users.notes
+    path: $['full name']
+    rule: redact
+"#,
+        );
+        let input = r#"CREATE TABLE `users` (
+  `id` int NOT NULL,
+  `profile` json DEFAULT NULL,
+  `notes` text
+);
+INSERT INTO `users` VALUES (1, '{"name":"Ann O\'Brien","address":{"street":"1 Main St","zip":12345},"contacts":[{"email":"ann@example.com"},{"email":"a@b.c","note":"say \\"hi\\""}]}', '{"full name":"Ann"}'), (2, 'not json', '[1,2]'), (3, NULL, '{"contacts":[]}');
+"#;
+        let mut output = Vec::new();
+        mask_dump(&config, input.as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        let insert = parse_rule::<Insert>(
+            Rule::INSERT_STATEMENT,
+            output.lines().last().unwrap().trim_end_matches(';'),
+        )
+        .unwrap();
+        let rows = insert
+            .values
+            .iter()
+            .map(|row| row.0.iter().map(InsertValue::unescaped).collect())
+            .collect::<Vec<Vec<Option<String>>>>();
+
+        let profile: serde_json::Value =
+            serde_json::from_str(rows[0][1].as_deref().unwrap()).unwrap();
+        assert_eq!(profile["name"], "Ann O'Brien");
+        assert_eq!(profile["address"]["street"], serde_json::Value::Null);
+        assert_eq!(profile["address"]["zip"], 12345);
+        let email = profile["contacts"][0]["email"].as_str().unwrap();
+        assert!(email.contains('@') && email != "ann@example.com");
+        assert_eq!(profile["contacts"][1]["note"], "say \"hi\"");
+        // Members keep their order
+        assert!(rows[0][1].as_deref().unwrap().starts_with(r#"{"name":"#));
+        assert_ne!(rows[0][2].as_deref(), Some(r#"{"full name":"Ann"}"#));
+
+        assert_eq!(rows[1][1].as_deref(), Some("not json"));
+        assert_eq!(rows[1][2].as_deref(), Some("[1,2]"));
+        assert_eq!(rows[2][1], None);
+        assert_eq!(rows[2][2].as_deref(), Some(r#"{"contacts":[]}"#));
+
+        let config =
+            config_from_yaml("json_paths:\n  - column: profile\n    path: a[\n    rule: redact\n");
+        assert!(Masker::new(&config).is_err());
+
+        // Detokenizing only restores whole values
+        let config = config_from_yaml(
+            "json_paths:\n  - column: profile\n    path: ssn\n    rule: tokenize()\n",
+        );
+        let err = Masker::new(&config).err().unwrap();
+        assert!(format!("{err:#}").contains("`tokenize` cannot mask the values at JSON path `ssn`"));
+        let config = config_from_yaml(
+            "json_paths:\n  - column: profile\n    path: ssn\nrules:\n  ssn: tokenize()\n",
+        );
+        let input = "INSERT INTO `users` (`profile`) VALUES ('{\"ssn\":\"123\"}');\n";
+        let err = mask_dump(&config, input.as_bytes(), &mut Vec::new()).unwrap_err();
+        assert!(
+            format!("{err:#}").contains("cannot mask ssn in `users`.`profile`"),
+            "{err:#}"
+        );
+    }
+
+    #[test]
+    fn can_scrub_pii_inside_free_text() {
+        let config = config_from_yaml(
+            r#"
+columns: [tickets.notes, tickets.comment]
+masking_key: secret
+rules:
+  notes: scrub()
+  comment: scrub('email', 'redact')
+"#,
+        );
+        let input = "INSERT INTO `tickets` (`notes`, `comment`) VALUES ('Ann (ann@example.com) called from 0612345678 about it.', 'Ask ann@example.com, 0612345678'), ('Ann (ann@example.com) again', NULL);\n";
+        let mut output = Vec::new();
+        mask_dump(&config, input.as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        let rows = &inserted_values(&output)["tickets"];
+        let notes = &rows[0][0];
+        assert!(notes.starts_with("Ann (") && notes.ends_with(" about it."));
+        assert!(!notes.contains("ann@example.com") && !notes.contains("0612345678"));
+        assert_eq!(rows[0][1], "Ask [EMAIL], 0612345678");
+        // The same email gets the same fake
+        let email = Builtin::Email.find_in(notes)[0].clone();
+        assert_eq!(rows[1][0], format!("Ann ({}) again", &notes[email]));
+    }
+
+    #[test]
+    fn can_mask_with_templates() {
+        let config = config_from_yaml(
+            r#"
+columns: [users.first_name, users.last_name, users.email, users.login]
+rules:
+  email: "{first_name}.{last_name}@example.test"
+  login: "template('{original.login}-{ID}')"
+  first_name: name::first
+  last_name: name::last
+"#,
+        );
+        let input = "INSERT INTO `users` (`id`, `email`, `login`, `first_name`, `last_name`) VALUES (7, 'john@doe.com', 'jd', 'John', 'Doe'), (8, 'x@y.z', 'x', NULL, 'Roe');\n";
+        let mut output = Vec::new();
+        mask_dump(&config, input.as_bytes(), &mut output).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        let insert = parse_rule::<Insert>(
+            Rule::INSERT_STATEMENT,
+            output.trim_end().trim_end_matches(';'),
+        )
+        .unwrap();
+        let rows = insert
+            .values
+            .iter()
+            .map(|row| row.0.iter().map(InsertValue::unescaped).collect())
+            .collect::<Vec<Vec<Option<String>>>>();
+
+        let first_name = rows[0][3].as_deref().unwrap();
+        let last_name = rows[0][4].as_deref().unwrap();
+        assert_ne!(first_name, "John");
+        assert_eq!(
+            rows[0][1].as_deref().unwrap(),
+            format!("{first_name}.{last_name}@example.test")
+        );
+        assert_eq!(rows[0][2].as_deref(), Some("jd-7"));
+        assert_eq!(rows[1][3], None);
+        assert_eq!(
+            rows[1][1].as_deref().unwrap(),
+            format!(".{}@example.test", rows[1][4].as_deref().unwrap())
+        );
+
+        let config = config_from_yaml("columns: [a, b]\nrules:\n  a: \"{b}\"\n  b: \"x{a}\"\n");
+        let err = mask_dump(
+            &config,
+            "INSERT INTO `t` (`a`, `b`) VALUES ('1', '2');\n".as_bytes(),
+            &mut Vec::new(),
+        )
+        .unwrap_err();
+        assert!(format!("{err:#}").contains("refer to each other"));
+    }
+
+    #[test]
+    fn can_mask_conditionally() {
+        let config = config_from_yaml(
+            r#"
+columns: [users.email, users.notes]
+rules:
+  email:
+    rule: redact
+    when: role != 'system'
+  notes:
+    rule: set_null
+    when: country = 'DE'
+"#,
+        );
+        let input = "INSERT INTO `users` (`email`, `role`, `country`, `notes`) VALUES ('john@example.com', 'admin', 'DE', 'likes cats'), ('root@example.com', 'system', 'NL', 'hi');
+INSERT INTO `users` (`email`, `notes`) VALUES ('jane@example.com', 'x');
+";
+        let mut output = Vec::new();
+        mask_dump(&config, input.as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.contains(
+            "VALUES ('j***@***.com', 'admin', 'DE', NULL), ('root@example.com', 'system', 'NL', 'hi');"
+        ));
+        // Without the columns to tell, rows are masked
+        assert!(output.contains("VALUES ('j***@***.com', NULL);"));
+
+        let config = config::Config::builder()
+            .add_source(config::File::from_str(
+                "rules:\n  email:\n    rule: redact\n    when: role ==\n",
+                config::FileFormat::Yaml,
+            ))
+            .build()
+            .unwrap()
+            .try_deserialize::<MaskingConfig>();
+        assert!(format!("{:#}", config.unwrap_err()).contains("invalid `when`"));
+    }
+
+    #[test]
+    fn fails_on_script_errors() {
+        let config =
+            config_from_yaml("columns: [email]\nrules:\n  email:\n    script: value.foo()\n");
+        let mut output = Vec::new();
+
+        let err = mask_dump(
+            &config,
+            "INSERT INTO `users` (`email`) VALUES ('a@b.c');\n".as_bytes(),
+            &mut output,
+        )
+        .unwrap_err();
+        assert!(format!("{err:#}").contains("masking rule `script` failed"));
+    }
+
+    fn config_from_yaml(yaml: &str) -> MaskingConfig {
+        config::Config::builder()
+            .add_source(config::File::from_str(yaml, config::FileFormat::Yaml))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap()
+    }
+}
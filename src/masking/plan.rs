@@ -0,0 +1,250 @@
+use std::{
+    fmt::{Display, Formatter, Result as FmtResult},
+    ops::Deref,
+    rc::Rc,
+};
+
+use crate::{
+    parser::types::Column,
+    rules::{CellContext, Strategy, Template},
+    settings::MaskingRule,
+    ExtractResult,
+};
+
+use super::{ColumnRef, JsonPath};
+
+/// How a column came to be masked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Selection {
+    /// A `columns` selector matched it.
+    Selector(String),
+    /// A `patterns` regex matched its name.
+    Pattern(String),
+    /// A column-scoped value pattern flagged its values.
+    Detected(String),
+}
+
+impl Display for Selection {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Selector(selector) => write!(f, "column `{selector}`"),
+            Self::Pattern(regex) => write!(f, "pattern `{regex}`"),
+            Self::Detected(name) => write!(f, "value pattern `{name}`"),
+        }
+    }
+}
+
+/// A rule from the config, or the faker named after a column that has no
+/// rule.
+#[derive(Debug)]
+pub enum PlannedRule<'a> {
+    Configured(&'a MaskingRule),
+    Fallback(MaskingRule),
+}
+
+impl Deref for PlannedRule<'_> {
+    type Target = MaskingRule;
+
+    fn deref(&self) -> &MaskingRule {
+        match self {
+            Self::Configured(rule) => rule,
+            Self::Fallback(rule) => rule,
+        }
+    }
+}
+
+/// Everything needed to mask the values of a column, resolved once from
+/// the config and the schema.
+#[derive(Debug)]
+pub struct ColumnMasker<'a> {
+    /// The column of the group that was selected, which is the masked
+    /// column itself unless it is only linked to it.
+    pub selected: ColumnRef,
+    pub selection: Selection,
+    pub rule: PlannedRule<'a>,
+    /// The root of the group of linked columns, which share replacements.
+    pub root: ColumnRef,
+    /// Masked with `shuffle`, which needs the other values of the column.
+    pub shuffle: bool,
+    /// Part of a unique index, or linked to a column that is.
+    pub unique: bool,
+    /// Replacements have to be remembered, as the column is linked to
+    /// others or its values are looked up by `UPDATE`s. Without a prepared
+    /// dump that is not known in advance, so all of them are.
+    pub memoized: bool,
+    /// The rule always gives the same replacement for the same value.
+    pub deterministic: bool,
+    /// Masked with a rule that looks at the rest of the row.
+    pub reads_row: bool,
+    masking_key: Option<&'a str>,
+}
+
+impl<'a> ColumnMasker<'a> {
+    pub(super) fn new(
+        selected: ColumnRef,
+        selection: Selection,
+        rule: PlannedRule<'a>,
+        root: ColumnRef,
+        masking_key: Option<&'a str>,
+    ) -> Self {
+        Self {
+            selected,
+            selection,
+            shuffle: rule.strategy() == Some(&Strategy::Shuffle),
+            deterministic: rule.is_deterministic(masking_key),
+            reads_row: rule.reads_row(),
+            rule,
+            root,
+            unique: false,
+            memoized: false,
+            masking_key,
+        }
+    }
+
+    /// The template of the rule, when it is one.
+    pub fn template(&self) -> Option<&Template> {
+        match self.rule.strategy() {
+            Some(Strategy::Template(template)) => Some(template),
+            _ => None,
+        }
+    }
+
+    /// A replacement for `original`, or `None` when it becomes `NULL`.
+    pub fn mask(&self, original: &str, context: &CellContext) -> ExtractResult<Option<String>> {
+        self.rule.apply_in(original, self.masking_key, context)
+    }
+}
+
+/// A path inside the JSON documents of a column, with the rule masking the
+/// values at it.
+#[derive(Debug)]
+pub struct JsonPathMasker<'a> {
+    pub path: JsonPath,
+    pub rule: PlannedRule<'a>,
+}
+
+/// A column of a [`TablePlan`].
+#[derive(Debug)]
+pub struct ColumnPlan<'a> {
+    pub column: ColumnRef,
+    /// The definition from the `CREATE TABLE` of the table, if any.
+    pub definition: Option<Column>,
+    /// Matched by an `exclude` selector, so never masked.
+    pub excluded: bool,
+    /// `None` when the values of the column are kept, apart from cells
+    /// matching a cell-scoped value pattern.
+    pub masker: Option<ColumnMasker<'a>>,
+    /// The values masked inside the JSON documents of the column, when
+    /// `masker` keeps them.
+    pub json_paths: Vec<JsonPathMasker<'a>>,
+}
+
+/// The columns of a table in the order of its `CREATE TABLE`, followed by
+/// the ones only named by statements.
+#[derive(Debug)]
+pub struct TablePlan<'a> {
+    pub database: Option<String>,
+    pub table: String,
+    pub columns: Vec<ColumnPlan<'a>>,
+}
+
+impl TablePlan<'_> {
+    /// The position of the column called `name`.
+    pub fn index(&self, name: &str) -> Option<usize> {
+        let name = name.trim_matches('`').to_lowercase();
+        self.columns
+            .iter()
+            .position(|column| column.column.column == name)
+    }
+}
+
+/// How each column of a dump is masked, compiled from a masking config and
+/// the schema of the dump by [`super::Masker::plan`].
+#[derive(Debug)]
+pub struct MaskingPlan<'a> {
+    /// Ordered by database and table.
+    pub tables: Vec<Rc<TablePlan<'a>>>,
+    /// The cell-scoped value patterns, with the rule masking their matches.
+    pub cells: Vec<(String, String)>,
+}
+
+impl MaskingPlan<'_> {
+    pub fn to_markdown(&self) -> String {
+        let mut markdown =
+            String::from("| Column | Type | Rule | Selected by | Notes |\n|---|---|---|---|---|\n");
+
+        for column in self.tables.iter().flat_map(|table| table.columns.iter()) {
+            let data_type = column
+                .definition
+                .as_ref()
+                .map(|definition| definition.data_type.to_string());
+            let (rule, selection, notes) = match column.masker {
+                Some(ref masker) => (
+                    masker.rule.name().to_string(),
+                    masker.selection.to_string(),
+                    notes(&column.column, masker),
+                ),
+                None if column.excluded => (String::new(), String::new(), "excluded".to_string()),
+                None if !column.json_paths.is_empty() => (
+                    column
+                        .json_paths
+                        .iter()
+                        .map(|json_path| format!("{}: {}", json_path.path, json_path.rule.name()))
+                        .collect::<Vec<String>>()
+                        .join(", "),
+                    "JSON paths".to_string(),
+                    String::new(),
+                ),
+                None => Default::default(),
+            };
+            markdown.push_str(&format!(
+                "| {} | {} | {rule} | {selection} | {notes} |\n",
+                column.column,
+                data_type.as_deref().unwrap_or("?"),
+            ));
+        }
+
+        if !self.cells.is_empty() {
+            markdown.push_str("\n| Cells matching | Rule |\n|---|---|\n");
+            for (name, rule) in self.cells.iter() {
+                markdown.push_str(&format!("| value pattern `{name}` | {rule} |\n"));
+            }
+        }
+
+        markdown
+    }
+}
+
+fn notes(column: &ColumnRef, masker: &ColumnMasker) -> String {
+    let mut notes = Vec::new();
+    if masker.selected != *column {
+        notes.push(format!("linked to {}", masker.selected));
+    }
+    if masker.shuffle {
+        notes.push("shuffled".to_string());
+    }
+    if masker.unique {
+        notes.push("unique".to_string());
+    }
+    if let Some(ref when) = masker.rule.when {
+        notes.push(format!("when `{when}`"));
+    }
+    if let Some(template) = masker.template() {
+        let columns = template.masked_columns().collect::<Vec<&str>>();
+        if !columns.is_empty() {
+            notes.push(format!("after {}", columns.join(", ")));
+        }
+    }
+    if !masker.shuffle {
+        notes.push(
+            match (masker.deterministic, masker.memoized) {
+                (true, _) => "deterministic",
+                (false, true) => "memoized",
+                (false, false) => "random",
+            }
+            .to_string(),
+        );
+    }
+
+    notes.join(", ")
+}
@@ -0,0 +1,342 @@
+use std::{
+    cmp::Ordering,
+    fmt::{Display, Formatter, Result as FmtResult},
+};
+
+use anyhow::Context;
+use pest::Parser;
+
+use crate::{
+    parser::{
+        parse_utils::{strip_quotes, unescape_str},
+        types::Where,
+        MySqlParser, Rule,
+    },
+    ExtractResult,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Equals,
+    NotEquals,
+    LessThan,
+    LessThanEquals,
+    GreaterThan,
+    GreaterThanEquals,
+    Like,
+    In,
+    IsNull,
+    IsNotNull,
+}
+
+impl Operator {
+    fn parse(operator: &str) -> Self {
+        let operator = operator
+            .split_whitespace()
+            .collect::<Vec<&str>>()
+            .join(" ")
+            .to_uppercase();
+        match operator.as_str() {
+            "=" => Self::Equals,
+            "!=" | "<>" => Self::NotEquals,
+            "<" => Self::LessThan,
+            "<=" => Self::LessThanEquals,
+            ">" => Self::GreaterThan,
+            ">=" => Self::GreaterThanEquals,
+            "LIKE" => Self::Like,
+            "IN" => Self::In,
+            "IS NULL" => Self::IsNull,
+            "IS NOT NULL" => Self::IsNotNull,
+            _ => unreachable!("`{operator}` is not a COMPARISON_OPERATOR"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Operand {
+    /// Literals, `None` for `NULL`. Only `IN` takes more than one.
+    Values(Vec<Option<String>>),
+    /// Another column of the row.
+    Column(String),
+}
+
+#[derive(Debug, Clone)]
+struct Condition {
+    /// Joined to the previous condition with `OR` rather than `AND`.
+    or: bool,
+    column: String,
+    operator: Operator,
+    operand: Operand,
+}
+
+/// A condition on the values of a row, written like a `WHERE` clause
+/// without the `WHERE`, e.g. `role != 'system' AND country IN ('DE', 'AT')`.
+///
+/// `AND` binds tighter than `OR`. As in SQL, comparisons with `NULL` are
+/// false, so only `IS NULL` matches it. Numbers compare as numbers, and text
+/// ignores case and trailing spaces like the default collation of MySQL.
+#[derive(Debug, Clone)]
+pub struct Predicate {
+    predicate: String,
+    conditions: Vec<Condition>,
+}
+
+impl Predicate {
+    pub fn parse(predicate: &str) -> ExtractResult<Self> {
+        let clause = format!("WHERE {}", predicate.trim());
+        let pair = MySqlParser::parse(Rule::WHERE_CLAUSE, &clause)
+            .ok()
+            .and_then(|mut pairs| pairs.next())
+            .filter(|pair| pair.as_span().end() == clause.len())
+            .with_context(|| format!("unable to parse condition `{}`", predicate.trim()))?;
+
+        let conditions = Where::conditions(pair)
+            .into_iter()
+            .map(|condition| {
+                Ok(Condition {
+                    or: condition
+                        .connector
+                        .is_some_and(|connector| connector.eq_ignore_ascii_case("OR")),
+                    column: condition.column.trim_matches('`').to_string(),
+                    operator: Operator::parse(&condition.operator),
+                    operand: operand(&condition.value)?,
+                })
+            })
+            .collect::<ExtractResult<Vec<Condition>>>()?;
+
+        Ok(Self {
+            predicate: predicate.trim().to_string(),
+            conditions,
+        })
+    }
+
+    /// The columns the predicate looks at.
+    pub fn columns(&self) -> impl Iterator<Item = &str> {
+        self.conditions.iter().flat_map(|condition| {
+            let operand = match condition.operand {
+                Operand::Column(ref column) => Some(column.as_str()),
+                Operand::Values(_) => None,
+            };
+            std::iter::once(condition.column.as_str()).chain(operand)
+        })
+    }
+
+    /// Whether `row`, given as values by column name, matches the
+    /// predicate, or `None` when it lacks a column the predicate looks at.
+    pub fn matches(&self, row: &[(String, Option<String>)]) -> Option<bool> {
+        let value = |column: &str| {
+            row.iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(column))
+                .map(|(_, value)| value.as_deref())
+        };
+
+        let mut matched = false;
+        let mut group = true;
+        for condition in self.conditions.iter() {
+            if condition.or {
+                matched |= group;
+                group = true;
+            }
+
+            let left = value(&condition.column)?;
+            let right = match condition.operand {
+                Operand::Values(ref values) => values.iter().map(Option::as_deref).collect(),
+                Operand::Column(ref column) => vec![value(column)?],
+            };
+            group &= compare(left, condition.operator, &right);
+        }
+
+        Some(matched || group)
+    }
+}
+
+impl Display for Predicate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}", self.predicate)
+    }
+}
+
+/// The operand of a condition, as written after its operator.
+fn operand(value: &str) -> ExtractResult<Operand> {
+    let literal = |value: &str| match value {
+        "NULL" => None,
+        value if value.starts_with('\'') => Some(unescape_str(strip_quotes(value))),
+        value => Some(value.to_string()),
+    };
+
+    Ok(match value {
+        "" => Operand::Values(Vec::new()),
+        "NULL" => Operand::Values(vec![None]),
+        value if value.starts_with('(') => {
+            let list = MySqlParser::parse(Rule::VALUE_LIST, value)
+                .with_context(|| format!("invalid list `{value}`"))?
+                .next()
+                .unwrap();
+            Operand::Values(
+                list.into_inner()
+                    .map(|pair| literal(pair.as_str()))
+                    .collect(),
+            )
+        }
+        value if value.eq_ignore_ascii_case("true") => Operand::Values(vec![Some("1".into())]),
+        value if value.eq_ignore_ascii_case("false") => Operand::Values(vec![Some("0".into())]),
+        value if value.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_' || c == '`') => {
+            Operand::Column(value.trim_matches('`').to_string())
+        }
+        value => Operand::Values(vec![literal(value)]),
+    })
+}
+
+fn compare(left: Option<&str>, operator: Operator, right: &[Option<&str>]) -> bool {
+    let ordering = |right: Option<&str>| match (left, right) {
+        (Some(left), Some(right)) => Some(order(left, right)),
+        _ => None,
+    };
+    let first = right.first().copied().flatten();
+
+    match operator {
+        Operator::IsNull => left.is_none(),
+        Operator::IsNotNull => left.is_some(),
+        Operator::Equals => ordering(first) == Some(Ordering::Equal),
+        Operator::NotEquals => ordering(first).is_some_and(Ordering::is_ne),
+        Operator::LessThan => ordering(first) == Some(Ordering::Less),
+        Operator::LessThanEquals => ordering(first).is_some_and(Ordering::is_le),
+        Operator::GreaterThan => ordering(first) == Some(Ordering::Greater),
+        Operator::GreaterThanEquals => ordering(first).is_some_and(Ordering::is_ge),
+        Operator::Like => left
+            .zip(first)
+            .is_some_and(|(left, pattern)| like(left, pattern)),
+        Operator::In => right
+            .iter()
+            .any(|&right| ordering(right) == Some(Ordering::Equal)),
+    }
+}
+
+fn order(left: &str, right: &str) -> Ordering {
+    match (left.trim().parse::<f64>(), right.trim().parse::<f64>()) {
+        (Ok(left), Ok(right)) => left.partial_cmp(&right).unwrap_or(Ordering::Equal),
+        _ => left
+            .trim_end()
+            .to_lowercase()
+            .cmp(&right.trim_end().to_lowercase()),
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum LikeToken {
+    AnyText,
+    AnyChar,
+    Char(char),
+}
+
+/// Match a `LIKE` pattern, where `%` is any text, `_` any character and
+/// `\` escapes either, ignoring case.
+fn like(value: &str, pattern: &str) -> bool {
+    let pattern = pattern.to_lowercase();
+    let mut tokens = Vec::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        tokens.push(match c {
+            '\\' => LikeToken::Char(chars.next().unwrap_or('\\')),
+            '%' => LikeToken::AnyText,
+            '_' => LikeToken::AnyChar,
+            c => LikeToken::Char(c),
+        });
+    }
+
+    // Whether the value so far matches the first `n` tokens, by `n`
+    let mut matched = vec![false; tokens.len() + 1];
+    matched[0] = true;
+    for (n, token) in tokens.iter().enumerate() {
+        matched[n + 1] = matched[n] && *token == LikeToken::AnyText;
+    }
+    for c in value.to_lowercase().chars() {
+        let mut next = vec![false; tokens.len() + 1];
+        for (n, token) in tokens.iter().enumerate() {
+            next[n + 1] = match *token {
+                LikeToken::AnyText => next[n] || matched[n + 1],
+                LikeToken::AnyChar => matched[n],
+                LikeToken::Char(expected) => matched[n] && expected == c,
+            };
+        }
+        matched = next;
+    }
+
+    matched[tokens.len()]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn row(values: &[(&str, Option<&str>)]) -> Vec<(String, Option<String>)> {
+        values
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.map(str::to_string)))
+            .collect()
+    }
+
+    #[test]
+    fn can_match_rows() {
+        let row = row(&[
+            ("role", Some("System ")),
+            ("country", Some("DE")),
+            ("age", Some("42")),
+            ("notes", None),
+            ("billing_country", Some("de")),
+        ]);
+
+        let cases = [
+            ("role = 'system'", true),
+            ("role != 'system'", false),
+            ("role <> 'admin'", true),
+            ("age > 9", true),
+            ("age <= 41.5", false),
+            ("country IN ('AT', 'DE')", true),
+            ("country in ('AT')", false),
+            ("role LIKE 'sys%'", true),
+            ("role LIKE 's_stem'", false),
+            ("notes IS NULL", true),
+            ("notes is not null", false),
+            ("notes = NULL", false),
+            ("notes != 'x'", false),
+            ("country = billing_country", true),
+            ("`country` = 'DE' AND role = 'admin'", false),
+            ("role = 'admin' AND age > 1 OR country = 'DE'", true),
+            ("country = 'DE' OR role = 'admin' AND age > 100", true),
+            ("country = 'FR' OR role = 'admin' AND age > 1", false),
+        ];
+        for (predicate, expected) in cases {
+            let parsed = Predicate::parse(predicate).unwrap();
+            assert_eq!(parsed.matches(&row), Some(expected), "{predicate}");
+        }
+
+        let predicate = Predicate::parse("missing = 1 OR country = 'DE'").unwrap();
+        assert_eq!(predicate.matches(&row), None);
+        assert_eq!(
+            predicate.columns().collect::<Vec<&str>>(),
+            vec!["missing", "country"]
+        );
+
+        for invalid in ["", "role", "role = ", "role = 'a' junk", "role IN ()"] {
+            assert!(Predicate::parse(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn can_match_like_patterns() {
+        for (value, pattern, expected) in [
+            ("hello", "h%o", true),
+            ("hello", "%", true),
+            ("", "%", true),
+            ("hello", "h_llo", true),
+            ("hello", "h_lo", false),
+            ("50%", "50\\%", true),
+            ("500", "50\\%", false),
+            ("a_b", "a\\_b", true),
+            ("HeLLo", "%ll%", true),
+        ] {
+            assert_eq!(like(value, pattern), expected, "{value} LIKE {pattern}");
+        }
+    }
+}
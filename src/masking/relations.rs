@@ -0,0 +1,153 @@
+use std::{
+    collections::BTreeMap,
+    fmt::{Display, Formatter, Result as FmtResult},
+};
+
+use anyhow::bail;
+
+use crate::ExtractResult;
+
+/// A column of a table, optionally qualified by its database.
+///
+/// Column names are case-insensitive in MySQL and are stored lowercased.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ColumnRef {
+    pub database: Option<String>,
+    pub table: String,
+    pub column: String,
+}
+
+impl ColumnRef {
+    pub fn new(database: Option<&str>, table: &str, column: &str) -> Self {
+        Self {
+            database: database.map(str::to_string),
+            table: table.to_string(),
+            column: column.to_lowercase(),
+        }
+    }
+
+    /// Parse `table.column` or `database.table.column`, with or without
+    /// backticks around each part.
+    pub fn parse(value: &str) -> ExtractResult<Self> {
+        let parts = value
+            .split('.')
+            .map(|part| part.trim().trim_matches('`'))
+            .collect::<Vec<&str>>();
+
+        match parts.as_slice() {
+            [table, column] if !table.is_empty() && !column.is_empty() => {
+                Ok(Self::new(None, table, column))
+            }
+            [database, table, column]
+                if !database.is_empty() && !table.is_empty() && !column.is_empty() =>
+            {
+                Ok(Self::new(Some(database), table, column))
+            }
+            _ => bail!("`{value}` is not a `table.column` or `database.table.column` reference"),
+        }
+    }
+
+    /// This column in `database` when it is not qualified by one already.
+    pub fn in_database(&self, database: Option<&str>) -> Self {
+        match self.database {
+            Some(_) => self.clone(),
+            None => Self {
+                database: database.map(str::to_string),
+                ..self.clone()
+            },
+        }
+    }
+}
+
+impl Display for ColumnRef {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        if let Some(ref database) = self.database {
+            write!(f, "`{database}`.")?;
+        }
+        write!(f, "`{}`.`{}`", self.table, self.column)
+    }
+}
+
+/// Groups of columns that hold the same values, built from foreign keys and
+/// from relationships declared in the masking config.
+///
+/// Each group has a root, which is the column that the others end up
+/// referencing.
+#[derive(Debug, Default)]
+pub struct Relations {
+    parents: BTreeMap<ColumnRef, ColumnRef>,
+}
+
+impl Relations {
+    /// Record that `column` holds values of `references`.
+    pub fn link(&mut self, column: ColumnRef, references: ColumnRef) {
+        let child = self.root(&column);
+        let parent = self.root(&references);
+
+        if child != parent {
+            self.parents.insert(child, parent);
+        }
+    }
+
+    /// The column at the root of the group of `column`.
+    pub fn root(&self, column: &ColumnRef) -> ColumnRef {
+        let mut root = column;
+        while let Some(parent) = self.parents.get(root) {
+            root = parent;
+        }
+        root.clone()
+    }
+
+    /// Every column in the group of `column`, root first and the rest in
+    /// sorted order.
+    pub fn group(&self, column: &ColumnRef) -> Vec<ColumnRef> {
+        let root = self.root(column);
+        let mut group = vec![root.clone()];
+        group.extend(
+            self.parents
+                .keys()
+                .filter(|member| self.root(member) == root)
+                .cloned(),
+        );
+        group
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn can_parse_column_refs() {
+        assert_eq!(
+            ColumnRef::parse("users.Email").unwrap(),
+            ColumnRef::new(None, "users", "email")
+        );
+        assert_eq!(
+            ColumnRef::parse("`app`.`users`.`email`").unwrap(),
+            ColumnRef::new(Some("app"), "users", "email")
+        );
+        assert!(ColumnRef::parse("email").is_err());
+        assert!(ColumnRef::parse("users.").is_err());
+    }
+
+    #[test]
+    fn can_group_linked_columns() {
+        let users = ColumnRef::new(None, "users", "email");
+        let orders = ColumnRef::new(None, "orders", "customer_email");
+        let invoices = ColumnRef::new(None, "invoices", "email");
+        let other = ColumnRef::new(None, "other", "email");
+
+        let mut relations = Relations::default();
+        relations.link(invoices.clone(), orders.clone());
+        relations.link(orders.clone(), users.clone());
+        relations.link(orders.clone(), users.clone());
+
+        assert_eq!(relations.root(&invoices), users);
+        assert_eq!(
+            relations.group(&orders),
+            vec![users.clone(), invoices.clone(), orders.clone()]
+        );
+        assert_eq!(relations.group(&other), vec![other]);
+    }
+}
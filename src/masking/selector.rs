@@ -0,0 +1,197 @@
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+use anyhow::{bail, Context};
+use regex::Regex;
+
+use crate::ExtractResult;
+
+use super::ColumnRef;
+
+/// One part of a [`ColumnSelector`].
+#[derive(Debug, Clone)]
+enum NamePattern {
+    Any,
+    Exact(String),
+    /// A glob with `*` and `?`, or a regex written between slashes.
+    Regex(Regex),
+}
+
+impl NamePattern {
+    fn parse(part: &str) -> ExtractResult<Self> {
+        if let Some(regex) = part
+            .strip_prefix('/')
+            .and_then(|part| part.strip_suffix('/'))
+        {
+            return Ok(Self::Regex(
+                Regex::new(&format!("(?i){regex}"))
+                    .with_context(|| format!("invalid regex `{regex}`"))?,
+            ));
+        }
+
+        let part = part.trim_matches('`');
+        match part {
+            "" => bail!("empty name"),
+            "*" => Ok(Self::Any),
+            part if part.contains(['*', '?']) => {
+                let regex = part
+                    .chars()
+                    .map(|c| match c {
+                        '*' => ".*".to_string(),
+                        '?' => ".".to_string(),
+                        c => regex::escape(&c.to_string()),
+                    })
+                    .collect::<String>();
+                Ok(Self::Regex(Regex::new(&format!("(?i)^{regex}$")).unwrap()))
+            }
+            part => Ok(Self::Exact(part.to_lowercase())),
+        }
+    }
+
+    fn matches(&self, name: Option<&str>) -> bool {
+        match (self, name) {
+            (Self::Any, _) => true,
+            (_, None) => false,
+            (Self::Exact(exact), Some(name)) => name.to_lowercase() == *exact,
+            (Self::Regex(regex), Some(name)) => regex.is_match(name),
+        }
+    }
+}
+
+/// Selects columns by `column`, `table.column` or `database.table.column`,
+/// where each part is a name, a glob such as `*phone*`, or a regex between
+/// slashes such as `/^crm_[0-9]+$/`. Names are compared case-insensitively.
+///
+/// Parts that are left out match any table or database.
+#[derive(Debug, Clone)]
+pub struct ColumnSelector {
+    selector: String,
+    database: NamePattern,
+    table: NamePattern,
+    column: NamePattern,
+}
+
+impl ColumnSelector {
+    pub fn parse(selector: &str) -> ExtractResult<Self> {
+        let parts = split_parts(selector.trim())
+            .iter()
+            .map(|part| NamePattern::parse(part))
+            .collect::<ExtractResult<Vec<NamePattern>>>()
+            .with_context(|| format!("invalid column selector `{selector}`"))?;
+
+        let count = parts.len();
+        let mut parts = parts.into_iter();
+        let mut next = || parts.next().unwrap();
+        let (database, table, column) = match count {
+            1 => (NamePattern::Any, NamePattern::Any, next()),
+            2 => (NamePattern::Any, next(), next()),
+            3 => (next(), next(), next()),
+            _ => bail!(
+                "`{selector}` is not a `column`, `table.column` or `database.table.column` selector"
+            ),
+        };
+
+        Ok(Self {
+            selector: selector.to_string(),
+            database,
+            table,
+            column,
+        })
+    }
+
+    pub fn matches(&self, column: &ColumnRef) -> bool {
+        self.column.matches(Some(&column.column))
+            && self.table.matches(Some(&column.table))
+            && self.database.matches(column.database.as_deref())
+    }
+
+    /// Whether the selector matches columns of `table` in `database`.
+    pub fn matches_table(&self, database: Option<&str>, table: &str) -> bool {
+        self.table.matches(Some(table)) && self.database.matches(database)
+    }
+
+    /// Whether the selector only names a column, so it applies in every
+    /// table.
+    pub fn is_unscoped(&self) -> bool {
+        matches!(self.database, NamePattern::Any) && matches!(self.table, NamePattern::Any)
+    }
+}
+
+impl Display for ColumnSelector {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}", self.selector)
+    }
+}
+
+/// Split a selector on the dots outside of backticks and regexes.
+fn split_parts(selector: &str) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut quote = None;
+    let mut chars = selector.chars();
+
+    while let Some(c) = chars.next() {
+        let part = parts.last_mut().unwrap();
+        match (c, quote) {
+            ('.', None) => parts.push(String::new()),
+            ('\\', Some('/')) => {
+                part.push(c);
+                part.extend(chars.next());
+            }
+            ('/' | '`', None) if part.is_empty() => {
+                quote = Some(c);
+                part.push(c);
+            }
+            (c, Some(open)) if c == open => {
+                quote = None;
+                part.push(c);
+            }
+            (c, _) => part.push(c),
+        }
+    }
+
+    parts
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn can_select_columns() {
+        let users_email = ColumnRef::new(Some("app"), "users", "Email");
+        let config_name = ColumnRef::new(None, "config", "name");
+        let crm_phone = ColumnRef::new(Some("crm_eu"), "contacts", "mobile_phone");
+
+        let cases = [
+            ("email", vec![true, false, false]),
+            ("*.users.email", vec![true, false, false]),
+            ("users.*", vec![true, false, false]),
+            ("*.name", vec![false, true, false]),
+            ("app.*.*", vec![true, false, false]),
+            ("crm_*.contacts.*phone*", vec![false, false, true]),
+            (
+                "/^crm_[a-z]+$/.`contacts`./phone$/",
+                vec![false, false, true],
+            ),
+            ("*.*.*", vec![true, true, true]),
+            ("`app`.users.e?ail", vec![true, false, false]),
+        ];
+
+        for (selector, expected) in cases {
+            let selector = ColumnSelector::parse(selector).unwrap();
+            let matched = [&users_email, &config_name, &crm_phone]
+                .iter()
+                .map(|column| selector.matches(column))
+                .collect::<Vec<bool>>();
+            assert_eq!(matched, expected, "{selector}");
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_selectors() {
+        for selector in ["a.b.c.d", "users.", "/(/.email", ""] {
+            assert!(ColumnSelector::parse(selector).is_err(), "{selector}");
+        }
+        assert!(ColumnSelector::parse("email").unwrap().is_unscoped());
+        assert!(!ColumnSelector::parse("users.email").unwrap().is_unscoped());
+    }
+}
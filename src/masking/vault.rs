@@ -0,0 +1,461 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    io::{Read, Write},
+    path::Path,
+};
+
+use aes_gcm::{
+    aead::{Aead, Payload},
+    Aes256Gcm, Nonce,
+};
+use anyhow::{anyhow, bail, Context};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::{
+    dump::StatementReader,
+    parser::{
+        parse_rule,
+        parse_utils::escape_str,
+        statements::{CreateTable, Insert, UseDatabase},
+        types::{AssignmentValue, DataType, InsertValue, Update},
+        Rule,
+    },
+    rules, ExtractResult,
+};
+
+use super::{fitting, update_row, ColumnRef, ColumnSelector, Predicate};
+
+type HmacSha256 = Hmac<Sha256>;
+
+const MAGIC: &[u8] = b"SQLEXVAULT\x02";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+/// Hex digits of a token after its `tok_` prefix.
+const TOKEN_DIGITS: usize = 16;
+
+/// The keys of a vault, derived from its passphrase and salt.
+struct VaultKeys {
+    encryption: [u8; 32],
+    tokens: [u8; 32],
+}
+
+impl VaultKeys {
+    fn derive(passphrase: &str, salt: &[u8]) -> Self {
+        let keys = rules::derive_key::<64>(passphrase, salt);
+        let (encryption, tokens) = keys.split_at(32);
+        Self {
+            encryption: encryption.try_into().unwrap(),
+            tokens: tokens.try_into().unwrap(),
+        }
+    }
+}
+
+/// The originals of the values replaced by `tokenize`, by token.
+///
+/// Vault files are encrypted with AES-256-GCM, under a key derived from the
+/// passphrase with PBKDF2-HMAC-SHA256. Tokens are derived from the value and
+/// the passphrase, so a value gets the same token every time it is
+/// tokenized into the same vault.
+pub struct TokenVault {
+    salt: [u8; SALT_LEN],
+    keys: VaultKeys,
+    tokens: BTreeMap<String, String>,
+}
+
+impl TokenVault {
+    /// An empty vault.
+    pub fn new(passphrase: &str) -> ExtractResult<Self> {
+        let salt = random_bytes()?;
+        Ok(Self {
+            keys: VaultKeys::derive(passphrase, &salt),
+            salt,
+            tokens: BTreeMap::new(),
+        })
+    }
+
+    /// The vault at `path`, or an empty one when there is no file yet.
+    pub fn open(path: &str, passphrase: &str) -> ExtractResult<Self> {
+        if !Path::new(path).exists() {
+            return Self::new(passphrase);
+        }
+        let bytes = fs::read(path).with_context(|| format!("unable to read vault `{path}`"))?;
+        Self::decrypt(&bytes, passphrase).with_context(|| format!("unable to open vault `{path}`"))
+    }
+
+    /// Write the vault to `path`, through a file next to it that replaces
+    /// it once written, so a vault is never left half written.
+    pub fn save(&self, path: &str) -> ExtractResult<()> {
+        let bytes = self.encrypt()?;
+        let temporary = format!("{path}.{}.tmp", hex(&random_bytes::<8>()?));
+        let written = fs::File::create(&temporary)
+            .and_then(|mut file| {
+                file.write_all(&bytes)?;
+                file.sync_all()
+            })
+            .and_then(|_| fs::rename(&temporary, path));
+        if written.is_err() {
+            let _ = fs::remove_file(&temporary);
+        }
+        written.with_context(|| format!("unable to write vault `{path}`"))
+    }
+
+    /// The token replacing `original`, recording it in the vault.
+    pub fn tokenize(&mut self, original: &str) -> ExtractResult<String> {
+        let mut mac = HmacSha256::new_from_slice(&self.keys.tokens).expect("any key length");
+        mac.update(original.as_bytes());
+        let digest = hex(&mac.finalize().into_bytes());
+        let token = format!("tok_{}", &digest[..TOKEN_DIGITS]);
+
+        match self.tokens.get(&token) {
+            Some(recorded) if recorded != original => {
+                bail!("token `{token}` is taken by another value")
+            }
+            Some(_) => {}
+            None => {
+                self.tokens.insert(token.clone(), original.to_string());
+            }
+        }
+        Ok(token)
+    }
+
+    /// The original value `token` replaced, if it is in the vault.
+    pub fn original(&self, token: &str) -> Option<&str> {
+        self.tokens.get(token).map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.tokens.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    /// The header, with the salt and the nonce, followed by the encrypted
+    /// tokens and their tag, which also authenticates the header.
+    fn encrypt(&self) -> ExtractResult<Vec<u8>> {
+        let nonce = random_bytes::<NONCE_LEN>()?;
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&self.salt);
+        bytes.extend_from_slice(&nonce);
+
+        let plaintext = serde_json::to_vec(&self.tokens)?;
+        let ciphertext = cipher(&self.keys)
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &plaintext,
+                    aad: &bytes,
+                },
+            )
+            .map_err(|_| anyhow!("unable to encrypt the vault"))?;
+        bytes.extend_from_slice(&ciphertext);
+        Ok(bytes)
+    }
+
+    fn decrypt(bytes: &[u8], passphrase: &str) -> ExtractResult<Self> {
+        let header = MAGIC.len() + SALT_LEN + NONCE_LEN;
+        if bytes.len() < header + TAG_LEN || !bytes.starts_with(MAGIC) {
+            bail!("not a token vault");
+        }
+        let salt: [u8; SALT_LEN] = bytes[MAGIC.len()..MAGIC.len() + SALT_LEN]
+            .try_into()
+            .unwrap();
+        let nonce = &bytes[MAGIC.len() + SALT_LEN..header];
+        let keys = VaultKeys::derive(passphrase, &salt);
+
+        let plaintext = cipher(&keys)
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: &bytes[header..],
+                    aad: &bytes[..header],
+                },
+            )
+            .ok()
+            .context("wrong vault key, or the vault is damaged")?;
+        Ok(Self {
+            salt,
+            keys,
+            tokens: serde_json::from_slice(&plaintext).context("damaged vault")?,
+        })
+    }
+}
+
+/// Whether a column of type `data_type` holds tokens as they are, rather
+/// than fitting them into something that is no longer in the vault.
+pub(crate) fn holds_tokens(data_type: &DataType) -> bool {
+    let token = format!("tok_{}", "f".repeat(TOKEN_DIGITS));
+    fitting::fit(&token, data_type).unescaped().as_deref() == Some(token.as_str())
+}
+
+fn cipher(keys: &VaultKeys) -> Aes256Gcm {
+    <Aes256Gcm as aes_gcm::KeyInit>::new(&keys.encryption.into())
+}
+
+/// Bytes from the random number generator of the OS.
+fn random_bytes<const N: usize>() -> ExtractResult<[u8; N]> {
+    let mut bytes = [0u8; N];
+    getrandom::getrandom(&mut bytes)
+        .map_err(|err| anyhow!("unable to get random bytes from the OS: {err}"))?;
+    Ok(bytes)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Restore the tokens of a dump masked with `tokenize` to their original
+/// values, writing the dump to `output`.
+///
+/// Tokens are restored in the values of `INSERT`s, and in the values
+/// `UPDATE`s assign and compare with. Only the columns matching `columns`,
+/// or every column when it is empty, and with `rows`, only the rows of
+/// `INSERT`s and the `UPDATE`s whose values match it are restored. Values
+/// that are not in the vault are left alone.
+///
+/// Returns the number of statements that were rewritten.
+pub fn detokenize_dump<R: Read, W: Write>(
+    vault: &TokenVault,
+    columns: &[ColumnSelector],
+    rows: Option<&Predicate>,
+    input: R,
+    output: &mut W,
+) -> ExtractResult<usize> {
+    let mut database = None;
+    let mut tables = HashMap::new();
+    let mut rewritten = 0;
+
+    for statement in StatementReader::new(input) {
+        let statement = statement?;
+        match statement.keyword().as_str() {
+            "USE" => {
+                if let Ok(use_database) =
+                    parse_rule::<UseDatabase>(Rule::USE_DATABASE, &statement.body)
+                {
+                    database = Some(use_database.name);
+                }
+            }
+            "CREATE" => {
+                if let Ok(create_table) =
+                    parse_rule::<CreateTable>(Rule::CREATE_TABLE, &statement.body)
+                {
+                    let names = create_table
+                        .columns
+                        .iter()
+                        .map(|column| column.name.clone())
+                        .collect::<Vec<String>>();
+                    tables.insert((database.clone(), create_table.name), names);
+                }
+            }
+            "INSERT" | "REPLACE" => {
+                let mut insert = parse_rule::<Insert>(Rule::INSERT_STATEMENT, &statement.body)?;
+                let names = match insert.column_names.is_empty() {
+                    false => insert.column_names.clone(),
+                    true => tables
+                        .get(&(database.clone(), insert.table_name.clone()))
+                        .cloned()
+                        .with_context(|| {
+                            format!(
+                                "INSERT into `{}` has no column list and no CREATE TABLE was found for it",
+                                insert.table_name
+                            )
+                        })?,
+                };
+                let selected = names
+                    .iter()
+                    .map(|name| {
+                        let column = ColumnRef::new(
+                            database.as_deref(),
+                            &insert.table_name,
+                            name.trim_matches('`'),
+                        );
+                        columns.is_empty()
+                            || columns.iter().any(|selector| selector.matches(&column))
+                    })
+                    .collect::<Vec<bool>>();
+
+                let mut changed = false;
+                for row in insert.values.iter_mut() {
+                    if let Some(rows) = rows {
+                        let values = names
+                            .iter()
+                            .map(|name| name.trim_matches('`').to_string())
+                            .zip(row.0.iter().map(InsertValue::unescaped))
+                            .collect::<Vec<(String, Option<String>)>>();
+                        if !rows.matches(&values).unwrap_or(false) {
+                            continue;
+                        }
+                    }
+                    for (value, _) in row
+                        .0
+                        .iter_mut()
+                        .zip(selected.iter())
+                        .filter(|(_, selected)| **selected)
+                    {
+                        if let Some(original) =
+                            value.unescaped().and_then(|token| vault.original(&token))
+                        {
+                            *value = InsertValue::text(original);
+                            changed = true;
+                        }
+                    }
+                }
+
+                if changed {
+                    rewritten += 1;
+                    write!(output, "{}", statement.with_body(insert.to_string()))?;
+                    continue;
+                }
+            }
+            "UPDATE" => {
+                let Ok(mut update) = parse_rule::<Update>(Rule::UPDATE_STATEMENT, &statement.body)
+                else {
+                    write!(output, "{statement}")?;
+                    continue;
+                };
+                if rows.is_some_and(|rows| !rows.matches(&update_row(&update)).unwrap_or(false)) {
+                    write!(output, "{statement}")?;
+                    continue;
+                }
+                let selected = |name: &str| {
+                    let column = ColumnRef::new(
+                        database.as_deref(),
+                        &update.table_name,
+                        name.trim_matches('`'),
+                    );
+                    columns.is_empty() || columns.iter().any(|selector| selector.matches(&column))
+                };
+
+                let mut changed = false;
+                for pair in update
+                    .set_clauses
+                    .iter_mut()
+                    .flat_map(|assignment| assignment.kv_pairs.iter_mut())
+                    .filter(|pair| selected(&pair.key.to_string()))
+                {
+                    if let AssignmentValue::String(ref token) = pair.value {
+                        if let Some(original) = vault.original(token) {
+                            pair.value = AssignmentValue::String(escape_str(original));
+                            changed = true;
+                        }
+                    }
+                }
+                for condition in update
+                    .where_clauses
+                    .iter_mut()
+                    .filter(|condition| selected(&condition.column))
+                {
+                    let literals = match condition.values.is_empty() {
+                        true => std::slice::from_mut(&mut condition.value),
+                        false => condition.values.as_mut_slice(),
+                    };
+                    let mut restored = false;
+                    for literal in literals.iter_mut() {
+                        if let Some(original) = literal
+                            .strip_prefix('\'')
+                            .and_then(|token| token.strip_suffix('\''))
+                            .and_then(|token| vault.original(token))
+                        {
+                            *literal = InsertValue::text(original).to_string();
+                            restored = true;
+                        }
+                    }
+                    if restored && !condition.values.is_empty() {
+                        condition.value = format!("({})", condition.values.join(", "));
+                    }
+                    changed |= restored;
+                }
+
+                if changed {
+                    rewritten += 1;
+                    let update = update.to_string();
+                    let body = update.strip_suffix(';').unwrap_or(&update).to_string();
+                    write!(output, "{}", statement.with_body(body))?;
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        write!(output, "{statement}")?;
+    }
+
+    Ok(rewritten)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn can_save_and_open_vaults() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tokens.vault");
+        let path = path.to_str().unwrap();
+
+        let mut vault = TokenVault::open(path, "secret").unwrap();
+        let token = vault.tokenize("john@example.com").unwrap();
+        assert!(token.starts_with("tok_") && token.len() == 20);
+        assert_eq!(vault.tokenize("john@example.com").unwrap(), token);
+        assert_ne!(vault.tokenize("jane@example.com").unwrap(), token);
+        vault.save(path).unwrap();
+
+        let bytes = fs::read(path).unwrap();
+        assert!(!String::from_utf8_lossy(&bytes).contains("john"));
+        // The temporary file replaced the vault
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        let mut vault = TokenVault::open(path, "secret").unwrap();
+        assert_eq!(vault.len(), 2);
+        assert_eq!(vault.original(&token), Some("john@example.com"));
+        assert_eq!(vault.tokenize("john@example.com").unwrap(), token);
+        assert_eq!(vault.original("tok_0000000000000000"), None);
+
+        assert!(TokenVault::open(path, "wrong").is_err());
+        let mut damaged = bytes.clone();
+        damaged[MAGIC.len() + SALT_LEN + NONCE_LEN] ^= 1;
+        assert!(TokenVault::decrypt(&damaged, "secret").is_err());
+        assert!(TokenVault::decrypt(b"not a vault", "secret").is_err());
+    }
+
+    #[test]
+    fn can_detokenize_dumps() {
+        let mut vault = TokenVault::new("secret").unwrap();
+        let john = vault.tokenize("john@example.com").unwrap();
+        let jane = vault.tokenize("jane@example.com").unwrap();
+        let input = format!(
+            "CREATE TABLE `users` (\n  `id` int NOT NULL,\n  `email` varchar(64) DEFAULT NULL,\n  `backup` varchar(64) DEFAULT NULL\n);\nINSERT INTO `users` VALUES (1, '{john}', '{jane}'), (2, '{jane}', 'other');\n"
+        );
+
+        let detokenize = |columns: &[&str], rows: Option<&str>| {
+            let columns = columns
+                .iter()
+                .map(|selector| ColumnSelector::parse(selector).unwrap())
+                .collect::<Vec<ColumnSelector>>();
+            let rows = rows.map(|rows| Predicate::parse(rows).unwrap());
+            let mut output = Vec::new();
+            detokenize_dump(
+                &vault,
+                &columns,
+                rows.as_ref(),
+                input.as_bytes(),
+                &mut output,
+            )
+            .unwrap();
+            String::from_utf8(output).unwrap()
+        };
+
+        assert!(detokenize(&[], None).contains(
+            "VALUES (1, 'john@example.com', 'jane@example.com'), (2, 'jane@example.com', 'other');"
+        ));
+        assert!(detokenize(&["users.email"], None).contains(&format!(
+            "VALUES (1, 'john@example.com', '{jane}'), (2, 'jane@example.com', 'other');"
+        )));
+        assert!(detokenize(&[], Some("id = 2")).contains(&format!(
+            "VALUES (1, '{john}', '{jane}'), (2, 'jane@example.com', 'other');"
+        )));
+    }
+}
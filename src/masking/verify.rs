@@ -0,0 +1,517 @@
+use std::{
+    collections::HashMap,
+    fmt::{Display, Formatter, Result as FmtResult},
+    io::Read,
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::{
+    dump::{DumpStatement, StatementReader},
+    parser::{
+        parse_rule,
+        parse_utils::unescape_str,
+        statements::{CreateTable, Insert, UseDatabase},
+        types::{AssignmentValue, Update},
+        Rule,
+    },
+    ExtractResult,
+};
+
+use super::{literal_value, ColumnRef, ColumnSelector, LITERAL};
+
+/// Original values shorter than this are not looked for by default, as
+/// they turn up everywhere by chance.
+pub const DEFAULT_MIN_LENGTH: usize = 4;
+/// The most words of an original value looked for inside longer text.
+const MAX_WORDS: usize = 32;
+
+type Digest128 = [u8; 16];
+
+/// An original value found in a masked dump.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Leak {
+    /// The column the value was written to or compared with, when known.
+    pub column: Option<ColumnRef>,
+    /// The position of the row among the rows inserted into the table,
+    /// starting at 1, for values of `INSERT`s.
+    pub row: Option<usize>,
+    /// The position of the statement in the masked dump, starting at 1.
+    pub statement: usize,
+    /// The masked column the value was taken from.
+    pub source: ColumnRef,
+}
+
+impl Display for Leak {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match (&self.column, self.row) {
+            (Some(column), Some(row)) => write!(f, "{column} row {row}")?,
+            (Some(column), None) => write!(f, "{column} in statement {}", self.statement)?,
+            (None, _) => write!(f, "statement {}", self.statement)?,
+        }
+        write!(f, ": original value of {}", self.source)
+    }
+}
+
+/// Looks for the original values of the masked columns of a dump in its
+/// masked version, to check that none of them survived masking.
+///
+/// Every value of the masked columns is kept, as a digest only, and found
+/// in any literal of the masked dump, on their own or among other text,
+/// with case, spacing and punctuation around words ignored.
+#[derive(Debug, Default)]
+pub struct LeakCheck {
+    /// Columns whose original values are not looked for, such as remapped
+    /// ids, whose replacements are other original ids.
+    exclusions: Vec<ColumnSelector>,
+    min_length: usize,
+    /// The masked columns values were collected from.
+    columns: Vec<ColumnRef>,
+    /// The index in `columns` of each column seen so far, `None` for the
+    /// columns that are not masked or excluded.
+    indexes: HashMap<ColumnRef, Option<usize>>,
+    /// The index in `columns` of the column of each original value.
+    originals: HashMap<Digest128, usize>,
+    max_words: usize,
+}
+
+impl LeakCheck {
+    pub fn new(exclusions: Vec<ColumnSelector>, min_length: usize) -> Self {
+        Self {
+            exclusions,
+            min_length,
+            ..Default::default()
+        }
+    }
+
+    /// Collect every value of the columns of `original` that `masked`
+    /// tells are masked, from the rows of `INSERT`s and the values `UPDATE`s
+    /// assign and compare.
+    pub fn learn<R: Read>(
+        &mut self,
+        original: R,
+        masked: &mut dyn FnMut(&ColumnRef) -> ExtractResult<bool>,
+    ) -> ExtractResult<()> {
+        let mut schema = Schema::default();
+
+        for statement in StatementReader::new(original) {
+            for value in schema.values(&statement?) {
+                let Some(column) = value.column else {
+                    continue;
+                };
+                if let Some(index) = self.index(column, masked)? {
+                    if let Some(digest) = self.digest(&value.value) {
+                        self.originals.entry(digest).or_insert(index);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// How many columns the original values were collected from.
+    pub fn masked_columns(&self) -> usize {
+        self.columns.len()
+    }
+
+    /// Every value of `masked` holding an original value, once for each
+    /// masked column the value comes from. The values of `INSERT`s are
+    /// found by column and row, those of `UPDATE`s by column, and the
+    /// literals of any other statement by statement alone.
+    pub fn scan<R: Read>(&self, masked: R) -> ExtractResult<Vec<Leak>> {
+        let mut schema = Schema::default();
+        let mut leaks = Vec::new();
+
+        for (index, statement) in StatementReader::new(masked).enumerate() {
+            for value in schema.values(&statement?) {
+                for source in self.sources(&value.value) {
+                    leaks.push(Leak {
+                        column: value.column.clone(),
+                        row: value.row,
+                        statement: index + 1,
+                        source: source.clone(),
+                    });
+                }
+            }
+        }
+
+        Ok(leaks)
+    }
+
+    /// The masked columns of the original values found in `value`.
+    fn sources(&self, value: &str) -> Vec<&ColumnRef> {
+        let words = words(value);
+        let mut sources = Vec::new();
+
+        for start in 0..words.len() {
+            for end in start + 1..=words.len().min(start + self.max_words) {
+                if let Some(&index) = self.originals.get(&digest(&words[start..end].join(" "))) {
+                    let source = &self.columns[index];
+                    if !sources.contains(&source) {
+                        sources.push(source);
+                    }
+                }
+            }
+        }
+
+        sources
+    }
+
+    /// The index of `column` in `columns`, when it is masked and not
+    /// excluded.
+    fn index(
+        &mut self,
+        column: ColumnRef,
+        masked: &mut dyn FnMut(&ColumnRef) -> ExtractResult<bool>,
+    ) -> ExtractResult<Option<usize>> {
+        if let Some(index) = self.indexes.get(&column) {
+            return Ok(*index);
+        }
+
+        let excluded = self
+            .exclusions
+            .iter()
+            .any(|exclusion| exclusion.matches(&column));
+        let index = match !excluded && masked(&column)? {
+            true => {
+                self.columns.push(column.clone());
+                Some(self.columns.len() - 1)
+            }
+            false => None,
+        };
+        self.indexes.insert(column, index);
+        Ok(index)
+    }
+
+    /// The digest of an original value, unless it is too short to look
+    /// for.
+    fn digest(&mut self, original: &str) -> Option<Digest128> {
+        let words = words(original);
+        let normalized = words.join(" ");
+        if words.len() > MAX_WORDS || normalized.chars().count() < self.min_length {
+            return None;
+        }
+
+        self.max_words = self.max_words.max(words.len());
+        Some(digest(&normalized))
+    }
+}
+
+/// The lowercased words of `value`: runs of letters and digits, along with
+/// the characters inside emails, phone numbers and names, but without the
+/// punctuation at their ends.
+fn words(value: &str) -> Vec<String> {
+    value
+        .split(|c: char| !(c.is_alphanumeric() || "@._+-'".contains(c)))
+        .map(|word| {
+            word.trim_start_matches(|c: char| !c.is_alphanumeric() && c != '+')
+                .trim_end_matches(|c: char| !c.is_alphanumeric())
+        })
+        .filter(|word| word.chars().any(char::is_alphanumeric))
+        .map(str::to_lowercase)
+        .collect()
+}
+
+fn digest(normalized: &str) -> Digest128 {
+    Sha256::digest(normalized.as_bytes())[..16]
+        .try_into()
+        .expect("SHA-256 has more than 16 bytes")
+}
+
+/// A value of a statement, with the column it was written to or compared
+/// with and its row, when known.
+struct StatementValue {
+    column: Option<ColumnRef>,
+    row: Option<usize>,
+    value: String,
+}
+
+/// The current database, the columns of the tables created so far and the
+/// number of rows inserted into each.
+#[derive(Default)]
+struct Schema {
+    database: Option<String>,
+    tables: HashMap<(Option<String>, String), Vec<String>>,
+    rows: HashMap<(Option<String>, String), usize>,
+}
+
+impl Schema {
+    /// The values of `statement`, following `USE` and `CREATE TABLE`
+    /// statements: by column and row for `INSERT`s, by column for
+    /// `UPDATE`s, and the literals alone for any other statement, or one
+    /// that can't be parsed.
+    fn values(&mut self, statement: &DumpStatement) -> Vec<StatementValue> {
+        let parsed = match statement.keyword().as_str() {
+            "USE" => {
+                if let Ok(use_database) =
+                    parse_rule::<UseDatabase>(Rule::USE_DATABASE, &statement.body)
+                {
+                    self.database = Some(use_database.name);
+                }
+                None
+            }
+            "CREATE" => {
+                if let Ok(create_table) =
+                    parse_rule::<CreateTable>(Rule::CREATE_TABLE, &statement.body)
+                {
+                    let names = create_table
+                        .columns
+                        .iter()
+                        .map(|column| column.name.clone())
+                        .collect();
+                    self.tables
+                        .insert((self.database.clone(), create_table.name), names);
+                }
+                None
+            }
+            "INSERT" | "REPLACE" => parse_rule::<Insert>(Rule::INSERT_STATEMENT, &statement.body)
+                .ok()
+                .and_then(|insert| self.insert_values(&insert)),
+            "UPDATE" => parse_rule::<Update>(Rule::UPDATE_STATEMENT, &statement.body)
+                .ok()
+                .map(|update| self.update_values(&update)),
+            _ => None,
+        };
+
+        parsed.unwrap_or_else(|| {
+            LITERAL
+                .find_iter(&statement.body)
+                .filter_map(|literal| {
+                    Some(StatementValue {
+                        column: None,
+                        row: None,
+                        value: literal_value(literal.as_str())?,
+                    })
+                })
+                .collect()
+        })
+    }
+
+    /// The values of an `INSERT`, or `None` when its columns are unknown.
+    fn insert_values(&mut self, insert: &Insert) -> Option<Vec<StatementValue>> {
+        let columns = self.columns(insert)?;
+        let count = self
+            .rows
+            .entry((self.database.clone(), insert.table_name.clone()))
+            .or_default();
+        let mut values = Vec::new();
+
+        for row in insert.values.iter() {
+            *count += 1;
+            for (column, value) in columns.iter().zip(row.0.iter()) {
+                if let Some(value) = value.unescaped() {
+                    values.push(StatementValue {
+                        column: Some(column.clone()),
+                        row: Some(*count),
+                        value,
+                    });
+                }
+            }
+        }
+
+        Some(values)
+    }
+
+    /// The values an `UPDATE` assigns and compares with.
+    fn update_values(&self, update: &Update) -> Vec<StatementValue> {
+        let value = |name: &str, value| StatementValue {
+            column: Some(ColumnRef::new(
+                self.database.as_deref(),
+                &update.table_name,
+                name.trim_matches('`'),
+            )),
+            row: None,
+            value,
+        };
+        let assigned = update
+            .set_clauses
+            .iter()
+            .flat_map(|assignment| assignment.kv_pairs.iter())
+            .filter_map(|pair| match pair.value {
+                AssignmentValue::String(ref string) => {
+                    Some(value(&pair.key.to_string(), unescape_str(string)))
+                }
+                AssignmentValue::Number(ref number) => {
+                    Some(value(&pair.key.to_string(), number.clone()))
+                }
+                _ => None,
+            });
+        let compared = update.where_clauses.iter().flat_map(|condition| {
+            let literals = match condition.values.is_empty() {
+                true => std::slice::from_ref(&condition.value),
+                false => condition.values.as_slice(),
+            };
+            literals
+                .iter()
+                .filter_map(|literal| literal_value(literal))
+                .map(|literal| value(&condition.column, literal))
+                .collect::<Vec<StatementValue>>()
+        });
+
+        assigned.chain(compared).collect()
+    }
+
+    /// The columns of an `INSERT`, taken from its `CREATE TABLE` when it
+    /// does not list them, or `None` when there is none.
+    fn columns(&self, insert: &Insert) -> Option<Vec<ColumnRef>> {
+        let names = match insert.column_names.is_empty() {
+            false => &insert.column_names,
+            true => self
+                .tables
+                .get(&(self.database.clone(), insert.table_name.clone()))?,
+        };
+
+        Some(
+            names
+                .iter()
+                .map(|name| {
+                    ColumnRef::new(
+                        self.database.as_deref(),
+                        &insert.table_name,
+                        name.trim_matches('`'),
+                    )
+                })
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn can_split_values_into_words() {
+        assert_eq!(
+            words("Call Ann O'Brien (ann@example.com) at +1 (555) 010-9999."),
+            [
+                "call",
+                "ann",
+                "o'brien",
+                "ann@example.com",
+                "at",
+                "+1",
+                "555",
+                "010-9999"
+            ]
+        );
+        assert_eq!(
+            words(r#"{"street":"1 Main St"}"#),
+            ["street", "1", "main", "st"]
+        );
+        assert!(words(" -- ").is_empty());
+    }
+
+    fn selected(selectors: &[&str]) -> impl FnMut(&ColumnRef) -> ExtractResult<bool> {
+        let selectors = selectors
+            .iter()
+            .map(|selector| ColumnSelector::parse(selector).unwrap())
+            .collect::<Vec<ColumnSelector>>();
+        move |column| Ok(selectors.iter().any(|selector| selector.matches(column)))
+    }
+
+    #[test]
+    fn can_find_leaks() {
+        let original = "CREATE TABLE `users` (`id` int, `name` varchar(20), `email` varchar(50), `notes` text);
+INSERT INTO `users` VALUES (1, 'Ann Lee', 'ann@example.com', 'Likes tea'), (2, 'Bobby', 'bob@example.com', NULL);
+INSERT INTO `orders` (`id`, `comment`) VALUES (1, 'ok');
+";
+        let masked = "CREATE TABLE `users` (`id` int, `name` varchar(20), `email` varchar(50), `notes` text);
+INSERT INTO `users` VALUES (1, 'Kim Park', 'x@example.net', 'Likes tea'), (2, 'Bobby', 'y@example.net', 'Mail ANN@example.com!');
+INSERT INTO `orders` (`id`, `comment`) VALUES (1, 'Ask ann  lee');
+";
+        let mut check = LeakCheck::new(Vec::new(), DEFAULT_MIN_LENGTH);
+        check
+            .learn(original.as_bytes(), &mut selected(&["users.name", "email"]))
+            .unwrap();
+        assert_eq!(check.masked_columns(), 2);
+
+        let leaks = check
+            .scan(masked.as_bytes())
+            .unwrap()
+            .iter()
+            .map(Leak::to_string)
+            .collect::<Vec<String>>();
+        assert_eq!(
+            leaks,
+            [
+                "`users`.`name` row 2: original value of `users`.`name`",
+                "`users`.`notes` row 2: original value of `users`.`email`",
+                "`orders`.`comment` row 1: original value of `users`.`name`",
+            ]
+        );
+
+        let mut check = LeakCheck::new(vec![ColumnSelector::parse("users.email").unwrap()], 4);
+        check
+            .learn(original.as_bytes(), &mut selected(&["users.name", "email"]))
+            .unwrap();
+        assert_eq!(check.masked_columns(), 1);
+        assert_eq!(check.scan(masked.as_bytes()).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn can_find_leaks_in_any_statement() {
+        let original = "INSERT INTO `users` (`id`, `name`, `email`) VALUES (1, 'Ann Lee', 'ann@example.com'), (2, 'Bob Stone', 'bob@example.com');
+UPDATE `users` SET `name` = 'Ann Lee' WHERE `email` IN ('ann@example.com', 'bob@example.com');
+DELETE FROM `users` WHERE `name` = 'Bob Stone';
+";
+        let masked = "INSERT INTO `users` (`id`, `name`, `email`) VALUES (1, 'Kim Park', 'x@example.net'), (2, 'Sam Poe', 'y@example.net');
+UPDATE `users` SET `name` = 'Kim Park' WHERE `email` IN ('x@example.net', 'bob@example.com');
+DELETE FROM `users` WHERE `name` = 'Bob Stone';
+";
+        let mut check = LeakCheck::new(Vec::new(), DEFAULT_MIN_LENGTH);
+        check
+            .learn(
+                original.as_bytes(),
+                &mut selected(&["users.name", "users.email"]),
+            )
+            .unwrap();
+
+        let leaks = check
+            .scan(masked.as_bytes())
+            .unwrap()
+            .iter()
+            .map(Leak::to_string)
+            .collect::<Vec<String>>();
+        assert_eq!(
+            leaks,
+            [
+                "`users`.`email` in statement 2: original value of `users`.`email`",
+                "statement 3: original value of `users`.`name`",
+            ]
+        );
+    }
+
+    #[test]
+    fn can_find_leaks_of_updated_values() {
+        let original = "INSERT INTO `users` (`id`, `email`) VALUES (1, 'ann@example.com');
+UPDATE `users` SET `email` = 'ann.lee@example.com' WHERE `id` = 1;
+INSERT INTO `log` (`id`, `at`) VALUES (1, NOW());
+INSERT INTO `sessions` VALUES (1, 'ann.lee@example.com');
+";
+        let masked = "INSERT INTO `users` (`id`, `email`) VALUES (1, 'x@example.net');
+UPDATE `users` SET `email` = 'ann.lee@example.com' WHERE `id` = 1;
+INSERT INTO `log` (`id`, `at`) VALUES (1, NOW());
+INSERT INTO `sessions` VALUES (1, 'ann.lee@example.com');
+";
+        let mut check = LeakCheck::new(Vec::new(), DEFAULT_MIN_LENGTH);
+        check
+            .learn(original.as_bytes(), &mut selected(&["users.email"]))
+            .unwrap();
+
+        let leaks = check
+            .scan(masked.as_bytes())
+            .unwrap()
+            .iter()
+            .map(Leak::to_string)
+            .collect::<Vec<String>>();
+        assert_eq!(
+            leaks,
+            [
+                "`users`.`email` in statement 2: original value of `users`.`email`",
+                "statement 4: original value of `users`.`email`",
+            ]
+        );
+    }
+}
@@ -1,12 +1,43 @@
+use anyhow::{anyhow, bail, Context};
+use pest::{iterators::Pair, Parser};
 use pest_derive::Parser;
 
+use crate::ExtractResult;
+
 pub(crate) mod parse_utils;
 pub mod statements;
 pub mod types;
 
 #[derive(Parser)]
 #[grammar = "parser/sql.pest"]
-struct MySqlParser;
+pub(crate) struct MySqlParser;
+
+/// Parse `input` as a single `rule`, requiring the rule to cover all of the
+/// input apart from trailing whitespace.
+pub fn parse_rule<T>(rule: Rule, input: &str) -> ExtractResult<T>
+where
+    T: for<'i> From<Pair<'i, Rule>>,
+{
+    let pair = MySqlParser::parse(rule, input)
+        .map_err(|err| {
+            let (line, column) = match err.line_col {
+                pest::error::LineColLocation::Pos(pos) => pos,
+                pest::error::LineColLocation::Span(start, _) => start,
+            };
+            anyhow!("unable to parse {rule:?} at line {line}, column {column}")
+        })?
+        .next()
+        .with_context(|| format!("unable to parse {rule:?}"))?;
+    let end = pair.as_span().end();
+    let trimmed = input.trim_end();
+
+    if end < trimmed.len() {
+        let rest: String = trimmed[end..].chars().take(40).collect();
+        bail!("unable to parse {rule:?}: unexpected `{rest}`");
+    }
+
+    Ok(T::from(pair))
+}
 
 // #[derive(Debug)]
 // pub struct MyParser {
@@ -279,6 +310,7 @@ struct MySqlParser;
 
 #[cfg(test)]
 mod tests {
+    #[allow(unused_imports)]
     use super::*;
 
     // #[test]
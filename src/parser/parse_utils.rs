@@ -5,3 +5,82 @@ use super::Rule;
 pub fn trim_str(s: Pair<'_, Rule>) -> String {
     s.as_str().trim_matches('`').trim_matches('\'').to_string()
 }
+
+/// Strip exactly one pair of surrounding quotes from a string literal,
+/// leaving any escaped quotes at either end intact.
+pub fn strip_quotes(s: &str) -> &str {
+    s.strip_prefix('\'')
+        .and_then(|s| s.strip_suffix('\''))
+        .unwrap_or(s)
+}
+
+/// Resolve MySQL backslash escapes and doubled quotes in the body of a
+/// string literal.
+pub fn unescape_str(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('0') => out.push('\0'),
+                Some('b') => out.push('\u{8}'),
+                Some('n') => out.push('\n'),
+                Some('r') => out.push('\r'),
+                Some('t') => out.push('\t'),
+                Some('Z') => out.push('\u{1a}'),
+                // `\%` and `\_` keep their backslash outside of LIKE patterns
+                Some(c @ ('%' | '_')) => {
+                    out.push('\\');
+                    out.push(c);
+                }
+                Some(other) => out.push(other),
+                None => out.push('\\'),
+            },
+            '\'' if chars.peek() == Some(&'\'') => {
+                chars.next();
+                out.push('\'');
+            }
+            other => out.push(other),
+        }
+    }
+
+    out
+}
+
+/// Escape a string so it can be placed between single quotes, the same
+/// way `mysqldump` writes string literals.
+pub fn escape_str(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+
+    for c in s.chars() {
+        match c {
+            '\0' => out.push_str("\\0"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\u{1a}' => out.push_str("\\Z"),
+            '\\' => out.push_str("\\\\"),
+            '\'' => out.push_str("\\'"),
+            '"' => out.push_str("\\\""),
+            other => out.push(other),
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn can_round_trip_escaped_strings() {
+        let raw = "it's a \"test\"\nwith \\ slashes";
+        let escaped = escape_str(raw);
+
+        assert_eq!(escaped, "it\\'s a \\\"test\\\"\\nwith \\\\ slashes");
+        assert_eq!(unescape_str(&escaped), raw);
+        assert_eq!(unescape_str("it''s"), "it's");
+        assert_eq!(strip_quotes("'it\\''"), "it\\'");
+    }
+}
@@ -271,6 +271,7 @@ mod test {
                     local_column_names: vec![String::from("ProductId")],
                     foreign_column_names: vec![String::from("Id")],
                     foreign_table_name: String::from("product"),
+                    on_delete: None,
                     on_update: None,
                 },],
                 indexes: vec![Index {
@@ -7,6 +7,7 @@ use std::fmt::{Display, Formatter, Result as FmtResult};
 
 #[derive(Debug, Clone)]
 pub struct Insert {
+    pub replace: bool,
     pub priority: Option<InsertPriority>,
     pub ignore: bool,
     pub table_name: String,
@@ -17,6 +18,7 @@ pub struct Insert {
 impl From<Pair<'_, Rule>> for Insert {
     fn from(pair: Pair<'_, Rule>) -> Self {
         let mut inner = pair.into_inner();
+        let mut replace = false;
         let mut priority = None;
         let mut ignore = false;
         let mut table_name = None;
@@ -27,16 +29,18 @@ impl From<Pair<'_, Rule>> for Insert {
             let element = inner.next().unwrap();
 
             match element.as_rule() {
+                Rule::INSERT_REPLACE => replace = true,
                 Rule::INSERT_PRIORITY => priority = Some(InsertPriority::from(element)),
                 Rule::INSERT_IGNORE => ignore = true,
                 Rule::QUOTED_IDENTIFIER => table_name = Some(element.as_str().trim_matches('`').to_string()),
                 Rule::INSERT_COLUMNS => column_names = Some(element.into_inner().map(|p| p.as_str().trim_matches('`').to_string()).collect::<Vec<String>>()),
-                Rule::INSERT_VALUES_LIST => { values = element.into_inner().map(|p| InsertValues::from(p)).collect::<Vec<InsertValues>>(); break },
-                other => panic!("Expected INSERT_PRIORITY, INSERT_IGNORE, QUOTED_IDENTIFIER, INSERT_COLUMNS or INSERT_VALUES_LIST, not {other:?}"),
+                Rule::INSERT_VALUES_LIST => { values = element.into_inner().map(InsertValues::from).collect::<Vec<InsertValues>>(); break },
+                other => panic!("Expected INSERT_REPLACE, INSERT_PRIORITY, INSERT_IGNORE, QUOTED_IDENTIFIER, INSERT_COLUMNS or INSERT_VALUES_LIST, not {other:?}"),
             }
         }
 
         Self {
+            replace,
             priority,
             ignore,
             table_name: table_name.expect("table name"),
@@ -50,7 +54,8 @@ impl Display for Insert {
     fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
         write!(
             f,
-            "INSERT{}{} INTO `{}` ({}) VALUES {}",
+            "{}{}{} INTO `{}`{} VALUES {}",
+            if self.replace { "REPLACE" } else { "INSERT" },
             if let Some(ref priority) = self.priority {
                 format!(" {priority}")
             } else {
@@ -58,11 +63,18 @@ impl Display for Insert {
             },
             if self.ignore { " IGNORE" } else { "" },
             self.table_name,
-            self.column_names
-                .iter()
-                .map(|name| format!("`{name}`"))
-                .collect::<Vec<String>>()
-                .join(", "),
+            if self.column_names.is_empty() {
+                "".to_string()
+            } else {
+                format!(
+                    " ({})",
+                    self.column_names
+                        .iter()
+                        .map(|name| format!("`{name}`"))
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                )
+            },
             self.values
                 .iter()
                 .map(|value| format!("{value}"))
@@ -90,6 +102,7 @@ mod test {
             .expect("Unable to parse input"),
         );
 
+        assert!(!insert.replace);
         assert!(matches!(insert.priority, Some(InsertPriority::High)));
         assert!(insert.ignore);
         assert_eq!(insert.table_name.as_str(), "my_table");
@@ -104,6 +117,7 @@ mod test {
     fn can_write_insert() {
         assert_eq!(
             Insert {
+                replace: false,
                 priority: Some(InsertPriority::High),
                 ignore: true,
                 table_name: String::from("my_table"),
@@ -115,4 +129,30 @@ mod test {
             "INSERT HIGH_PRIORITY IGNORE INTO `my_table` (`col1`, `col2`) VALUES (NULL, DEFAULT), ('foo', 42)"
         );
     }
+
+    #[test]
+    fn can_parse_replace_with_escaped_strings() {
+        let insert = Insert::from(
+            MySqlParser::parse(
+                Rule::INSERT_STATEMENT,
+                r"REPLACE INTO `my_table` VALUES (1, 'it\'s', 'say ''hi''');",
+            )
+            .expect("Invalid input")
+            .next()
+            .expect("Unable to parse input"),
+        );
+
+        assert!(insert.replace);
+        assert!(!insert.ignore);
+        assert!(insert.column_names.is_empty());
+        assert_eq!(insert.values[0].0[1].unescaped().as_deref(), Some("it's"));
+        assert_eq!(
+            insert.values[0].0[2].unescaped().as_deref(),
+            Some("say 'hi'")
+        );
+        assert_eq!(
+            insert.to_string().as_str(),
+            r"REPLACE INTO `my_table` VALUES (1, 'it\'s', 'say ''hi''')"
+        );
+    }
 }
@@ -1,12 +1,17 @@
-use crate::parser::{parse_utils::trim_str, Rule};
+use crate::parser::{
+    parse_utils::{strip_quotes, trim_str},
+    Rule,
+};
 use pest::iterators::{Pair, Pairs};
 use std::fmt::{Display, Formatter, Result as FmtResult};
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum AssignmentValue {
+    /// The body of a string literal, still escaped.
     String(String),
-    Number(usize),
+    Number(String),
     Boolean(bool),
+    Identifier(String),
     Null,
 }
 
@@ -16,6 +21,7 @@ impl Display for AssignmentValue {
             AssignmentValue::String(s) => write!(f, "'{}'", s),
             AssignmentValue::Number(n) => write!(f, "{}", n),
             AssignmentValue::Boolean(b) => write!(f, "{}", b),
+            AssignmentValue::Identifier(i) => write!(f, "{}", i),
             AssignmentValue::Null => write!(f, "NULL"),
         }
     }
@@ -24,12 +30,12 @@ impl Display for AssignmentValue {
 impl From<Pair<'_, Rule>> for AssignmentValue {
     fn from(pair: Pair<'_, Rule>) -> Self {
         match pair.as_rule() {
-            Rule::STRING_LITERAL => AssignmentValue::String(trim_str(pair)),
-            Rule::BOOLEAN_LITERAL => AssignmentValue::Boolean(trim_str(pair).parse().unwrap()),
-            Rule::NUMBER => {
-                let value = pair.as_span().as_str();
-                AssignmentValue::Number(value.parse().unwrap())
+            Rule::STRING_LITERAL => {
+                AssignmentValue::String(strip_quotes(pair.as_str()).to_string())
             }
+            Rule::BOOLEAN_LITERAL => AssignmentValue::Boolean(trim_str(pair).parse().unwrap()),
+            Rule::NUMBER => AssignmentValue::Number(pair.as_str().to_string()),
+            Rule::IDENTIFIER => AssignmentValue::Identifier(pair.as_str().to_string()),
             _ => AssignmentValue::String(trim_str(pair)),
         }
     }
@@ -111,8 +117,10 @@ impl From<Pair<'_, Rule>> for Assignment {
 
         while let Some(key_inner) = inner.next() {
             let key = AssignmentKey::from(key_inner);
-            let value_inner = inner.next().expect("invalid value");
-            let value = AssignmentValue::from(value_inner);
+            // `NULL` is matched as a keyword, so it leaves no pair behind
+            let value = inner
+                .next()
+                .map_or(AssignmentValue::Null, AssignmentValue::from);
             kv_pairs.push(KVPair::new(key, value));
         }
 
@@ -132,7 +140,7 @@ impl From<Pairs<'_, Rule>> for Assignment {
                 acc
             });
 
-        Assignment { kv_pairs: kv_pairs }
+        Assignment { kv_pairs }
     }
 }
 
@@ -151,6 +159,31 @@ mod tests {
         let kvs = set.kv_pairs;
         assert_eq!(kvs.len(), 1);
         assert_eq!(kvs[0].key, AssignmentKey::Identifier("a".to_string()));
-        assert_eq!(kvs[0].value, AssignmentValue::Number(1));
+        assert_eq!(kvs[0].value, AssignmentValue::Number("1".to_string()));
+    }
+
+    #[test]
+    fn test_parses_assignment_values() {
+        for (sql, value) in [
+            ("a = -1.5", AssignmentValue::Number("-1.5".to_string())),
+            (
+                "`a` = 'it''s'",
+                AssignmentValue::String("it''s".to_string()),
+            ),
+            ("a = NULL", AssignmentValue::Null),
+            (
+                "a = CURRENT_TIMESTAMP",
+                AssignmentValue::Identifier("CURRENT_TIMESTAMP".to_string()),
+            ),
+        ] {
+            let parsed = MySqlParser::parse(Rule::ASSIGNMENT_CLAUSE, sql).unwrap();
+            let set = Assignment::from(parsed);
+            assert_eq!(
+                set.kv_pairs[0].key,
+                AssignmentKey::Identifier("a".to_string())
+            );
+            assert_eq!(set.kv_pairs[0].value, value);
+            assert_eq!(set.to_string().replace('`', ""), sql.replace('`', ""));
+        }
     }
 }
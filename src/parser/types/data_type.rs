@@ -634,8 +634,7 @@ impl Display for DataType {
                     .iter()
                     .map(|value| format!("'{value}'"))
                     .collect::<Vec<String>>()
-                    .join(", ")
-                    .to_string(),
+                    .join(", "),
                 if let Some(charset_name) = charset_name {
                     format!(" CHARACTER SET {}", charset_name)
                 } else {
@@ -2042,7 +2041,7 @@ mod test {
 
         assert!(matches!(m, Some(4)));
         assert_eq!(charset_name.unwrap().as_str(), "utf8mb4");
-        assert!(matches!(collation_name, None));
+        assert!(collation_name.is_none());
     }
 
     #[test]
@@ -2179,7 +2178,7 @@ mod test {
 
         assert!(matches!(m, Some(4)));
         assert_eq!(charset_name.unwrap().as_str(), "utf8mb4");
-        assert!(matches!(collation_name, None));
+        assert!(collation_name.is_none());
     }
 
     #[test]
@@ -2469,7 +2468,7 @@ mod test {
 
         assert!(matches!(m, Some(4)));
         assert_eq!(charset_name.unwrap().as_str(), "utf8mb4");
-        assert!(matches!(collation_name, None));
+        assert!(collation_name.is_none());
     }
 
     #[test]
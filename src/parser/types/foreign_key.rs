@@ -8,6 +8,7 @@ pub struct ForeignKey {
     pub local_column_names: Vec<String>,
     pub foreign_column_names: Vec<String>,
     pub foreign_table_name: String,
+    pub on_delete: Option<String>,
     pub on_update: Option<String>,
 }
 
@@ -20,29 +21,25 @@ impl From<Pair<'_, Rule>> for ForeignKey {
                 .map(|p| p.as_str().trim_matches('`').to_string()),
             _ => None,
         };
-        let (local_column_names, foreign_table_name, foreign_column_names, on_update) = inner.fold(
-            (Vec::new(), String::new(), Vec::new(), None),
-            |(mut local, mut table, mut foreign, mut on_update), pair| {
+        let (local_column_names, foreign_table_name, foreign_column_names, on_delete, on_update) = inner.fold(
+            (Vec::new(), String::new(), Vec::new(), None, None),
+            |(mut local, mut table, mut foreign, mut on_delete, mut on_update), pair| {
                 match pair.as_rule() {
                     Rule::QUOTED_IDENTIFIER => {
-                        table
-                            .is_empty()
-                            .then(|| &mut local)
-                            .unwrap_or_else(|| &mut foreign)
+                        if table.is_empty() { &mut local } else { &mut foreign }
                             .push(pair.as_str().trim_matches('`').to_string());
                     }
                     Rule::TABLE_NAME => {
                         table = pair.as_str().trim_matches('`').to_string();
                     }
-                    Rule::FK_ON_UPDATE => {
-                        on_update = Some(pair.as_str().split_ascii_whitespace().rev().next().expect("ON UPDATE value").to_string());
-                    }
+                    Rule::FK_ON_DELETE => on_delete = Some(referential_action(pair)),
+                    Rule::FK_ON_UPDATE => on_update = Some(referential_action(pair)),
                     rule => {
-                        panic!("Expected QUOTED_IDENTIFIER, TABLE_NAME or FK_ON_UPDATE, not not {rule:?}")
+                        panic!("Expected QUOTED_IDENTIFIER, TABLE_NAME, FK_ON_DELETE or FK_ON_UPDATE, not not {rule:?}")
                     }
                 };
 
-                (local, table, foreign, on_update)
+                (local, table, foreign, on_delete, on_update)
             },
         );
 
@@ -51,16 +48,29 @@ impl From<Pair<'_, Rule>> for ForeignKey {
             local_column_names,
             foreign_column_names,
             foreign_table_name,
+            on_delete,
             on_update,
         }
     }
 }
 
+/// The action of an `ON DELETE` or `ON UPDATE` clause, e.g. `SET NULL`.
+fn referential_action(pair: Pair<'_, Rule>) -> String {
+    pair.into_inner()
+        .next()
+        .expect("FK_ACTION")
+        .as_str()
+        .split_ascii_whitespace()
+        .map(|word| word.to_ascii_uppercase())
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
 impl Display for ForeignKey {
     fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
         write!(
             f,
-            "{}FOREIGN KEY ({}) REFERENCES `{}` ({}){}",
+            "{}FOREIGN KEY ({}) REFERENCES `{}` ({}){}{}",
             if let Some(ref name) = self.name {
                 format!("CONSTRAINT `{name}` ")
             } else {
@@ -77,6 +87,11 @@ impl Display for ForeignKey {
                 .map(|col| format!("`{col}`"))
                 .collect::<Vec<String>>()
                 .join(", "),
+            if let Some(ref delete) = self.on_delete {
+                format!(" ON DELETE {delete}")
+            } else {
+                "".to_string()
+            },
             if let Some(ref update) = self.on_update {
                 format!(" ON UPDATE {update}")
             } else {
@@ -193,6 +208,26 @@ mod test {
         assert_eq!(foreign_key.on_update.unwrap().as_str(), "CASCADE");
     }
 
+    #[test]
+    fn can_parse_foreign_key_with_on_delete_and_on_update() {
+        let foreign_key = ForeignKey::from(
+            MySqlParser::parse(
+                Rule::FOREIGN_KEY,
+                "CONSTRAINT `fk_column` FOREIGN KEY (`column_id`) REFERENCES `column` (`id`) ON DELETE set  null ON UPDATE NO ACTION,",
+            )
+            .expect("Invalid input")
+            .next()
+            .expect("Unable to parse input"),
+        );
+
+        assert_eq!(foreign_key.on_delete.as_deref(), Some("SET NULL"));
+        assert_eq!(foreign_key.on_update.as_deref(), Some("NO ACTION"));
+        assert_eq!(
+            foreign_key.to_string(),
+            "CONSTRAINT `fk_column` FOREIGN KEY (`column_id`) REFERENCES `column` (`id`) ON DELETE SET NULL ON UPDATE NO ACTION"
+        );
+    }
+
     #[test]
     fn can_write_foreign_key_without_name() {
         let foreign_key = ForeignKey {
@@ -200,6 +235,7 @@ mod test {
             local_column_names: vec![String::from("column_id"), String::from("column_name")],
             foreign_column_names: vec![String::from("id"), String::from("name")],
             foreign_table_name: String::from("column"),
+            on_delete: None,
             on_update: None,
         };
 
@@ -216,6 +252,7 @@ mod test {
             local_column_names: vec![String::from("column_id"), String::from("column_name")],
             foreign_column_names: vec![String::from("id"), String::from("name")],
             foreign_table_name: String::from("column"),
+            on_delete: None,
             on_update: None,
         };
 
@@ -233,6 +270,7 @@ mod test {
                 local_column_names: vec![String::from("column_id"), String::from("column_name")],
                 foreign_column_names: vec![String::from("id"), String::from("name")],
                 foreign_table_name: String::from("column"),
+                on_delete: None,
                 on_update: Some(String::from("CASCADE")),
             }
             .to_string()
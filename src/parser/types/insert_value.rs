@@ -1,4 +1,7 @@
-use crate::parser::Rule;
+use crate::parser::{
+    parse_utils::{escape_str, strip_quotes, unescape_str},
+    Rule,
+};
 use pest::iterators::Pair;
 use std::fmt::{Display, Formatter, Result as FmtResult};
 
@@ -6,9 +9,47 @@ use std::fmt::{Display, Formatter, Result as FmtResult};
 pub enum InsertValue {
     Null,
     Default,
-    Text { value: String },
-    Number { value: String },
-    Identifier { value: String },
+    Text {
+        value: String,
+    },
+    Number {
+        value: String,
+    },
+    Identifier {
+        value: String,
+    },
+    /// A string after a character set introducer, such as `_binary 'abc'`.
+    Introduced {
+        charset: String,
+        value: String,
+    },
+    /// A hexadecimal or bit-value literal such as `X'616263'`, kept as
+    /// written.
+    Literal {
+        value: String,
+    },
+}
+
+impl InsertValue {
+    /// Build a text value from an unescaped string.
+    pub fn text(value: &str) -> Self {
+        Self::Text {
+            value: escape_str(value),
+        }
+    }
+
+    /// The value as it would be read back by the server, or `None` for
+    /// `NULL` and `DEFAULT`.
+    pub fn unescaped(&self) -> Option<String> {
+        match self {
+            Self::Null | Self::Default => None,
+            Self::Text { value } => Some(unescape_str(value)),
+            Self::Introduced { value, .. } => Some(unescape_str(value)),
+            Self::Number { value } | Self::Identifier { value } | Self::Literal { value } => {
+                Some(value.clone())
+            }
+        }
+    }
 }
 
 impl From<Pair<'_, Rule>> for InsertValue {
@@ -24,7 +65,7 @@ impl From<Pair<'_, Rule>> for InsertValue {
 
             match inner.as_rule() {
                 Rule::STRING_LITERAL => Self::Text {
-                    value: inner.as_str().trim_matches('\'').to_string(),
+                    value: strip_quotes(inner.as_str()).to_string(),
                 },
                 Rule::NUMBER => Self::Number {
                     value: inner.as_str().to_string(),
@@ -32,7 +73,17 @@ impl From<Pair<'_, Rule>> for InsertValue {
                 Rule::IDENTIFIER => Self::Identifier {
                     value: inner.as_str().to_string(),
                 },
-                other => panic!("Expected STRING_LITERAL, NUMBER or IDENTIFIER, not {other:?}"),
+                Rule::INTRODUCED_STRING => {
+                    let mut parts = inner.into_inner();
+                    Self::Introduced {
+                        charset: parts.next().unwrap().as_str().to_string(),
+                        value: strip_quotes(parts.next().unwrap().as_str()).to_string(),
+                    }
+                }
+                Rule::HEX_BIT_LITERAL => Self::Literal {
+                    value: inner.as_str().to_string(),
+                },
+                other => panic!("Expected STRING_LITERAL, INTRODUCED_STRING, HEX_BIT_LITERAL, NUMBER or IDENTIFIER, not {other:?}"),
             }
         }
     }
@@ -46,6 +97,8 @@ impl Display for InsertValue {
             Self::Text { value } => write!(f, "'{value}'"),
             Self::Number { value } => write!(f, "{value}"),
             Self::Identifier { value } => write!(f, "{value}"),
+            Self::Introduced { charset, value } => write!(f, "{charset} '{value}'"),
+            Self::Literal { value } => write!(f, "{value}"),
         }
     }
 }
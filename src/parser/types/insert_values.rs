@@ -7,7 +7,7 @@ pub struct InsertValues(pub Vec<InsertValue>);
 
 impl From<Pair<'_, Rule>> for InsertValues {
     fn from(pair: Pair<'_, Rule>) -> Self {
-        Self(pair.into_inner().map(|p| InsertValue::from(p)).collect())
+        Self(pair.into_inner().map(InsertValue::from).collect())
     }
 }
 
@@ -20,7 +20,7 @@ impl Display for InsertValues {
                 write!(f, ", ")?;
             }
 
-            write!(f, "{}", value.to_string())?;
+            write!(f, "{}", value)?;
         }
 
         write!(f, ")")
@@ -59,6 +59,31 @@ mod test {
         }
     }
 
+    #[test]
+    fn can_parse_binary_literals() {
+        let input = "(1,_binary 'a\\'b',X'616263', b'0101',0x1F)";
+        let insert_values = InsertValues::from(
+            MySqlParser::parse(Rule::INSERT_VALUES, input)
+                .expect("Invalid input")
+                .next()
+                .expect("Unable to parse input"),
+        );
+
+        match insert_values.0.get(1).unwrap() {
+            InsertValue::Introduced { charset, value } => {
+                assert_eq!((charset.as_str(), value.as_str()), ("_binary", "a\\'b"))
+            }
+            _ => panic!("Expected value 1 to be an introduced string"),
+        }
+        assert_eq!(insert_values.0[1].unescaped().as_deref(), Some("a'b"));
+        assert!(matches!(insert_values.0[2], InsertValue::Literal { .. }));
+        assert!(matches!(insert_values.0[3], InsertValue::Literal { .. }));
+        assert_eq!(
+            insert_values.to_string(),
+            "(1, _binary 'a\\'b', X'616263', b'0101', 0x1F)"
+        );
+    }
+
     #[test]
     fn can_write_insert_values() {
         assert_eq!(
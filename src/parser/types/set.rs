@@ -96,7 +96,7 @@ impl Display for Set {
         let s = self
             .kv_pairs
             .iter()
-            .map(|kv| format!("{}={}", kv.key, SetValue::from(kv.value.clone())))
+            .map(|kv| format!("{}={}", kv.key, kv.value))
             .collect::<Vec<String>>();
 
         write!(f, "SET {}", s.join(", "))
@@ -32,15 +32,14 @@ impl From<Pair<'_, Rule>> for Update {
         let mut update_sets: Vec<Assignment> = Vec::new();
         let mut where_clauses: Vec<Where> = Vec::new();
 
-        while let Some(pair) = inner.next() {
+        for pair in inner {
             match pair.as_rule() {
                 Rule::ASSIGNMENT_CLAUSE => {
                     let set_clause = Assignment::from(pair);
                     update_sets.push(set_clause);
                 }
                 Rule::WHERE_CLAUSE => {
-                    let where_clause = Where::from(pair);
-                    where_clauses.push(where_clause);
+                    where_clauses.extend(Where::conditions(pair));
                 }
                 _ => {}
             }
@@ -61,18 +60,25 @@ impl Display for Update {
 
         write!(
             f,
-            r#"UPDATE `{table_name}` SET {set_clauses} WHERE {where_clauses};"#,
+            r#"UPDATE `{table_name}` SET {set_clauses}{where_clauses};"#,
             table_name = table_name,
             set_clauses = set_clauses
                 .into_iter()
                 .map(|a| a.to_string())
                 .collect::<Vec<String>>()
                 .join(","),
-            where_clauses = where_clauses
-                .into_iter()
-                .map(|a| a.to_string())
-                .collect::<Vec<String>>()
-                .join(" "),
+            where_clauses = if where_clauses.is_empty() {
+                "".to_string()
+            } else {
+                format!(
+                    " WHERE {}",
+                    where_clauses
+                        .into_iter()
+                        .map(|a| a.to_string())
+                        .collect::<Vec<String>>()
+                        .join(" ")
+                )
+            },
         )
     }
 }
@@ -93,4 +99,16 @@ mod tests {
 
         assert_eq!(update.to_string(), sql);
     }
+
+    #[test]
+    fn test_can_parse_update_with_several_conditions() {
+        for sql in [
+            "UPDATE `users` SET `name` = 'John',`email` = NULL WHERE `id` = 1 AND `name` != 'Jo';",
+            "UPDATE `users` SET `name` = 'John';",
+        ] {
+            let mut parsed = MySqlParser::parse(Rule::UPDATE_STATEMENT, sql).unwrap();
+            let update = Update::from(parsed.next().unwrap());
+            assert_eq!(update.to_string(), sql);
+        }
+    }
 }
@@ -2,32 +2,87 @@ use crate::parser::Rule;
 use pest::iterators::Pair;
 use std::fmt::{Display, Formatter, Result as FmtResult};
 
+/// A single condition of a `WHERE` clause.
 #[derive(Debug, Clone)]
 pub struct Where {
+    /// The `AND` or `OR` joining this condition to the previous one.
+    pub connector: Option<String>,
     pub column: String,
     pub operator: String,
+    /// The value as written, with the quotes of a string literal. Empty for
+    /// `IS NULL` and `IS NOT NULL`.
     pub value: String,
+    /// The elements of a `(...)` list value, as written.
+    pub values: Vec<String>,
 }
 
-impl From<Pair<'_, Rule>> for Where {
-    fn from(pair: Pair<'_, Rule>) -> Self {
-        let mut inner = pair.into_inner();
-        let mut inner_pair = inner.next().unwrap().into_inner();
-        let column = inner_pair.next().unwrap().as_str().to_string();
-        let operator = inner_pair.next().unwrap().as_str().to_string();
-        let value = inner_pair.next().unwrap().as_str().to_string();
+impl Where {
+    /// Every condition of a `WHERE` clause, in the order they are written.
+    pub fn conditions(pair: Pair<'_, Rule>) -> Vec<Self> {
+        let mut conditions = Vec::new();
+
+        for (position, pair) in pair
+            .into_inner()
+            .filter(|pair| pair.as_rule() == Rule::CONDITION)
+            .enumerate()
+        {
+            let connector = (position > 0).then(|| "AND".to_string());
+            conditions.extend(Self::flatten(pair, connector));
+        }
 
-        Self {
+        conditions
+    }
+
+    fn flatten(pair: Pair<'_, Rule>, connector: Option<String>) -> Vec<Self> {
+        let mut inner = pair.into_inner().peekable();
+        let column = inner.next().unwrap().as_str().to_string();
+        let operator = inner.next().unwrap().as_str().to_string();
+        let value = inner.next_if(|pair| pair.as_rule() != Rule::LOGICAL_OPERATOR);
+        let values = value
+            .iter()
+            .filter(|pair| pair.as_rule() == Rule::VALUE_LIST)
+            .flat_map(|pair| pair.clone().into_inner())
+            .map(|pair| pair.as_str().to_string())
+            .collect();
+        let mut conditions = vec![Self {
+            connector,
             column,
             operator,
-            value,
+            value: value
+                .map(|pair| pair.as_str().to_string())
+                .unwrap_or_default(),
+            values,
+        }];
+
+        while let (Some(operator), Some(condition)) = (inner.next(), inner.next()) {
+            conditions.extend(Self::flatten(
+                condition,
+                Some(operator.as_str().to_uppercase()),
+            ));
         }
+
+        conditions
+    }
+}
+
+/// The first condition of a `WHERE` clause, see [`Where::conditions`] for
+/// all of them.
+impl From<Pair<'_, Rule>> for Where {
+    fn from(pair: Pair<'_, Rule>) -> Self {
+        Self::conditions(pair).remove(0)
     }
 }
 
 impl Display for Where {
     fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
-        write!(f, "{} {} {}", self.column, self.operator, self.value)
+        if let Some(ref connector) = self.connector {
+            write!(f, "{connector} ")?;
+        }
+        write!(f, "{} {}", self.column, self.operator)?;
+        if !self.value.is_empty() {
+            write!(f, " {}", self.value)?;
+        }
+        Ok(())
     }
 }
 
@@ -57,6 +112,43 @@ mod tests {
         assert_eq!(where_stmt.value, "true");
     }
 
+    #[test]
+    fn test_with_several_conditions() {
+        let sql = "WHERE `email` = 'a@b.c' and id <= 3 OR name <> 'x'";
+        let mut parsed = MySqlParser::parse(Rule::WHERE_CLAUSE, sql).unwrap();
+        let conditions = Where::conditions(parsed.next().unwrap());
+
+        assert_eq!(
+            conditions
+                .iter()
+                .map(|condition| condition.to_string())
+                .collect::<Vec<String>>(),
+            vec!["`email` = 'a@b.c'", "AND id <= 3", "OR name <> 'x'"]
+        );
+    }
+
+    #[test]
+    fn test_with_null_checks_and_lists() {
+        let sql = "WHERE deleted_at IS NULL AND role in ('a', 'b') OR name is not null";
+        let mut parsed = MySqlParser::parse(Rule::WHERE_CLAUSE, sql).unwrap();
+        let conditions = Where::conditions(parsed.next().unwrap());
+
+        assert_eq!(
+            conditions
+                .iter()
+                .map(|condition| condition.to_string())
+                .collect::<Vec<String>>(),
+            vec![
+                "deleted_at IS NULL",
+                "AND role in ('a', 'b')",
+                "OR name is not null"
+            ]
+        );
+        assert_eq!(conditions[0].value, "");
+        assert_eq!(conditions[1].values, ["'a'", "'b'"]);
+        assert!(conditions[0].values.is_empty());
+    }
+
     #[test]
     fn test_with_greater_than() {
         let sql = "WHERE id > 1";
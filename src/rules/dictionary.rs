@@ -0,0 +1,220 @@
+use std::{collections::HashMap, fs, path::Path, sync::Arc};
+
+use anyhow::{bail, Context};
+
+use crate::ExtractResult;
+
+/// The values of a dictionary file: the lines of a text file, or a column
+/// of a `.csv` file, the first one unless `column` names another in its
+/// header row.
+///
+/// Blank lines are skipped, as are lines starting with `#` in text files.
+pub fn read_dictionary(path: &str, column: Option<&str>) -> ExtractResult<Vec<String>> {
+    let text =
+        fs::read_to_string(path).with_context(|| format!("unable to read dictionary `{path}`"))?;
+
+    let values = match is_csv(path) {
+        true => {
+            let mut records = csv_records(&text)
+                .with_context(|| format!("invalid CSV in `{path}`"))?
+                .into_iter();
+            let header = records.next().unwrap_or_default();
+            let index = match column {
+                Some(column) => header
+                    .iter()
+                    .position(|name| name.trim().eq_ignore_ascii_case(column))
+                    .with_context(|| format!("`{path}` has no column `{column}`"))?,
+                None => 0,
+            };
+            records
+                .filter_map(|mut record| (index < record.len()).then(|| record.swap_remove(index)))
+                .filter(|value| !value.is_empty())
+                .collect::<Vec<String>>()
+        }
+        false if column.is_some() => bail!("`{path}` is not a CSV file with columns"),
+        false => text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::to_string)
+            .collect(),
+    };
+
+    if values.is_empty() {
+        bail!("dictionary `{path}` has no values");
+    }
+    Ok(values)
+}
+
+/// Replacements by original value, read from the first two columns of a
+/// `.csv` file with a header row.
+#[derive(Debug, Clone)]
+pub struct Lookup {
+    path: String,
+    replacements: Arc<HashMap<String, String>>,
+}
+
+impl Lookup {
+    pub fn load(path: &str) -> ExtractResult<Self> {
+        if !is_csv(path) {
+            bail!("lookup file `{path}` is not a CSV file");
+        }
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("unable to read lookup file `{path}`"))?;
+
+        let mut replacements = HashMap::new();
+        for (line, record) in csv_records(&text)
+            .with_context(|| format!("invalid CSV in `{path}`"))?
+            .into_iter()
+            .enumerate()
+            .skip(1)
+        {
+            let [original, replacement, ..] = record.as_slice() else {
+                bail!(
+                    "row {} of `{path}` needs an original and a replacement",
+                    line + 1
+                );
+            };
+            if replacements
+                .insert(original.clone(), replacement.clone())
+                .is_some()
+            {
+                bail!(
+                    "row {} of `{path}` maps `{original}` a second time",
+                    line + 1
+                );
+            }
+        }
+
+        Ok(Self {
+            path: path.to_string(),
+            replacements: Arc::new(replacements),
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn get(&self, original: &str) -> Option<&str> {
+        self.replacements.get(original).map(String::as_str)
+    }
+}
+
+fn is_csv(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("csv"))
+}
+
+/// The records of a CSV file, with fields in double quotes where they hold
+/// commas, quotes or line breaks. Blank lines are skipped.
+fn csv_records(text: &str) -> ExtractResult<Vec<Vec<String>>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            ('"', true) => quoted = false,
+            ('"', false) if field.is_empty() => quoted = true,
+            (',', false) => record.push(std::mem::take(&mut field)),
+            ('\r', false) if chars.peek() == Some(&'\n') => {}
+            ('\n', false) => {
+                record.push(std::mem::take(&mut field));
+                if record.iter().any(|field| !field.is_empty()) {
+                    records.push(std::mem::take(&mut record));
+                } else {
+                    record.clear();
+                }
+            }
+            (c, _) => field.push(c),
+        }
+    }
+    if quoted {
+        bail!("unclosed quote");
+    }
+    record.push(field);
+    if record.iter().any(|field| !field.is_empty()) {
+        records.push(record);
+    }
+
+    Ok(records)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn file(extension: &str, contents: &str) -> tempfile::NamedTempFile {
+        let file = tempfile::Builder::new()
+            .suffix(extension)
+            .tempfile()
+            .unwrap();
+        fs::write(file.path(), contents).unwrap();
+        file
+    }
+
+    #[test]
+    fn can_read_csv_records() {
+        assert_eq!(
+            csv_records("a,\"b, \"\"c\"\"\"\r\n\n\"multi\nline\",\n").unwrap(),
+            vec![
+                vec!["a".to_string(), "b, \"c\"".to_string()],
+                vec!["multi\nline".to_string(), String::new()],
+            ]
+        );
+        assert!(csv_records("\"open").is_err());
+    }
+
+    #[test]
+    fn can_read_dictionaries() {
+        let text = file(".txt", "# departments\nSales\n\n  Finance \n");
+        let path = text.path().to_str().unwrap();
+        assert_eq!(
+            read_dictionary(path, None).unwrap(),
+            vec!["Sales", "Finance"]
+        );
+        assert!(read_dictionary(path, Some("name")).is_err());
+
+        let csv = file(
+            ".csv",
+            "code,name\nP-1,Widget\nP-2,\"Gadget, large\"\nP-3\n",
+        );
+        let path = csv.path().to_str().unwrap();
+        assert_eq!(
+            read_dictionary(path, None).unwrap(),
+            vec!["P-1", "P-2", "P-3"]
+        );
+        assert_eq!(
+            read_dictionary(path, Some("NAME")).unwrap(),
+            vec!["Widget", "Gadget, large"]
+        );
+        assert!(read_dictionary(path, Some("price")).is_err());
+        assert!(read_dictionary(file(".txt", "# none\n").path().to_str().unwrap(), None).is_err());
+        assert!(read_dictionary("/nonexistent.txt", None).is_err());
+    }
+
+    #[test]
+    fn can_load_lookups() {
+        let csv = file(".csv", "original,replacement\nC-1,T-100\nC-2,T-200\n");
+        let lookup = Lookup::load(csv.path().to_str().unwrap()).unwrap();
+        assert_eq!(lookup.get("C-2"), Some("T-200"));
+        assert_eq!(lookup.get("C-3"), None);
+
+        for contents in ["a,b\nC-1,T\nC-1,U\n", "a,b\nC-1\n"] {
+            let csv = file(".csv", contents);
+            assert!(
+                Lookup::load(csv.path().to_str().unwrap()).is_err(),
+                "{contents}"
+            );
+        }
+        assert!(Lookup::load(file(".txt", "a,b\n").path().to_str().unwrap()).is_err());
+    }
+}
@@ -0,0 +1,327 @@
+use std::{
+    collections::HashMap,
+    fmt::{Display, Formatter, Result as FmtResult},
+    str::FromStr,
+};
+
+use anyhow::{anyhow, bail, Context};
+use pest::{iterators::Pair, Parser};
+use pest_derive::Parser;
+
+use crate::ExtractResult;
+
+#[derive(Parser)]
+#[grammar = "rules/expression.pest"]
+struct ExpressionParser;
+
+/// An argument value, as written in a rule.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    /// A quoted string, without its quotes and escapes.
+    String(String),
+    Number(String),
+    /// Anything else written without quotes.
+    Word(String),
+}
+
+impl Value {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::String(value) | Self::Number(value) | Self::Word(value) => value,
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::String(value) => {
+                write!(f, "'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+            }
+            Self::Number(value) | Self::Word(value) => write!(f, "{value}"),
+        }
+    }
+}
+
+impl From<Pair<'_, Rule>> for Value {
+    fn from(pair: Pair<'_, Rule>) -> Self {
+        let pair = pair.into_inner().next().unwrap();
+        match pair.as_rule() {
+            Rule::string => {
+                let mut escaped = false;
+                let value = pair
+                    .into_inner()
+                    .next()
+                    .unwrap()
+                    .as_str()
+                    .chars()
+                    .filter(|c| {
+                        let keep = escaped || *c != '\\';
+                        escaped = !escaped && *c == '\\';
+                        keep
+                    })
+                    .collect();
+                Self::String(value)
+            }
+            Rule::number => Self::Number(pair.as_str().to_string()),
+            _ => Self::Word(pair.as_str().to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Argument {
+    /// The parameter the argument is given for, unless it is positional.
+    pub name: Option<String>,
+    pub value: Value,
+}
+
+impl From<Pair<'_, Rule>> for Argument {
+    fn from(pair: Pair<'_, Rule>) -> Self {
+        let pair = pair.into_inner().next().unwrap();
+        match pair.as_rule() {
+            Rule::named_argument => {
+                let mut inner = pair.into_inner();
+                Self {
+                    name: Some(inner.next().unwrap().as_str().to_string()),
+                    value: Value::from(inner.next().unwrap()),
+                }
+            }
+            _ => Self {
+                name: None,
+                value: Value::from(pair),
+            },
+        }
+    }
+}
+
+/// A masking rule as written in the config: a function, optionally in a
+/// module, called with positional or named arguments, e.g.
+/// `number::between(1, 100)` or `datetime::date(min='2000-01-01')`.
+///
+/// The parentheses can be left out when there are no arguments.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleExpression {
+    pub module: Option<String>,
+    pub function: String,
+    pub arguments: Vec<Argument>,
+}
+
+impl RuleExpression {
+    /// The function, with its module when it has one.
+    pub fn path(&self) -> String {
+        match self.module {
+            Some(ref module) => format!("{module}::{}", self.function),
+            None => self.function.clone(),
+        }
+    }
+
+    /// The arguments by the name of their parameter, taking positional
+    /// arguments in the order of `parameters`.
+    ///
+    /// Fails on positional arguments after named ones, on arguments for
+    /// parameters the rule does not have, and on arguments given twice.
+    pub fn arguments(&self, parameters: &[&str]) -> ExtractResult<Arguments> {
+        let path = self.path();
+        let mut values = HashMap::new();
+        let mut named = false;
+
+        for (position, argument) in self.arguments.iter().enumerate() {
+            let parameter = match argument.name {
+                Some(ref name) => {
+                    named = true;
+                    parameters
+                        .iter()
+                        .find(|parameter| parameter.eq_ignore_ascii_case(name))
+                        .with_context(|| match parameters {
+                            [] => format!("`{path}` takes no arguments"),
+                            _ => format!(
+                                "`{path}` has no parameter `{name}`, expected {}",
+                                parameters.join(", ")
+                            ),
+                        })?
+                }
+                None if named => bail!("positional argument after named ones in `{path}`"),
+                None => parameters.get(position).with_context(|| match parameters {
+                    [] => format!("`{path}` takes no arguments"),
+                    _ => format!("`{path}` takes at most {} arguments", parameters.len()),
+                })?,
+            };
+
+            if values
+                .insert(parameter.to_string(), argument.value.clone())
+                .is_some()
+            {
+                bail!("`{parameter}` is given twice to `{path}`");
+            }
+        }
+
+        Ok(Arguments { path, values })
+    }
+}
+
+impl FromStr for RuleExpression {
+    type Err = anyhow::Error;
+
+    fn from_str(expression: &str) -> ExtractResult<Self> {
+        let pair = ExpressionParser::parse(Rule::expression, expression.trim())
+            .map_err(|err| {
+                let column = match err.line_col {
+                    pest::error::LineColLocation::Pos((_, column)) => column,
+                    pest::error::LineColLocation::Span((_, column), _) => column,
+                };
+                anyhow!(
+                    "unable to parse rule `{}` at column {column}",
+                    expression.trim()
+                )
+            })?
+            .next()
+            .unwrap();
+
+        let mut inner = pair.into_inner();
+        let mut path = inner.next().unwrap().into_inner();
+        let first = path.next().unwrap().as_str().to_string();
+        let (module, function) = match path.next() {
+            Some(function) => (Some(first), function.as_str().to_string()),
+            None => (None, first),
+        };
+        let arguments = inner
+            .next()
+            .filter(|pair| pair.as_rule() == Rule::arguments)
+            .map(|pair| pair.into_inner().map(Argument::from).collect())
+            .unwrap_or_default();
+
+        Ok(Self {
+            module,
+            function,
+            arguments,
+        })
+    }
+}
+
+impl Display for RuleExpression {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let arguments = self
+            .arguments
+            .iter()
+            .map(|argument| match argument.name {
+                Some(ref name) => format!("{name}={}", argument.value),
+                None => argument.value.to_string(),
+            })
+            .collect::<Vec<String>>();
+        write!(f, "{}({})", self.path(), arguments.join(", "))
+    }
+}
+
+/// The arguments of a rule, by parameter, see [`RuleExpression::arguments`].
+#[derive(Debug)]
+pub struct Arguments {
+    path: String,
+    values: HashMap<String, Value>,
+}
+
+impl Arguments {
+    pub fn string(&self, parameter: &str) -> Option<String> {
+        self.values
+            .get(parameter)
+            .map(|value| value.as_str().to_string())
+    }
+
+    pub fn number<T: FromStr>(&self, parameter: &str) -> ExtractResult<Option<T>> {
+        self.values
+            .get(parameter)
+            .map(|value| {
+                value.as_str().parse::<T>().map_err(|_| {
+                    anyhow!(
+                        "`{}` needs a number for `{parameter}`, not `{}`",
+                        self.path,
+                        value
+                    )
+                })
+            })
+            .transpose()
+    }
+
+    /// Fail when `parameter` is missing, with `example` as a hint.
+    pub fn require(&self, parameter: &str, example: &str) -> ExtractResult<()> {
+        if !self.values.contains_key(parameter) {
+            bail!("`{}` needs a {parameter}, e.g. `{example}`", self.path);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn can_parse_rule_expressions() {
+        let expression = "number::between(1, max = -2.5)"
+            .parse::<RuleExpression>()
+            .unwrap();
+        assert_eq!(expression.module.as_deref(), Some("number"));
+        assert_eq!(expression.function, "between");
+        assert_eq!(
+            expression.arguments,
+            vec![
+                Argument {
+                    name: None,
+                    value: Value::Number("1".to_string())
+                },
+                Argument {
+                    name: Some("max".to_string()),
+                    value: Value::Number("-2.5".to_string())
+                },
+            ]
+        );
+
+        let cases = [
+            ("email", "email()"),
+            (" contact::email() ", "contact::email()"),
+            ("constant(n/a)", "constant(n/a)"),
+            ("string::pattern('###-??')", "string::pattern('###-??')"),
+            (r#"constant("it's")"#, r"constant('it\'s')"),
+            (r"constant('a\\b')", r"constant('a\\b')"),
+            ("truncate( 8 )", "truncate(8)"),
+        ];
+        for (expression, expected) in cases {
+            let parsed = expression.parse::<RuleExpression>().unwrap();
+            assert_eq!(parsed.to_string(), expected);
+            assert_eq!(parsed, expected.parse().unwrap());
+        }
+
+        for invalid in [
+            "",
+            "contact::",
+            "a::b::c",
+            "email(",
+            "email('x)",
+            "f(1abc=2)",
+            "f(x) y",
+        ] {
+            assert!(invalid.parse::<RuleExpression>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn can_bind_arguments_to_parameters() {
+        let expression = "datetime::date('2000-01-01', max='2010-12-31')"
+            .parse::<RuleExpression>()
+            .unwrap();
+        let arguments = expression.arguments(&["min", "max"]).unwrap();
+        assert_eq!(arguments.string("min").unwrap(), "2000-01-01");
+        assert_eq!(arguments.string("max").unwrap(), "2010-12-31");
+        assert!(arguments.number::<i64>("min").is_err());
+        assert!(arguments.require("min", "").is_ok());
+
+        assert!(expression.arguments(&["min"]).is_err());
+        assert!(expression.arguments(&["max", "min"]).is_err());
+        assert!(expression.arguments(&[]).is_err());
+        assert!("f(a=1, 2)"
+            .parse::<RuleExpression>()
+            .unwrap()
+            .arguments(&["a", "b"])
+            .is_err());
+    }
+}
@@ -0,0 +1,475 @@
+use std::{
+    collections::HashMap,
+    fmt::{Display, Formatter, Result as FmtResult},
+    sync::Mutex,
+};
+
+use aes::{
+    cipher::{consts::U16, BlockEncrypt, BlockSizeUser, KeyInit},
+    Aes256,
+};
+use anyhow::{bail, Context};
+use lazy_static::lazy_static;
+use sha2::{Digest, Sha256};
+
+use crate::ExtractResult;
+
+use super::derive_key;
+
+/// The smallest domain NIST allows format-preserving encryption on.
+const MIN_DOMAIN: u128 = 1_000_000;
+/// The largest half of a numeral string we do arithmetic on, leaving room
+/// for adding two of them in a `u128`.
+const MAX_HALF_DOMAIN: u128 = 1 << 120;
+
+/// The salt of the keys derived for FF1 and FF3-1, which sets them apart
+/// from the other keys derived from the masking key.
+const KEY_LABEL: &[u8] = b"sqlex fpe";
+
+lazy_static! {
+    /// The AES-256 keys derived so far, by masking key, as PBKDF2 is too
+    /// slow to run for every value.
+    static ref KEYS: Mutex<HashMap<String, [u8; 32]>> = Mutex::new(HashMap::new());
+}
+
+/// AES of any key size, encrypting the single blocks FF1 and FF3-1 need.
+trait Aes: BlockEncrypt + BlockSizeUser<BlockSize = U16> {
+    fn encrypt(&self, block: &[u8; 16]) -> [u8; 16] {
+        let mut block = (*block).into();
+        self.encrypt_block(&mut block);
+        block.into()
+    }
+}
+
+impl<C: BlockEncrypt + BlockSizeUser<BlockSize = U16>> Aes for C {}
+
+/// The format-preserving encryption modes of NIST SP 800-38G.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FpeMode {
+    Ff1,
+    Ff3_1,
+}
+
+impl FpeMode {
+    pub fn parse(mode: &str) -> ExtractResult<Self> {
+        match mode.to_ascii_lowercase().replace('_', "-").as_str() {
+            "ff1" => Ok(Self::Ff1),
+            "ff3-1" | "ff31" => Ok(Self::Ff3_1),
+            _ => bail!("unknown fpe mode `{mode}`, expected `ff1` or `ff3-1`"),
+        }
+    }
+}
+
+impl Display for FpeMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Ff1 => write!(f, "ff1"),
+            Self::Ff3_1 => write!(f, "ff3-1"),
+        }
+    }
+}
+
+/// Format-preserving encryption of the digits and the letters of a value,
+/// keeping everything else, so `+49 170 1234567` encrypts to another phone
+/// number and `AB-1234-x` to two capitals, four digits and a small letter.
+///
+/// The digits and the letters are encrypted as two numeral strings, with
+/// the letters ignoring case, and values decrypt with the same key and
+/// tweak. Digits or letters too few to encrypt are kept, as NIST requires
+/// a domain of a million values, but a value needs at least one of them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fpe {
+    pub mode: FpeMode,
+    pub tweak: Option<String>,
+}
+
+#[derive(Clone, Copy)]
+enum Direction {
+    Encrypt,
+    Decrypt,
+}
+
+impl Fpe {
+    /// Encrypt `value` with an AES-256 key derived from `key` with PBKDF2.
+    pub fn encrypt(&self, key: &str, value: &str) -> ExtractResult<String> {
+        self.transform(key, value, Direction::Encrypt)
+    }
+
+    pub fn decrypt(&self, key: &str, value: &str) -> ExtractResult<String> {
+        self.transform(key, value, Direction::Decrypt)
+    }
+
+    fn transform(&self, key: &str, value: &str, direction: Direction) -> ExtractResult<String> {
+        let key = aes_key(key);
+        let aes = Aes256::new(&key.into());
+        let mut chars = value.chars().collect::<Vec<char>>();
+        let mut transformed = false;
+
+        for (class, radix) in [("digits", 10), ("letters", 26)] {
+            let positions = (0..chars.len())
+                .filter(|&i| match radix {
+                    10 => chars[i].is_ascii_digit(),
+                    _ => chars[i].is_ascii_alphabetic(),
+                })
+                .collect::<Vec<usize>>();
+            let numerals = positions
+                .iter()
+                .map(|&i| match radix {
+                    10 => chars[i] as u8 - b'0',
+                    _ => chars[i].to_ascii_lowercase() as u8 - b'a',
+                })
+                .collect::<Vec<u8>>();
+            if domain(radix, numerals.len()).is_some_and(|domain| domain < MIN_DOMAIN)
+                || numerals.is_empty()
+            {
+                continue;
+            }
+
+            let tweak = format!("{class}:{}", self.tweak.as_deref().unwrap_or_default());
+            let numerals = match self.mode {
+                FpeMode::Ff1 => ff1(&aes, radix, tweak.as_bytes(), &numerals, direction),
+                FpeMode::Ff3_1 => {
+                    let digest = Sha256::digest(tweak.as_bytes());
+                    ff3_1(&ff3_aes(key), radix, &digest[..7], &numerals, direction)
+                }
+            }
+            .with_context(|| format!("unable to {} `{value}`", direction.verb()))?;
+
+            for (&i, numeral) in positions.iter().zip(numerals) {
+                chars[i] = match radix {
+                    10 => char::from(b'0' + numeral),
+                    _ if chars[i].is_ascii_uppercase() => char::from(b'A' + numeral),
+                    _ => char::from(b'a' + numeral),
+                };
+            }
+            transformed = true;
+        }
+
+        if !transformed {
+            bail!(
+                "unable to {} `{value}`: it needs at least 6 digits or 5 letters",
+                direction.verb()
+            );
+        }
+        Ok(chars.into_iter().collect())
+    }
+}
+
+impl Direction {
+    fn verb(&self) -> &'static str {
+        match self {
+            Self::Encrypt => "encrypt",
+            Self::Decrypt => "decrypt",
+        }
+    }
+}
+
+/// The AES-256 key of the masking key `key`.
+fn aes_key(key: &str) -> [u8; 32] {
+    let mut keys = KEYS.lock().unwrap_or_else(|err| err.into_inner());
+    *keys
+        .entry(key.to_string())
+        .or_insert_with(|| derive_key(key, KEY_LABEL))
+}
+
+/// FF3-1 reverses the bytes of its key.
+fn ff3_aes(mut key: [u8; 32]) -> Aes256 {
+    key.reverse();
+    Aes256::new(&key.into())
+}
+
+/// `radix` to the power of `length`, `None` when it doesn't fit.
+fn domain(radix: u32, length: usize) -> Option<u128> {
+    u128::from(radix).checked_pow(length.try_into().ok()?)
+}
+
+/// The number a numeral string stands for, most significant numeral first.
+fn num(radix: u32, numerals: &[u8]) -> u128 {
+    numerals.iter().fold(0, |num, &numeral| {
+        num * u128::from(radix) + u128::from(numeral)
+    })
+}
+
+/// `num` as a numeral string of `length` numerals.
+fn numerals(radix: u32, mut num: u128, length: usize) -> Vec<u8> {
+    let mut numerals = vec![0; length];
+    for numeral in numerals.iter_mut().rev() {
+        *numeral = (num % u128::from(radix)) as u8;
+        num /= u128::from(radix);
+    }
+    numerals
+}
+
+/// A big-endian byte string modulo `modulus`, which is at most 2^120.
+fn bytes_mod(bytes: &[u8], modulus: u128) -> u128 {
+    bytes
+        .iter()
+        .fold(0, |rest, &byte| (rest * 256 + u128::from(byte)) % modulus)
+}
+
+/// One Feistel round: `(a + y) mod modulus` to encrypt, `(a - y) mod
+/// modulus` to decrypt.
+fn combine(a: u128, y: u128, modulus: u128, direction: Direction) -> u128 {
+    match direction {
+        Direction::Encrypt => (a + y) % modulus,
+        Direction::Decrypt => (a + modulus - y) % modulus,
+    }
+}
+
+fn xor(left: &[u8; 16], right: &[u8; 16]) -> [u8; 16] {
+    std::array::from_fn(|i| left[i] ^ right[i])
+}
+
+/// FF1 of NIST SP 800-38G.
+fn ff1(
+    aes: &impl Aes,
+    radix: u32,
+    tweak: &[u8],
+    numerals: &[u8],
+    direction: Direction,
+) -> ExtractResult<Vec<u8>> {
+    let n = numerals.len();
+    let u = n / 2;
+    let v = n - u;
+    let (modulus_u, modulus_v) = match (domain(radix, u), domain(radix, v)) {
+        (Some(modulus_u), Some(modulus_v)) if modulus_v <= MAX_HALF_DOMAIN => {
+            (modulus_u, modulus_v)
+        }
+        _ => bail!("{n} numerals are too many for ff1"),
+    };
+    // Bytes to hold a half: ceil(ceil(v * log2(radix)) / 8)
+    let bits = 128 - (modulus_v - 1).leading_zeros() as usize;
+    let b = bits.div_ceil(8);
+    let d = 4 * b.div_ceil(4) + 4;
+
+    let mut p = vec![1, 2, 1];
+    p.extend_from_slice(&radix.to_be_bytes()[1..]);
+    p.extend_from_slice(&[10, (u % 256) as u8]);
+    p.extend_from_slice(&(n as u32).to_be_bytes());
+    p.extend_from_slice(&(tweak.len() as u32).to_be_bytes());
+    let p: [u8; 16] = p.try_into().unwrap();
+    let prefix = aes.encrypt(&p);
+
+    let round = |i: usize, half: &[u8]| {
+        let mut q = tweak.to_vec();
+        q.resize(tweak.len() + (16 - (tweak.len() + b + 1) % 16) % 16, 0);
+        q.push(i as u8);
+        q.extend_from_slice(&num(radix, half).to_be_bytes()[16 - b..]);
+
+        // CBC-MAC of P || Q, starting from the encrypted P
+        let r = q.chunks(16).fold(prefix, |r, block| {
+            aes.encrypt(&xor(&r, block.try_into().unwrap()))
+        });
+        let mut s = r.to_vec();
+        for j in 1..d.div_ceil(16) {
+            let mut counter = [0u8; 16];
+            counter[8..].copy_from_slice(&(j as u64).to_be_bytes());
+            s.extend_from_slice(&aes.encrypt(&xor(&r, &counter)));
+        }
+        s.truncate(d);
+        s
+    };
+
+    let (mut a, mut b) = (numerals[..u].to_vec(), numerals[u..].to_vec());
+    for step in 0..10 {
+        let i = match direction {
+            Direction::Encrypt => step,
+            Direction::Decrypt => 9 - step,
+        };
+        let (m, modulus) = match i % 2 {
+            0 => (u, modulus_u),
+            _ => (v, modulus_v),
+        };
+        match direction {
+            Direction::Encrypt => {
+                let y = bytes_mod(&round(i, &b), modulus);
+                let c = combine(num(radix, &a), y, modulus, direction);
+                a = std::mem::replace(&mut b, self::numerals(radix, c, m));
+            }
+            Direction::Decrypt => {
+                let y = bytes_mod(&round(i, &a), modulus);
+                let c = combine(num(radix, &b), y, modulus, direction);
+                b = std::mem::replace(&mut a, self::numerals(radix, c, m));
+            }
+        }
+    }
+
+    a.extend(b);
+    Ok(a)
+}
+
+/// FF3-1 of NIST SP 800-38G Rev. 1, with a 56-bit tweak. The key is
+/// reversed already.
+fn ff3_1(
+    aes: &impl Aes,
+    radix: u32,
+    tweak: &[u8],
+    numerals: &[u8],
+    direction: Direction,
+) -> ExtractResult<Vec<u8>> {
+    let left = [tweak[0], tweak[1], tweak[2], tweak[3] & 0xf0];
+    let right = [tweak[4], tweak[5], tweak[6], (tweak[3] & 0x0f) << 4];
+    ff3(aes, radix, left, right, numerals, direction)
+}
+
+/// FF3 with the tweak split into its halves, which is all FF3-1 changes.
+fn ff3(
+    aes: &impl Aes,
+    radix: u32,
+    left: [u8; 4],
+    right: [u8; 4],
+    numerals: &[u8],
+    direction: Direction,
+) -> ExtractResult<Vec<u8>> {
+    let n = numerals.len();
+    let u = n.div_ceil(2);
+    let v = n - u;
+    let (modulus_u, modulus_v) = match (domain(radix, u), domain(radix, v)) {
+        (Some(modulus_u), Some(modulus_v)) if modulus_u <= 1 << 96 => (modulus_u, modulus_v),
+        _ => bail!("{n} numerals are too many for ff3-1"),
+    };
+    let reversed = |numerals: &[u8]| numerals.iter().rev().copied().collect::<Vec<u8>>();
+
+    let round = |i: usize, half: &[u8]| {
+        let w = match i % 2 {
+            0 => right,
+            _ => left,
+        };
+        let mut p = [0u8; 16];
+        p[..4].copy_from_slice(&w);
+        p[3] ^= i as u8;
+        p[4..].copy_from_slice(&num(radix, &reversed(half)).to_be_bytes()[4..]);
+        p.reverse();
+        let mut s = aes.encrypt(&p);
+        s.reverse();
+        u128::from_be_bytes(s)
+    };
+
+    let (mut a, mut b) = (numerals[..u].to_vec(), numerals[u..].to_vec());
+    for step in 0..8 {
+        let i = match direction {
+            Direction::Encrypt => step,
+            Direction::Decrypt => 7 - step,
+        };
+        let (m, modulus) = match i % 2 {
+            0 => (u, modulus_u),
+            _ => (v, modulus_v),
+        };
+        match direction {
+            Direction::Encrypt => {
+                let y = round(i, &b) % modulus;
+                let c = combine(num(radix, &reversed(&a)), y, modulus, direction);
+                a = std::mem::replace(&mut b, reversed(&self::numerals(radix, c, m)));
+            }
+            Direction::Decrypt => {
+                let y = round(i, &a) % modulus;
+                let c = combine(num(radix, &reversed(&b)), y, modulus, direction);
+                b = std::mem::replace(&mut a, reversed(&self::numerals(radix, c, m)));
+            }
+        }
+    }
+
+    a.extend(b);
+    Ok(a)
+}
+
+#[cfg(test)]
+mod test {
+    use aes::Aes128;
+
+    use super::*;
+
+    fn bytes(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn numerals(text: &str) -> Vec<u8> {
+        text.chars()
+            .map(|c| c.to_digit(36).unwrap() as u8)
+            .collect()
+    }
+
+    fn text(numerals: &[u8]) -> String {
+        numerals
+            .iter()
+            .map(|&numeral| char::from_digit(numeral.into(), 36).unwrap())
+            .collect()
+    }
+
+    fn check_ff1(aes: &impl Aes, radix: u32, tweak: &str, plaintext: &str, ciphertext: &str) {
+        let tweak = bytes(tweak);
+        let encrypted = ff1(aes, radix, &tweak, &numerals(plaintext), Direction::Encrypt).unwrap();
+        assert_eq!(text(&encrypted), ciphertext, "{plaintext}");
+        let decrypted = ff1(aes, radix, &tweak, &encrypted, Direction::Decrypt).unwrap();
+        assert_eq!(text(&decrypted), plaintext);
+    }
+
+    #[test]
+    fn can_run_nist_samples() {
+        let aes128 = Aes128::new_from_slice(&bytes("2b7e151628aed2a6abf7158809cf4f3c")).unwrap();
+        let aes256 = Aes256::new_from_slice(&bytes(
+            "2b7e151628aed2a6abf7158809cf4f3cef4359d8d580aa4f7f036d6f04fc6a94",
+        ))
+        .unwrap();
+        check_ff1(&aes128, 10, "", "0123456789", "2433477484");
+        check_ff1(
+            &aes128,
+            10,
+            "39383736353433323130",
+            "0123456789",
+            "6124200773",
+        );
+        check_ff1(
+            &aes128,
+            36,
+            "3737373770717273373737",
+            "0123456789abcdefghi",
+            "a9tv40mll9kdu509eum",
+        );
+        check_ff1(&aes256, 10, "", "0123456789", "6657667009");
+
+        let mut key = bytes("ef4359d8d580aa4f7f036d6f04fc6a94");
+        key.reverse();
+        let aes = Aes128::new_from_slice(&key).unwrap();
+        let tweak = bytes("d8e7920afa330a73");
+        let (left, right) = (
+            tweak[..4].try_into().unwrap(),
+            tweak[4..].try_into().unwrap(),
+        );
+        let plaintext = numerals("890121234567890000");
+        let encrypted = ff3(&aes, 10, left, right, &plaintext, Direction::Encrypt).unwrap();
+        assert_eq!(text(&encrypted), "750918814058654607");
+        let decrypted = ff3(&aes, 10, left, right, &encrypted, Direction::Decrypt).unwrap();
+        assert_eq!(decrypted, plaintext);
+    }
+
+    #[test]
+    fn can_encrypt_values_in_their_format() {
+        for mode in [FpeMode::Ff1, FpeMode::Ff3_1] {
+            let fpe = Fpe {
+                mode,
+                tweak: Some("users.phone".to_string()),
+            };
+            for value in ["+49 170 1234567", "John.Doe@example.com", "AB-123456-x"] {
+                let encrypted = fpe.encrypt("secret", value).unwrap();
+                assert_ne!(encrypted, value);
+                assert_eq!(encrypted, fpe.encrypt("secret", value).unwrap());
+                assert_ne!(encrypted, fpe.encrypt("other", value).unwrap());
+                assert!(encrypted.chars().zip(value.chars()).all(|(e, v)| {
+                    e.is_ascii_digit() == v.is_ascii_digit()
+                        && e.is_ascii_uppercase() == v.is_ascii_uppercase()
+                        && e.is_ascii_lowercase() == v.is_ascii_lowercase()
+                        && (e.is_ascii_alphanumeric() || e == v)
+                }));
+                assert_eq!(fpe.decrypt("secret", &encrypted).unwrap(), value);
+            }
+            assert!(fpe.encrypt("secret", "12-34").is_err());
+        }
+
+        assert_eq!(FpeMode::parse("FF3_1").unwrap(), FpeMode::Ff3_1);
+        assert!(FpeMode::parse("ff2").is_err());
+    }
+}
@@ -0,0 +1,787 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    sync::Mutex,
+};
+
+use anyhow::bail;
+use hmac::{Hmac, Mac};
+use lazy_static::lazy_static;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    masking::{fitting, Builtin, ColumnRef},
+    parser::types::DataType,
+    ExtractResult,
+};
+
+mod dictionary;
+mod expression;
+mod fpe;
+mod perturb;
+mod registry;
+mod remap;
+mod script;
+mod scrub;
+mod template;
+
+pub use dictionary::{read_dictionary, Lookup};
+pub use expression::{Argument, Arguments, RuleExpression, Value};
+pub use fpe::{Fpe, FpeMode};
+pub use perturb::{add_noise, shift_date};
+pub use registry::{registry, registry_mut, FakerRegistry, FnFaker, RegisteredFaker};
+pub use remap::{integer_max, remap_id};
+pub use script::Script;
+pub use scrub::scrub;
+pub use template::Template;
+
+lazy_static! {
+    /// `fakeit` draws from a single global generator, so seeding it and
+    /// generating a value has to happen under one lock.
+    static ref GENERATOR_LOCK: Mutex<()> = Mutex::new(());
+    /// Stands in for the masking key of `shift_date` and `id_remap` when
+    /// there is none, so they stay consistent for the whole run.
+    static ref RUN_KEY: String = uuid::Uuid::new_v4().to_string();
+}
+
+/// Where a masked value comes from, for rules that look beyond the value.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CellContext<'a> {
+    pub column: Option<&'a ColumnRef>,
+    pub data_type: Option<&'a DataType>,
+    /// The original values of the row, by column name, `None` for `NULL`.
+    /// For `UPDATE`s, only the values they assign and compare with `=`.
+    pub row: &'a [(String, Option<String>)],
+    /// The values of the row as masked so far: columns are masked after the
+    /// ones their template refers to.
+    pub masked: &'a [(String, Option<String>)],
+}
+
+pub trait Faking {
+    fn fake(&self) -> String;
+}
+pub trait FromStr: std::fmt::Debug {
+    fn from_str(s: &str) -> Option<Self>
+    where
+        Self: Sized;
+}
+
+pub trait FromStrFaking: FromStr + Faking {}
+
+impl<T: FromStr + Faking> FromStrFaking for T {}
+
+/// The `fakeit` generators, with replacements for the ones that don't draw
+/// from its seedable random number generator.
+mod generators {
+    pub use fakeit::*;
+
+    pub mod unique {
+        pub fn uuid_v4() -> String {
+            let high = fakeit::misc::random::<u64>(0, u64::MAX).to_be_bytes();
+            let low = fakeit::misc::random::<u64>(0, u64::MAX).to_be_bytes();
+            let mut bytes = [0u8; 16];
+            bytes[..8].copy_from_slice(&high);
+            bytes[8..].copy_from_slice(&low);
+
+            uuid::Builder::from_random_bytes(bytes)
+                .into_uuid()
+                .to_string()
+        }
+    }
+}
+
+/// Derive a generator seed from the masking key, the rule and the original
+/// value, so that the same value always masks to the same fake.
+pub fn keyed_seed(key: &str, rule: &str, value: &str) -> u128 {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(key.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(rule.as_bytes());
+    mac.update(&[0]);
+    mac.update(value.as_bytes());
+
+    let digest = mac.finalize().into_bytes();
+    let mut seed = [0u8; 8];
+    seed.copy_from_slice(&digest[..8]);
+
+    // The generator works modulo 2^63 and overflows on larger seeds
+    (u64::from_be_bytes(seed) >> 1) as u128
+}
+
+/// PBKDF2 rounds deriving keys from vault passphrases and masking keys.
+#[cfg(not(test))]
+pub(crate) const KEY_ROUNDS: u32 = 100_000;
+#[cfg(test)]
+pub(crate) const KEY_ROUNDS: u32 = 1_000;
+
+/// A key derived from `secret` with PBKDF2-HMAC-SHA256 and `salt`, which
+/// also keeps the keys derived from one secret for different uses apart.
+pub(crate) fn derive_key<const N: usize>(secret: &str, salt: &[u8]) -> [u8; N] {
+    let mut key = [0u8; N];
+    pbkdf2::pbkdf2_hmac::<Sha256>(secret.as_bytes(), salt, KEY_ROUNDS, &mut key);
+    key
+}
+
+/// The masking key, or else a key made up for the run.
+pub fn run_key(masking_key: Option<&str>) -> &str {
+    masking_key.unwrap_or(&RUN_KEY)
+}
+
+/// The number of days `shift_date` moves the dates of `entity` by, up to
+/// `days` either way but never zero.
+pub fn shift_offset(masking_key: Option<&str>, days: u32, entity: &str) -> i64 {
+    let seed = keyed_seed(run_key(masking_key), "shift_date", entity);
+    let days = i64::from(days);
+    let offset = (seed % (2 * days as u128)) as i64 - days;
+
+    match offset < 0 {
+        true => offset,
+        false => offset + 1,
+    }
+}
+
+/// Generate a value with the generator seeded from `seed`, leaving the
+/// generator's state as it was for everyone else.
+pub fn fake_with_seed<F: Faking + ?Sized>(faker: &F, seed: u128) -> String {
+    let _guard = GENERATOR_LOCK.lock().unwrap_or_else(|err| err.into_inner());
+    let previous = simplerand::seed::<u64>();
+
+    simplerand::set_seed::<u64>(seed);
+    let value = faker.fake();
+    simplerand::set_seed::<u64>(previous);
+
+    value
+}
+
+/// Generate a value from the unseeded generator.
+pub fn fake_random<F: Faking + ?Sized>(faker: &F) -> String {
+    let _guard = GENERATOR_LOCK.lock().unwrap_or_else(|err| err.into_inner());
+    faker.fake()
+}
+
+/// How far `shift_date` moves dates when it isn't told.
+const DEFAULT_SHIFT_DAYS: u32 = 30;
+/// How much `noise` changes numbers when it isn't told.
+const DEFAULT_NOISE_PERCENT: u32 = 10;
+
+/// Masking rules that transform the original value instead of faking a new
+/// one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Strategy {
+    /// Replace the value with `NULL`.
+    SetNull,
+    /// Hex SHA-256 of the value, salted with the given salt, or else with the
+    /// masking key or a key made up for the run.
+    Hash { salt: Option<String> },
+    /// Keep the first character and hide the rest, e.g. `j***@***.com`.
+    Redact,
+    /// Keep the first `n` characters.
+    Truncate(usize),
+    /// Replace every value with the same one.
+    Constant(String),
+    /// Permute the existing values of the column across rows.
+    Shuffle,
+    /// Build the value from other values of the row, see [`Template`].
+    Template(Template),
+    /// Replace each digit and letter with a random one of the same kind and
+    /// case, keeping the first `keep` characters and everything else, such
+    /// as punctuation and spaces.
+    PreserveFormat { keep: usize },
+    /// Encrypt the digits and letters with FF1 or FF3-1 under the masking
+    /// key, see [`Fpe`].
+    Fpe(Fpe),
+    /// Move dates by up to `days` days either way, by the same offset for
+    /// every date of the same `entity`, the value of that column in the row,
+    /// or of the first column without one.
+    ShiftDate { days: u32, entity: Option<String> },
+    /// Scale numbers by a random factor of up to `percent` percent either
+    /// way, fitted to the decimals and range of the column.
+    Noise { percent: u32 },
+    /// Remap integer or UUID keys with a keyed permutation, see
+    /// [`remap_id`]. Columns referencing the key get the same replacements.
+    IdRemap,
+    /// Replace the value with an opaque token, recorded in a token vault so
+    /// it can be restored with `sqlex detokenize`. Only for text columns of
+    /// at least 20 characters, which hold tokens whole, and not inside JSON
+    /// documents.
+    Tokenize,
+    /// Replace the emails, phone numbers and other PII that `detectors`
+    /// find inside free text, with fakes of the same kind or with markers
+    /// such as `[EMAIL]` when `redact`, see [`scrub`].
+    Scrub {
+        detectors: Vec<Builtin>,
+        redact: bool,
+    },
+}
+
+impl Strategy {
+    /// Every strategy with its parameters, as rules call them.
+    pub const SIGNATURES: &'static [&'static str] = &[
+        "constant(value)",
+        "fpe(mode, tweak)",
+        "hash(salt)",
+        "id_remap()",
+        "noise(percent)",
+        "null()",
+        "preserve_format(keep)",
+        "redact()",
+        "scrub(detectors, replace)",
+        "set_null()",
+        "sha256(salt)",
+        "shift_date(days, entity)",
+        "shuffle()",
+        "template(format)",
+        "tokenize()",
+        "truncate(length)",
+    ];
+
+    /// The strategy called by `expression`, or `None` when it does not
+    /// call one.
+    pub fn parse(expression: &RuleExpression) -> Option<ExtractResult<Self>> {
+        if expression.module.is_some() {
+            return None;
+        }
+
+        let name = expression.function.as_str();
+        let strategy = match name {
+            "set_null" | "null" => expression.arguments(&[]).map(|_| Self::SetNull),
+            "hash" | "sha256" => expression.arguments(&["salt"]).map(|args| Self::Hash {
+                salt: args.string("salt"),
+            }),
+            "redact" => expression.arguments(&[]).map(|_| Self::Redact),
+            "truncate" => expression.arguments(&["length"]).and_then(|args| {
+                args.require("length", &format!("{name}(8)"))?;
+                Ok(Self::Truncate(args.number("length")?.unwrap()))
+            }),
+            "constant" => expression.arguments(&["value"]).and_then(|args| {
+                args.require("value", &format!("{name}('x')"))?;
+                Ok(Self::Constant(args.string("value").unwrap()))
+            }),
+            "shuffle" => expression.arguments(&[]).map(|_| Self::Shuffle),
+            "template" => expression.arguments(&["format"]).and_then(|args| {
+                args.require("format", &format!("{name}('{{first_name}}@example.test')"))?;
+                Ok(Self::Template(Template::parse(
+                    &args.string("format").unwrap(),
+                )?))
+            }),
+            "tokenize" => expression.arguments(&[]).map(|_| Self::Tokenize),
+            "id_remap" => expression.arguments(&[]).map(|_| Self::IdRemap),
+            "preserve_format" => expression.arguments(&["keep"]).and_then(|args| {
+                Ok(Self::PreserveFormat {
+                    keep: args.number("keep")?.unwrap_or_default(),
+                })
+            }),
+            "shift_date" => expression.arguments(&["days", "entity"]).and_then(|args| {
+                let days = args.number("days")?.unwrap_or(DEFAULT_SHIFT_DAYS);
+                if days == 0 {
+                    bail!("`{name}` needs at least one day to shift by");
+                }
+                Ok(Self::ShiftDate {
+                    days,
+                    entity: args.string("entity"),
+                })
+            }),
+            "noise" => expression.arguments(&["percent"]).and_then(|args| {
+                let percent = args.number("percent")?.unwrap_or(DEFAULT_NOISE_PERCENT);
+                if !(1..=100).contains(&percent) {
+                    bail!("`{name}` needs a percent from 1 to 100");
+                }
+                Ok(Self::Noise { percent })
+            }),
+            "scrub" => expression
+                .arguments(&["detectors", "replace"])
+                .and_then(|args| {
+                    let detectors = match args.string("detectors") {
+                        Some(names) => names
+                            .split(',')
+                            .map(|name| match Builtin::from_name(name.trim()) {
+                                Some(builtin) => Ok(builtin),
+                                None => bail!(
+                                    "unknown detector `{}` for `{name}`, expected one of email, phone, iban, credit_card, ip or ssn",
+                                    name.trim()
+                                ),
+                            })
+                            .collect::<ExtractResult<Vec<Builtin>>>()?,
+                        None => Builtin::ALL.to_vec(),
+                    };
+                    let redact = match args.string("replace").as_deref() {
+                        None | Some("fake") => false,
+                        Some("redact") => true,
+                        Some(other) => {
+                            bail!("`{name}` replaces with `fake` or `redact`, not `{other}`")
+                        }
+                    };
+                    Ok(Self::Scrub { detectors, redact })
+                }),
+            "fpe" => expression.arguments(&["mode", "tweak"]).and_then(|args| {
+                Ok(Self::Fpe(Fpe {
+                    mode: FpeMode::parse(&args.string("mode").unwrap_or("ff1".into()))?,
+                    tweak: args.string("tweak"),
+                }))
+            }),
+            _ => return None,
+        };
+
+        Some(strategy)
+    }
+
+    /// Apply the strategy to `original`, returning `None` for `NULL`.
+    ///
+    /// [`Strategy::Shuffle`] needs the other values of the column,
+    /// [`Strategy::Template`] and [`Strategy::ShiftDate`] the rest of the
+    /// row, [`Strategy::Tokenize`] a token vault, and [`Strategy::Fpe`],
+    /// [`Strategy::Noise`] and [`Strategy::IdRemap`] may fail, so they are
+    /// left to the caller; they return the value unchanged here.
+    pub fn apply(&self, original: &str, masking_key: Option<&str>) -> Option<String> {
+        match self {
+            Self::SetNull => None,
+            Self::Hash { salt } => {
+                let mut hasher = Sha256::new();
+                hasher.update(salt.as_deref().unwrap_or(run_key(masking_key)));
+                hasher.update(original);
+                Some(
+                    hasher
+                        .finalize()
+                        .iter()
+                        .map(|byte| format!("{byte:02x}"))
+                        .collect(),
+                )
+            }
+            Self::Redact => Some(redact(original)),
+            Self::Truncate(len) => Some(original.chars().take(*len).collect()),
+            Self::Constant(value) => Some(value.clone()),
+            Self::PreserveFormat { keep } => {
+                let seed = match masking_key {
+                    Some(key) => keyed_seed(key, "preserve_format", original) as u64,
+                    None => RandomState::new().build_hasher().finish(),
+                };
+                Some(preserve_format(original, *keep, seed))
+            }
+            Self::Scrub { detectors, redact } => Some(scrub(
+                original,
+                detectors,
+                *redact,
+                |value| match masking_key {
+                    Some(key) => keyed_seed(key, "scrub", value) as u64,
+                    None => RandomState::new().build_hasher().finish(),
+                },
+            )),
+            Self::Shuffle
+            | Self::Template(_)
+            | Self::Tokenize
+            | Self::Fpe(_)
+            | Self::ShiftDate { .. }
+            | Self::Noise { .. }
+            | Self::IdRemap => Some(original.to_string()),
+        }
+    }
+}
+
+/// The splitmix64 generator, for strategies that take their own seed.
+struct SplitMix(u64);
+
+impl SplitMix {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number from 0 up to but excluding `bound`.
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next() % bound
+    }
+}
+
+/// Shuffle `values` in place with a generator seeded from `seed`.
+pub fn shuffle<T>(values: &mut [T], seed: u64) {
+    let mut generator = SplitMix(seed);
+
+    for index in (1..values.len()).rev() {
+        values.swap(index, generator.below(index as u64 + 1) as usize);
+    }
+}
+
+/// Replace the ASCII digits and letters of `value` after the first `keep`
+/// characters with random ones of the same kind and case. Digits that start
+/// a number stay non-zero when they were, so numbers keep their length.
+fn preserve_format(value: &str, keep: usize, seed: u64) -> String {
+    let mut generator = SplitMix(seed);
+    let mut previous = None;
+
+    value
+        .chars()
+        .enumerate()
+        .map(|(index, c)| {
+            let starts_number = !previous.is_some_and(|previous: char| previous.is_ascii_digit());
+            previous = Some(c);
+            let mut random =
+                |first: u8, count: u64| char::from(first + generator.below(count) as u8);
+            match c {
+                _ if index < keep => c,
+                '1'..='9' if starts_number => random(b'1', 9),
+                '0'..='9' => random(b'0', 10),
+                'a'..='z' => random(b'a', 26),
+                'A'..='Z' => random(b'A', 26),
+                c => c,
+            }
+        })
+        .collect()
+}
+
+/// A whole number between `min` and `max`, both included.
+#[derive(Debug)]
+pub struct Between {
+    min: i64,
+    max: i64,
+}
+
+impl Faking for Between {
+    fn fake(&self) -> String {
+        // The generator excludes its upper bound, and its range has to fit
+        generators::misc::random(i128::from(self.min), i128::from(self.max) + 1).to_string()
+    }
+}
+
+/// A `YYYY-MM-DD` date between `min` and `max`, both included.
+#[derive(Debug)]
+pub struct DateBetween {
+    min: i64,
+    max: i64,
+}
+
+impl Faking for DateBetween {
+    fn fake(&self) -> String {
+        let (year, month, day) =
+            fitting::civil_from_days(generators::misc::random(self.min, self.max + 1));
+        format!("{year:04}-{month:02}-{day:02}")
+    }
+}
+
+/// Fills a pattern, replacing `#` with a digit and `?` with a letter.
+#[derive(Debug)]
+pub struct Pattern(String);
+
+impl Faking for Pattern {
+    fn fake(&self) -> String {
+        self.0
+            .chars()
+            .map(|c| match c {
+                '#' => char::from(b'0' + generators::misc::random(0u8, 10)),
+                '?' => char::from(b'a' + generators::misc::random(0u8, 26)),
+                c => c,
+            })
+            .collect()
+    }
+}
+
+/// Built from the arguments of their rule by the [`FakerRegistry`] only.
+macro_rules! from_arguments {
+    ($($faker:ident),*) => {
+        $(
+            impl FromStr for $faker {
+                fn from_str(_: &str) -> Option<Self> {
+                    None
+                }
+            }
+        )*
+    };
+}
+
+from_arguments!(Between, DateBetween, Pattern);
+
+/// Emails keep the first character of the local part and the top-level
+/// domain, everything else keeps its first character.
+fn redact(value: &str) -> String {
+    let first = |part: &str| part.chars().next().map(String::from).unwrap_or_default();
+
+    match value.split_once('@') {
+        Some((local, domain)) => match domain.rsplit_once('.') {
+            Some((_, tld)) => format!("{}***@***.{tld}", first(local)),
+            None => format!("{}***@***", first(local)),
+        },
+        None => format!("{}***", first(value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_faker_for_name() {
+        assert_eq!(
+            format!("{:?}", registry().for_name("first").unwrap()),
+            "FnFaker(name::first)"
+        );
+    }
+
+    fn expression(rule: &str) -> RuleExpression {
+        rule.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_strategies() {
+        let parse = |rule| Strategy::parse(&expression(rule)).unwrap();
+
+        assert_eq!(parse("set_null").unwrap(), Strategy::SetNull);
+        assert_eq!(
+            parse("sha256('pepper')").unwrap(),
+            Strategy::Hash {
+                salt: Some("pepper".to_string())
+            }
+        );
+        assert_eq!(parse("truncate( 3 )").unwrap(), Strategy::Truncate(3));
+        assert_eq!(parse("truncate(length=3)").unwrap(), Strategy::Truncate(3));
+        assert_eq!(
+            parse("constant(\"n/a\")").unwrap(),
+            Strategy::Constant("n/a".to_string())
+        );
+        assert_eq!(
+            parse("constant(x)").unwrap(),
+            Strategy::Constant("x".to_string())
+        );
+        assert!(Strategy::parse(&expression("email")).is_none());
+        assert!(Strategy::parse(&expression("contact::redact()")).is_none());
+        assert!(parse("truncate").is_err());
+        assert!(parse("truncate(many)").is_err());
+        assert!(parse("redact(1)").is_err());
+        assert!(parse("constant('a', 'b')").is_err());
+        assert!(parse("hash(pepper='x')").is_err());
+
+        assert_eq!(
+            parse("preserve_format").unwrap(),
+            Strategy::PreserveFormat { keep: 0 }
+        );
+        assert_eq!(
+            parse("preserve_format(keep=3)").unwrap(),
+            Strategy::PreserveFormat { keep: 3 }
+        );
+        assert_eq!(
+            parse("fpe(tweak='users')").unwrap(),
+            Strategy::Fpe(Fpe {
+                mode: FpeMode::Ff1,
+                tweak: Some("users".to_string())
+            })
+        );
+        assert_eq!(
+            parse("fpe('ff3-1')").unwrap(),
+            Strategy::Fpe(Fpe {
+                mode: FpeMode::Ff3_1,
+                tweak: None
+            })
+        );
+        assert_eq!(
+            parse("shift_date").unwrap(),
+            Strategy::ShiftDate {
+                days: 30,
+                entity: None
+            }
+        );
+        assert_eq!(
+            parse("shift_date(7, 'user_id')").unwrap(),
+            Strategy::ShiftDate {
+                days: 7,
+                entity: Some("user_id".to_string())
+            }
+        );
+        assert_eq!(parse("noise").unwrap(), Strategy::Noise { percent: 10 });
+        assert!(parse("shift_date(0)").is_err());
+        assert!(parse("noise(101)").is_err());
+        assert!(parse("fpe('ff2')").is_err());
+        assert!(parse("preserve_format(all)").is_err());
+
+        assert_eq!(
+            parse("scrub").unwrap(),
+            Strategy::Scrub {
+                detectors: Builtin::ALL.to_vec(),
+                redact: false
+            }
+        );
+        assert_eq!(
+            parse("scrub('email, phone', replace='redact')").unwrap(),
+            Strategy::Scrub {
+                detectors: vec![Builtin::Email, Builtin::Phone],
+                redact: true
+            }
+        );
+        assert!(parse("scrub('passport')").is_err());
+        assert!(parse("scrub(replace='hash')").is_err());
+    }
+
+    #[test]
+    fn test_resolve_fakers() {
+        let fake = |rule| registry().resolve(&expression(rule)).unwrap().fake();
+
+        assert!(fake("contact::email()").contains('@'));
+        assert!(fake("email").contains('@'));
+        assert!(fake("internet::ipv4_address")
+            .parse::<std::net::Ipv4Addr>()
+            .is_ok());
+        for _ in 0..20 {
+            let number = fake("number::between(1, 3)").parse::<i64>().unwrap();
+            assert!((1..=3).contains(&number));
+
+            let date = fake("datetime::date(min='2000-01-30', max='2000-02-01')");
+            assert!(["2000-01-30", "2000-01-31", "2000-02-01"].contains(&date.as_str()));
+
+            let code = fake("string::pattern('###-??')");
+            assert!(code.len() == 6 && code[..3].chars().all(|c| c.is_ascii_digit()));
+            assert!(code[4..].chars().all(|c| c.is_ascii_lowercase()));
+        }
+        assert_eq!(fake("number::between(-5, -5)"), "-5");
+        assert!(fake("number::between(max=9223372036854775807)")
+            .parse::<i64>()
+            .is_ok());
+
+        for invalid in [
+            "contact::mail()",
+            "name::email()",
+            "emial",
+            "email(1)",
+            "number::between(3, 1)",
+            "number::between(1, x)",
+            "datetime::date(min='2000-02-30')",
+            "string::pattern()",
+            "between(1, 2, 3)",
+        ] {
+            assert!(
+                registry().resolve(&expression(invalid)).is_err(),
+                "{invalid}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_apply_strategies() {
+        assert_eq!(Strategy::SetNull.apply("x", None), None);
+        assert_eq!(
+            Strategy::Redact.apply("john@example.com", None).unwrap(),
+            "j***@***.com"
+        );
+        assert_eq!(Strategy::Redact.apply("Johnson", None).unwrap(), "J***");
+        assert_eq!(Strategy::Truncate(3).apply("héllo", None).unwrap(), "hél");
+        assert_eq!(
+            Strategy::Constant("x".to_string())
+                .apply("y", None)
+                .unwrap(),
+            "x"
+        );
+
+        let hash = Strategy::Hash { salt: None };
+        assert_ne!(
+            hash.apply("abc", None).unwrap(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(hash.apply("abc", None), hash.apply("abc", None));
+        assert_ne!(hash.apply("abc", Some("key")), hash.apply("abc", None));
+        assert_eq!(
+            Strategy::Hash {
+                salt: Some("key".to_string())
+            }
+            .apply("abc", None),
+            hash.apply("abc", Some("key"))
+        );
+    }
+
+    #[test]
+    fn test_preserve_format() {
+        let phone = Strategy::PreserveFormat { keep: 3 };
+        let masked = phone.apply("+49 (170) 012-3456", Some("key")).unwrap();
+        assert_ne!(masked, "+49 (170) 012-3456");
+        assert_eq!(
+            masked,
+            phone.apply("+49 (170) 012-3456", Some("key")).unwrap()
+        );
+        assert!(masked.starts_with("+49 ("));
+        assert!(masked
+            .chars()
+            .zip("+49 (170) 012-3456".chars())
+            .all(
+                |(masked, original)| masked.is_ascii_digit() == original.is_ascii_digit()
+                    && (original.is_ascii_digit() || masked == original)
+            ));
+        assert_ne!(&masked[5..6], "0");
+
+        let email = Strategy::PreserveFormat { keep: 0 }
+            .apply("John.Doe42@Example.com", None)
+            .unwrap();
+        assert_eq!(email.len(), 22);
+        assert!(email.chars().zip("John.Doe42@Example.com".chars()).all(
+            |(masked, original)| masked.is_ascii_uppercase() == original.is_ascii_uppercase()
+                && masked.is_ascii_lowercase() == original.is_ascii_lowercase()
+                && masked.is_ascii_digit() == original.is_ascii_digit()
+        ));
+        assert_eq!(&email[4..5], ".");
+        assert_eq!(&email[10..11], "@");
+    }
+
+    #[test]
+    fn test_shift_offsets() {
+        let offsets = (0..200)
+            .map(|entity| shift_offset(Some("key"), 3, &entity.to_string()))
+            .collect::<Vec<i64>>();
+        assert!(offsets
+            .iter()
+            .all(|offset| (-3..=3).contains(offset) && *offset != 0));
+        assert!(offsets.contains(&-3) && offsets.contains(&3));
+        assert_eq!(shift_offset(Some("key"), 3, "0"), offsets[0]);
+        assert_eq!(shift_offset(None, 30, "7"), shift_offset(None, 30, "7"));
+    }
+
+    #[test]
+    fn test_shuffle_is_a_seeded_permutation() {
+        let original = (0..50).collect::<Vec<u32>>();
+        let mut shuffled = original.clone();
+        shuffle(&mut shuffled, 42);
+        let mut again = original.clone();
+        shuffle(&mut again, 42);
+
+        assert_eq!(shuffled, again);
+        assert_ne!(shuffled, original);
+        shuffled.sort();
+        assert_eq!(shuffled, original);
+    }
+
+    #[test]
+    fn test_get_struct_by_snake_case_name() {
+        assert_eq!(
+            format!("{:?}", registry().for_name("ipv4_address").unwrap()),
+            "FnFaker(internet::ipv4_address)"
+        );
+        assert!(registry()
+            .for_name("ipv4_address")
+            .unwrap()
+            .fake()
+            .parse::<std::net::Ipv4Addr>()
+            .is_ok());
+    }
+
+    #[test]
+    fn test_fake_with_seed_is_repeatable() {
+        let seed = keyed_seed("secret", "contact::email()", "john@example.com");
+
+        assert_eq!(
+            seed,
+            keyed_seed("secret", "contact::email()", "john@example.com")
+        );
+        assert_ne!(
+            seed,
+            keyed_seed("other", "contact::email()", "john@example.com")
+        );
+        assert_ne!(
+            seed,
+            keyed_seed("secret", "name::first()", "john@example.com")
+        );
+
+        for faker in [
+            registry().for_name("email").unwrap(),
+            registry().for_name("uuidv4").unwrap(),
+        ] {
+            let first = fake_with_seed(faker.as_ref(), seed);
+            assert_eq!(first, fake_with_seed(faker.as_ref(), seed));
+            assert_ne!(first, fake_with_seed(faker.as_ref(), seed + 1));
+        }
+    }
+}
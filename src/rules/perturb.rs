@@ -0,0 +1,176 @@
+use anyhow::{bail, Context};
+
+use crate::{
+    masking::fitting::{self, DAY},
+    parser::types::DataType,
+    ExtractResult,
+};
+
+/// Move the date at the start of `value` by `days`, keeping any time after
+/// it. Zero dates and other values that are not a date are kept.
+///
+/// Columns of any other type than `DATE`, `DATETIME` and `TIMESTAMP` are an
+/// error, as their values would only look like dates by accident.
+pub fn shift_date(value: &str, data_type: Option<&DataType>, days: i64) -> ExtractResult<String> {
+    match data_type {
+        None | Some(DataType::Date | DataType::DateTime { .. } | DataType::Timestamp { .. }) => {}
+        Some(data_type) => bail!(
+            "`shift_date` needs a DATE, DATETIME or TIMESTAMP column, not {}",
+            <&str>::from(data_type)
+        ),
+    }
+
+    let value = value.trim();
+    let Some(seconds) = value.get(..10).and_then(fitting::parse_date) else {
+        return Ok(value.to_string());
+    };
+    let (year, month, day) = fitting::civil_from_days(seconds / DAY + days);
+
+    Ok(format!("{year:04}-{month:02}-{day:02}{}", &value[10..]))
+}
+
+/// Scale the number `value` by `factor`, rounded and clamped to what a
+/// column of `data_type` holds: whole numbers for integers, `d` decimals
+/// and `m` digits for `DECIMAL(m,d)`, and no negatives when `UNSIGNED`.
+pub fn add_noise(value: &str, data_type: Option<&DataType>, factor: f64) -> ExtractResult<String> {
+    let number = value
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|number| number.is_finite())
+        .with_context(|| format!("`noise` needs a number, not `{value}`"))?;
+    let noisy = number * factor;
+
+    let integer = |bits: u32, unsigned: bool| {
+        let (min, max) = match unsigned {
+            true => (0.0, 2f64.powi(bits as i32) - 1.0),
+            false => (
+                -(2f64.powi(bits as i32 - 1)),
+                2f64.powi(bits as i32 - 1) - 1.0,
+            ),
+        };
+        format!("{:.0}", noisy.round().clamp(min, max))
+    };
+    let decimal = |m: u32, d: u32, unsigned: bool| {
+        let d = d.min(m);
+        let max = 10f64.powi((m - d) as i32) - 10f64.powi(-(d as i32));
+        let min = if unsigned { 0.0 } else { -max };
+        format!("{:.1$}", noisy.clamp(min, max), d as usize)
+    };
+
+    Ok(match data_type {
+        Some(DataType::TinyInt { unsigned, .. }) => integer(8, *unsigned),
+        Some(DataType::SmallInt { unsigned, .. }) => integer(16, *unsigned),
+        Some(DataType::MediumInt { unsigned, .. }) => integer(24, *unsigned),
+        Some(DataType::Int { unsigned, .. }) => integer(32, *unsigned),
+        Some(DataType::BigInt { unsigned, .. }) => integer(64, *unsigned),
+        Some(DataType::Decimal { m, d, unsigned, .. }) => {
+            decimal(m.unwrap_or(10), d.unwrap_or(0), *unsigned)
+        }
+        Some(
+            DataType::Float {
+                m: Some(m),
+                d,
+                unsigned,
+                ..
+            }
+            | DataType::Double {
+                m: Some(m),
+                d,
+                unsigned,
+                ..
+            },
+        ) => decimal(*m, d.unwrap_or(0), *unsigned),
+        Some(DataType::Float { unsigned, .. } | DataType::Double { unsigned, .. }) => {
+            match *unsigned {
+                true => noisy.max(0.0),
+                false => noisy,
+            }
+            .to_string()
+        }
+        // Numbers stored as text keep the decimals they had
+        None
+        | Some(
+            DataType::Char { .. }
+            | DataType::Varchar { .. }
+            | DataType::Text { .. }
+            | DataType::TinyText { .. }
+            | DataType::MediumText { .. }
+            | DataType::LongText { .. },
+        ) => {
+            let decimals = value
+                .trim()
+                .split_once('.')
+                .map_or(0, |(_, decimals)| decimals.len());
+            format!("{noisy:.decimals$}")
+        }
+        Some(data_type) => bail!(
+            "`noise` needs a numeric column, not {}",
+            <&str>::from(data_type)
+        ),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::{parse_rule, statements::CreateTable, Rule};
+
+    fn data_type(definition: &str) -> DataType {
+        let table = format!("CREATE TABLE t (c {definition})");
+        parse_rule::<CreateTable>(Rule::CREATE_TABLE, &table)
+            .unwrap()
+            .columns
+            .remove(0)
+            .data_type
+    }
+
+    #[test]
+    fn can_shift_dates() {
+        let datetime = data_type("DATETIME");
+        assert_eq!(
+            shift_date("2024-02-27 10:11:12", Some(&datetime), 3).unwrap(),
+            "2024-03-01 10:11:12"
+        );
+        assert_eq!(
+            shift_date("2024-01-05", Some(&data_type("DATE")), -10).unwrap(),
+            "2023-12-26"
+        );
+        assert_eq!(
+            shift_date("2024-01-05T08:00", None, 1).unwrap(),
+            "2024-01-06T08:00"
+        );
+        assert_eq!(
+            shift_date("0000-00-00 00:00:00", Some(&datetime), 5).unwrap(),
+            "0000-00-00 00:00:00"
+        );
+        assert!(shift_date("2024-01-05", Some(&data_type("VARCHAR(10)")), 1).is_err());
+    }
+
+    #[test]
+    fn can_add_noise() {
+        assert_eq!(
+            add_noise("100", Some(&data_type("INT")), 1.104).unwrap(),
+            "110"
+        );
+        assert_eq!(
+            add_noise("12.34", Some(&data_type("DECIMAL(5,2)")), 1.1).unwrap(),
+            "13.57"
+        );
+        assert_eq!(
+            add_noise("990.00", Some(&data_type("DECIMAL(5,2)")), 1.1).unwrap(),
+            "999.99"
+        );
+        assert_eq!(
+            add_noise("250", Some(&data_type("TINYINT UNSIGNED")), 1.2).unwrap(),
+            "255"
+        );
+        assert_eq!(
+            add_noise("-5", Some(&data_type("INT UNSIGNED")), 1.2).unwrap(),
+            "0"
+        );
+        assert_eq!(add_noise("2.50", None, 0.9).unwrap(), "2.25");
+        assert!(add_noise("n/a", None, 1.1).is_err());
+        assert!(add_noise("1", Some(&data_type("DATE")), 1.1).is_err());
+    }
+}
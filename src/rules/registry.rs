@@ -0,0 +1,510 @@
+use std::{
+    fmt::{Debug, Formatter, Result as FmtResult},
+    sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+
+use anyhow::{bail, Context};
+use lazy_static::lazy_static;
+
+use crate::{masking::fitting, ExtractResult};
+
+use super::{
+    generators, read_dictionary, Arguments, Between, DateBetween, Faking, FromStr, FromStrFaking,
+    Pattern, RuleExpression,
+};
+
+lazy_static! {
+    static ref REGISTRY: RwLock<FakerRegistry> = RwLock::new(FakerRegistry::default());
+}
+
+/// The registry the rules of masking configs are resolved in.
+pub fn registry() -> RwLockReadGuard<'static, FakerRegistry> {
+    REGISTRY.read().unwrap_or_else(|err| err.into_inner())
+}
+
+/// The registry the rules of masking configs are resolved in, to register
+/// more fakers before loading a config.
+pub fn registry_mut() -> RwLockWriteGuard<'static, FakerRegistry> {
+    REGISTRY.write().unwrap_or_else(|err| err.into_inner())
+}
+
+type Factory = dyn Fn(&Arguments) -> ExtractResult<Box<dyn FromStrFaking>> + Send + Sync;
+
+/// A faker generating its values with a closure.
+pub struct FnFaker {
+    path: String,
+    generate: Arc<dyn Fn() -> String + Send + Sync>,
+}
+
+impl FnFaker {
+    pub fn new<G>(path: &str, generate: G) -> Self
+    where
+        G: Fn() -> String + Send + Sync + 'static,
+    {
+        Self {
+            path: path.to_string(),
+            generate: Arc::new(generate),
+        }
+    }
+}
+
+impl Debug for FnFaker {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "FnFaker({})", self.path)
+    }
+}
+
+impl Faking for FnFaker {
+    fn fake(&self) -> String {
+        (self.generate)()
+    }
+}
+
+impl FromStr for FnFaker {
+    fn from_str(_: &str) -> Option<Self> {
+        None
+    }
+}
+
+/// A faker that rules can call as `module::function(parameters)`.
+pub struct RegisteredFaker {
+    pub module: String,
+    pub function: String,
+    pub parameters: Vec<String>,
+    factory: Box<Factory>,
+}
+
+impl RegisteredFaker {
+    pub fn path(&self) -> String {
+        format!("{}::{}", self.module, self.function)
+    }
+
+    /// The faker with its parameters, as rules call it.
+    pub fn signature(&self) -> String {
+        format!("{}({})", self.path(), self.parameters.join(", "))
+    }
+
+    /// Whether the faker is called `function`, ignoring case and
+    /// underscores, in `module` or in any module.
+    fn is_called(&self, module: Option<&str>, function: &str) -> bool {
+        let normalized = |name: &str| name.replace('_', "").to_lowercase();
+        normalized(&self.function) == normalized(function)
+            && module.is_none_or(|module| module.eq_ignore_ascii_case(&self.module))
+    }
+}
+
+impl Debug for RegisteredFaker {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "RegisteredFaker({})", self.signature())
+    }
+}
+
+/// The fakers rules can call, by module and function.
+///
+/// The default registry has the generators of `fakeit`; more can be
+/// registered at runtime, see [`registry_mut`]. Registering a faker under
+/// a name that is taken replaces the previous one.
+#[derive(Debug)]
+pub struct FakerRegistry {
+    fakers: Vec<RegisteredFaker>,
+}
+
+impl FakerRegistry {
+    /// A registry without any faker.
+    pub fn new() -> Self {
+        Self { fakers: Vec::new() }
+    }
+
+    /// Register a faker built from the arguments of the rule calling it.
+    pub fn register<F>(&mut self, module: &str, function: &str, parameters: &[&str], factory: F)
+    where
+        F: Fn(&Arguments) -> ExtractResult<Box<dyn FromStrFaking>> + Send + Sync + 'static,
+    {
+        let faker = RegisteredFaker {
+            module: module.to_string(),
+            function: function.to_string(),
+            parameters: parameters
+                .iter()
+                .map(|parameter| parameter.to_string())
+                .collect(),
+            factory: Box::new(factory),
+        };
+
+        match self
+            .fakers
+            .iter_mut()
+            .find(|registered| registered.is_called(Some(module), function))
+        {
+            Some(registered) => *registered = faker,
+            None => self.fakers.push(faker),
+        }
+    }
+
+    /// Register a faker without arguments that generates its values with
+    /// `generate`.
+    pub fn register_fn<G>(&mut self, module: &str, function: &str, generate: G)
+    where
+        G: Fn() -> String + Send + Sync + 'static,
+    {
+        let generate = Arc::new(generate);
+        let path = format!("{module}::{function}");
+        self.register(module, function, &[], move |_| {
+            let generate = generate.clone();
+            Ok(Box::new(FnFaker::new(&path, move || generate())))
+        });
+    }
+
+    /// Register a faker without arguments, created with its
+    /// [`FromStr::from_str`] from the rule calling it.
+    pub fn register_faker<T: FromStrFaking + 'static>(&mut self, module: &str, function: &str) {
+        let path = format!("{module}::{function}");
+        self.register(module, function, &[], move |_| {
+            Ok(Box::new(T::from_str(&path).with_context(|| {
+                format!("unable to create faker `{path}`")
+            })?))
+        });
+    }
+
+    /// The faker called `function`, in `module` when given, or else the
+    /// first one registered in any module.
+    pub fn find(&self, module: Option<&str>, function: &str) -> Option<&RegisteredFaker> {
+        self.fakers
+            .iter()
+            .find(|faker| faker.is_called(module, function))
+    }
+
+    /// The faker called by `expression`, with its arguments.
+    pub fn resolve(&self, expression: &RuleExpression) -> ExtractResult<Box<dyn FromStrFaking>> {
+        let faker = self
+            .find(expression.module.as_deref(), &expression.function)
+            .with_context(|| format!("unknown masking rule `{}`", expression.path()))?;
+        let parameters = faker
+            .parameters
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<&str>>();
+
+        (faker.factory)(&expression.arguments(&parameters)?)
+    }
+
+    /// The faker named after a column, called without arguments.
+    pub fn for_name(&self, name: &str) -> ExtractResult<Box<dyn FromStrFaking>> {
+        let expression = RuleExpression {
+            module: None,
+            function: name.to_string(),
+            arguments: Vec::new(),
+        };
+        self.resolve(&expression)
+    }
+
+    /// Every registered faker, ordered by module and function.
+    pub fn fakers(&self) -> Vec<&RegisteredFaker> {
+        let mut fakers = self.fakers.iter().collect::<Vec<&RegisteredFaker>>();
+        fakers.sort_by_key(|faker| (faker.module.clone(), faker.function.clone()));
+        fakers
+    }
+}
+
+impl Default for FakerRegistry {
+    /// Every `fakeit` generator that gives text or numbers.
+    fn default() -> Self {
+        let mut registry = Self::new();
+
+        macro_rules! register_generators {
+            ($($module:ident :: $function:ident),* $(,)?) => {
+                $(
+                    registry.register_fn(stringify!($module), stringify!($function), || {
+                        generators::$module::$function().to_string()
+                    });
+                )*
+            };
+        }
+
+        // The most common names first, as rules without a module take the
+        // first faker with their name
+        register_generators! {
+            contact::email, contact::phone, contact::phone_formatted,
+
+            name::first, name::last, name::full, name::prefix, name::suffix,
+
+            person::ssn, person::gender,
+
+            internet::username, internet::domain_name, internet::domain_suffix,
+            internet::http_method, internet::ipv4_address, internet::ipv6_address,
+            internet::mac_address,
+
+            address::street, address::street_number, address::street_prefix,
+            address::street_name, address::street_suffix, address::city, address::state,
+            address::state_abr, address::zip, address::country, address::country_abr,
+            address::latitude, address::longitude,
+
+            company::company, company::company_suffix, company::buzzword, company::bs,
+
+            job::title, job::descriptor, job::level,
+
+            payment::credit_card_type, payment::credit_card_number,
+            payment::credit_card_luhn_number, payment::credit_card_exp,
+            payment::credit_card_cvv,
+
+            datetime::month, datetime::day, datetime::week_day, datetime::year,
+            datetime::hour, datetime::minute, datetime::second, datetime::nanosecond,
+            datetime::timezone, datetime::timezone_full, datetime::timezone_abv,
+            datetime::timezone_offset,
+
+            currency::short, currency::long,
+
+            hacker::phrase, hacker::abbreviation, hacker::adjective, hacker::noun,
+            hacker::verb, hacker::ingverb,
+
+            animal::pet_name, animal::animal, animal::type_of, animal::farm, animal::cat,
+            animal::dog,
+
+            beer::name, beer::style, beer::hop, beer::yeast, beer::malt, beer::ibu,
+            beer::alcohol, beer::blg,
+
+            bool_rand::bool,
+
+            color::full, color::hex, color::safe,
+
+            file::mime_type, file::extension,
+
+            language::random, language::abbreviation, language::programming,
+
+            log_level::general, log_level::syslog, log_level::apache,
+
+            status_code::simple, status_code::general,
+
+            unique::uuid_v4,
+
+            user_agent::chrome, user_agent::firefox, user_agent::safari, user_agent::opera,
+            user_agent::linux_platform_token, user_agent::mac_platform_token,
+            user_agent::windows_platform_token, user_agent::random_platform,
+
+            vehicle::vehicle_type, vehicle::fuel, vehicle::transmission_gear,
+            vehicle::car_maker, vehicle::car_model,
+        }
+
+        // `words` is the lorem ipsum generator of `fakeit`
+        for module in ["words", "lorem"] {
+            registry.register_fn(module, "word", generators::words::word);
+            registry.register_fn(module, "question", generators::words::question);
+            registry.register_fn(module, "quote", generators::words::quote);
+            registry.register(module, "sentence", &["word_count"], move |args| {
+                let word_count = args.number("word_count")?.unwrap_or(10);
+                Ok(Box::new(FnFaker::new(
+                    &format!("{module}::sentence"),
+                    move || generators::words::sentence(word_count),
+                )))
+            });
+            registry.register(
+                module,
+                "paragraph",
+                &["count", "sentence_count", "word_count", "separator"],
+                move |args| {
+                    let count = args.number("count")?.unwrap_or(1);
+                    let sentence_count = args.number("sentence_count")?.unwrap_or(3);
+                    let word_count = args.number("word_count")?.unwrap_or(10);
+                    let separator = args.string("separator").unwrap_or("\n".to_string());
+                    Ok(Box::new(FnFaker::new(
+                        &format!("{module}::paragraph"),
+                        move || {
+                            generators::words::paragraph(
+                                count,
+                                sentence_count,
+                                word_count,
+                                separator.clone(),
+                            )
+                        },
+                    )))
+                },
+            );
+        }
+        registry.register_fn("hipster", "word", generators::hipster::word);
+        registry.register("hipster", "sentence", &["word_count"], |args| {
+            let word_count = args.number("word_count")?.unwrap_or(10);
+            Ok(Box::new(FnFaker::new("hipster::sentence", move || {
+                generators::hipster::sentence(word_count)
+            })))
+        });
+
+        registry.register("number", "between", &["min", "max"], |args| {
+            let min = args.number("min")?.unwrap_or(0);
+            let max = args.number("max")?.unwrap_or(i64::MAX);
+            if min > max {
+                bail!("`number::between` needs `min` to be at most `max`");
+            }
+            Ok(Box::new(Between { min, max }))
+        });
+        registry.register("datetime", "date", &["min", "max"], |args| {
+            let date = |parameter: &str, default: &str| {
+                let value = args.string(parameter).unwrap_or(default.to_string());
+                fitting::parse_date(&value)
+                    .map(|seconds| seconds / fitting::DAY)
+                    .with_context(|| {
+                        format!(
+                            "`datetime::date` needs a `YYYY-MM-DD` date for `{parameter}`, not `{value}`"
+                        )
+                    })
+            };
+            let (min, max) = (date("min", "1970-01-01")?, date("max", "2030-12-31")?);
+            if min > max {
+                bail!("`datetime::date` needs `min` to be at most `max`");
+            }
+            Ok(Box::new(DateBetween { min, max }))
+        });
+        registry.register("string", "pattern", &["pattern"], |args| {
+            args.require("pattern", "string::pattern('###-??')")?;
+            Ok(Box::new(Pattern(args.string("pattern").unwrap())))
+        });
+        registry.register("currency", "price", &["min", "max"], |args| {
+            let min = args.number("min")?.unwrap_or(0.0);
+            let max = args.number("max")?.unwrap_or(1000.0);
+            if min > max {
+                bail!("`currency::price` needs `min` to be at most `max`");
+            }
+            Ok(Box::new(FnFaker::new("currency::price", move || {
+                format!("{:.2}", generators::currency::price(min, max))
+            })))
+        });
+        registry.register("dictionary", "pick", &["file", "column"], |args| {
+            args.require("file", "dictionary::pick('departments.txt')")?;
+            let values = read_dictionary(
+                &args.string("file").unwrap(),
+                args.string("column").as_deref(),
+            )?;
+            Ok(Box::new(FnFaker::new("dictionary::pick", move || {
+                values[generators::misc::random(0, values.len() as i64) as usize].clone()
+            })))
+        });
+        registry.register(
+            "password",
+            "generate",
+            &["length", "upper", "numeric", "special"],
+            |args| {
+                let flag = |parameter: &str| match args.string(parameter).as_deref() {
+                    None | Some("true") => Ok(true),
+                    Some("false") => Ok(false),
+                    Some(value) => bail!(
+                        "`password::generate` needs true or false for `{parameter}`, not `{value}`"
+                    ),
+                };
+                let length = args.number("length")?.unwrap_or(12);
+                let (upper, numeric, special) =
+                    (flag("upper")?, flag("numeric")?, flag("special")?);
+                Ok(Box::new(FnFaker::new("password::generate", move || {
+                    generators::password::generate(upper, numeric, special, length)
+                })))
+            },
+        );
+
+        registry
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn resolve(registry: &FakerRegistry, rule: &str) -> ExtractResult<String> {
+        Ok(registry.resolve(&rule.parse()?)?.fake())
+    }
+
+    #[test]
+    fn has_every_generator() {
+        let registry = FakerRegistry::default();
+
+        for rule in [
+            "address::city()",
+            "payment::credit_card_number()",
+            "hacker::phrase()",
+            "lorem::sentence(4)",
+            "words::paragraph(count=2, separator=' ')",
+            "currency::price(1, 2)",
+            "password::generate(16, special=false)",
+            "bool",
+        ] {
+            assert!(!resolve(&registry, rule).unwrap().is_empty(), "{rule}");
+        }
+        assert_eq!(
+            resolve(&registry, "lorem::sentence(4)")
+                .unwrap()
+                .split(' ')
+                .count(),
+            4
+        );
+        assert_eq!(
+            registry.find(None, "email").unwrap().signature(),
+            "contact::email()"
+        );
+        assert_eq!(
+            registry.find(None, "IPv4Address").unwrap().path(),
+            "internet::ipv4_address"
+        );
+        assert!(resolve(&registry, "password::generate(upper=maybe)").is_err());
+    }
+
+    #[derive(Debug)]
+    struct Badge(String);
+
+    impl Faking for Badge {
+        fn fake(&self) -> String {
+            format!("badge for {}", self.0)
+        }
+    }
+
+    impl FromStr for Badge {
+        fn from_str(s: &str) -> Option<Self> {
+            Some(Badge(s.to_string()))
+        }
+    }
+
+    #[test]
+    fn can_register_fakers() {
+        let mut registry = FakerRegistry::new();
+        assert!(resolve(&registry, "contact::email()").is_err());
+
+        registry.register_faker::<Badge>("acme", "badge");
+        registry.register_fn("acme", "email", || "nobody@acme.test".to_string());
+        registry.register("acme", "repeat", &["text", "times"], |args| {
+            let text = args.string("text").unwrap_or_default();
+            let times = args.number("times")?.unwrap_or(2);
+            Ok(Box::new(FnFaker::new("acme::repeat", move || {
+                text.repeat(times)
+            })))
+        });
+
+        assert_eq!(
+            resolve(&registry, "acme::badge").unwrap(),
+            "badge for acme::badge"
+        );
+        assert_eq!(resolve(&registry, "email").unwrap(), "nobody@acme.test");
+        assert_eq!(
+            resolve(&registry, "acme::repeat(ab, times=3)").unwrap(),
+            "ababab"
+        );
+        assert!(resolve(&registry, "acme::repeat(ab, 3, 4)").is_err());
+
+        registry.register_fn("acme", "email", || "somebody@acme.test".to_string());
+        assert_eq!(
+            resolve(&registry, "acme::email").unwrap(),
+            "somebody@acme.test"
+        );
+        assert_eq!(
+            registry
+                .fakers()
+                .iter()
+                .map(|faker| faker.signature())
+                .collect::<Vec<String>>(),
+            vec![
+                "acme::badge()",
+                "acme::email()",
+                "acme::repeat(text, times)"
+            ]
+        );
+        assert_eq!(
+            registry.for_name("Email").unwrap().fake(),
+            "somebody@acme.test"
+        );
+        assert!(registry.for_name("nickname").is_err());
+    }
+}
@@ -0,0 +1,185 @@
+use anyhow::bail;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::{parser::types::DataType, ExtractResult};
+
+/// Feistel rounds of the permutations.
+const ROUNDS: u8 = 8;
+
+/// Map an integer or UUID key to another one with a permutation keyed by
+/// `key`, so that no two keys get the same replacement and references to a
+/// key can be remapped on their own.
+///
+/// Integers keep their sign and number of digits, and stay in the range of
+/// an integer column of `data_type`. UUIDs keep their version and variant.
+pub fn remap_id(key: &str, value: &str, data_type: Option<&DataType>) -> ExtractResult<String> {
+    let max = data_type.map_or(u64::MAX, integer_max);
+    let trimmed = value.trim();
+    let (sign, digits) = match trimmed.strip_prefix('-') {
+        Some(digits) => ("-", digits),
+        None => ("", trimmed),
+    };
+
+    if let Some(number) = digits.parse::<u64>().ok().filter(|number| *number <= max) {
+        if !digits.starts_with('0') || digits == "0" {
+            let remapped = match number {
+                0 => 0,
+                _ => {
+                    let length = number.ilog10();
+                    let low = 10u128.pow(length);
+                    let high = 10u128.pow(length + 1).min(u128::from(max) + 1);
+                    low + permute(key, u128::from(number) - low, high - low)
+                }
+            };
+            return Ok(format!("{sign}{remapped}"));
+        }
+    }
+
+    if let Ok(uuid) = uuid::Uuid::try_parse(trimmed) {
+        let bits = uuid.as_u128();
+        // Everything but the 4 version bits at 76 and the 2 variant bits at 62
+        let free = (bits >> 80) << 74 | (bits >> 64 & 0xfff) << 62 | bits & ((1 << 62) - 1);
+        let remapped = permute_bits(key, free, 122);
+        let bits = (remapped >> 74) << 80
+            | (bits >> 76 & 0xf) << 76
+            | (remapped >> 62 & 0xfff) << 64
+            | (bits >> 62 & 0x3) << 62
+            | remapped & ((1 << 62) - 1);
+        let remapped = uuid::Uuid::from_u128(bits).hyphenated().to_string();
+        return Ok(match trimmed.chars().any(|c| c.is_ascii_uppercase()) {
+            true => remapped.to_uppercase(),
+            false => remapped,
+        });
+    }
+
+    bail!("`id_remap` needs an integer up to {max} or a UUID, not `{value}`")
+}
+
+/// The largest value of an integer column of `data_type`, or of a `u64`
+/// for other columns.
+pub fn integer_max(data_type: &DataType) -> u64 {
+    let (bits, unsigned) = match data_type {
+        DataType::TinyInt { unsigned, .. } => (8, *unsigned),
+        DataType::SmallInt { unsigned, .. } => (16, *unsigned),
+        DataType::MediumInt { unsigned, .. } => (24, *unsigned),
+        DataType::Int { unsigned, .. } => (32, *unsigned),
+        DataType::BigInt { unsigned, .. } => (64, *unsigned),
+        _ => return u64::MAX,
+    };
+    match unsigned {
+        true => u64::MAX >> (64 - bits),
+        false => u64::MAX >> (65 - bits),
+    }
+}
+
+/// A permutation of `0..size`, by cycle walking a permutation of the
+/// smallest even number of bits that holds `size`.
+fn permute(key: &str, mut value: u128, size: u128) -> u128 {
+    let bits = (128 - (size - 1).leading_zeros())
+        .max(2)
+        .next_multiple_of(2);
+    loop {
+        value = permute_bits(key, value, bits);
+        if value < size {
+            return value;
+        }
+    }
+}
+
+/// A balanced Feistel permutation of the numbers of `bits` bits, with
+/// HMAC-SHA256 of the key as its round function.
+fn permute_bits(key: &str, value: u128, bits: u32) -> u128 {
+    let half = bits / 2;
+    let mask = (1u128 << half) - 1;
+    let (mut left, mut right) = (value >> half, value & mask);
+
+    for round in 0..ROUNDS {
+        let mut mac = Hmac::<Sha256>::new_from_slice(key.as_bytes())
+            .expect("HMAC accepts keys of any length");
+        mac.update(b"id_remap");
+        mac.update(&[round, bits as u8]);
+        mac.update(&right.to_be_bytes());
+        let digest = mac.finalize().into_bytes();
+        let f = u128::from_be_bytes(digest[..16].try_into().unwrap()) & mask;
+        (left, right) = (right, left ^ f);
+    }
+
+    left << half | right
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn can_remap_integers() {
+        for size in [1, 9, 90, 900, 1000] {
+            let permuted = (0..size)
+                .map(|value| permute("key", value, size))
+                .collect::<HashSet<u128>>();
+            assert_eq!(permuted.len(), size as usize);
+            assert!(permuted.iter().all(|value| *value < size));
+        }
+
+        let remapped = (1..=1000)
+            .map(|id| remap_id("key", &id.to_string(), None).unwrap())
+            .collect::<Vec<String>>();
+        assert_eq!(remapped.iter().collect::<HashSet<&String>>().len(), 1000);
+        assert!(remapped
+            .iter()
+            .zip(1..=1000)
+            .all(|(remapped, id)| remapped.len() == id.to_string().len()));
+        assert_ne!(
+            remapped[..100],
+            (1..=100).map(|id| id.to_string()).collect::<Vec<_>>()
+        );
+        assert_eq!(remap_id("key", "42", None).unwrap(), remapped[41]);
+        assert_ne!(remap_id("other", "42", None).unwrap(), remapped[41]);
+        assert_eq!(remap_id("key", "0", None).unwrap(), "0");
+        assert_eq!(
+            remap_id("key", "-42", None).unwrap(),
+            format!("-{}", remapped[41])
+        );
+        assert_eq!(
+            remap_id("key", "18446744073709551615", None).unwrap().len(),
+            20
+        );
+        let int = DataType::Int {
+            m: None,
+            unsigned: false,
+            zerofill: false,
+        };
+        assert_eq!(integer_max(&int), 2_147_483_647);
+        for id in 1_000_000_000..1_000_000_100 {
+            let remapped = remap_id("key", &id.to_string(), Some(&int)).unwrap();
+            assert!(remapped.parse::<u64>().unwrap() <= 2_147_483_647);
+        }
+        let tinyint = DataType::TinyInt {
+            m: None,
+            unsigned: true,
+            zerofill: false,
+        };
+        assert!(remap_id("key", "300", Some(&tinyint)).is_err());
+        assert_eq!(integer_max(&tinyint), 255);
+        assert!(remap_id("key", "abc", None).is_err());
+        assert!(remap_id("key", "0042", None).is_err());
+    }
+
+    #[test]
+    fn can_remap_uuids() {
+        let original = "9f1c2a4e-7b3d-4c8e-a1f2-3d4e5f607182";
+        let remapped = remap_id("key", original, None).unwrap();
+        let uuid = uuid::Uuid::parse_str(&remapped).unwrap();
+        assert_ne!(remapped, original);
+        assert_eq!(uuid.get_version_num(), 4);
+        assert_eq!(uuid.get_variant(), uuid::Variant::RFC4122);
+        assert_eq!(remap_id("key", original, None).unwrap(), remapped);
+        assert_eq!(
+            remap_id("key", &original.to_uppercase(), None).unwrap(),
+            remapped.to_uppercase()
+        );
+    }
+}
@@ -0,0 +1,194 @@
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+
+use anyhow::anyhow;
+use lazy_static::lazy_static;
+use rhai::{Dynamic, Engine, EvalAltResult, Map, Scope, AST, INT};
+use sha2::{Digest, Sha256};
+
+use crate::ExtractResult;
+
+use super::{fake_with_seed, registry, CellContext, RuleExpression};
+
+/// Stops scripts that loop forever.
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+lazy_static! {
+    static ref ENGINE: Engine = engine();
+}
+
+/// The engine scripts run in, with the helpers they can call on top of
+/// the Rhai standard library:
+///
+/// - `fake(rule, seed)` fakes a value with a faker rule such as
+///   `"contact::email"`, the same for the same seed.
+/// - `sha256(text)` hashes `text` to lowercase hex.
+fn engine() -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.register_fn(
+        "fake",
+        |rule: &str, seed: INT| -> Result<String, Box<EvalAltResult>> {
+            let faker = rule
+                .parse::<RuleExpression>()
+                .and_then(|expression| registry().resolve(&expression))
+                .map_err(|err| format!("{err:#}"))?;
+            // The generator overflows on seeds of 2^63 and up
+            Ok(fake_with_seed(
+                &*faker,
+                (seed as u64 & (u64::MAX >> 1)) as u128,
+            ))
+        },
+    );
+    engine.register_fn("sha256", |text: &str| -> String {
+        Sha256::digest(text)
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect()
+    });
+    engine
+}
+
+/// A masking rule written in [Rhai](https://rhai.rs), compiled once when the
+/// config is loaded.
+///
+/// The script is evaluated for each value with these variables:
+///
+/// - `value`, the original value as a string.
+/// - `column`, a map with the `name`, `table`, `database` and `type` of the
+///   column, `()` where unknown.
+/// - `row`, a map of the original values of the row by column name, `()`
+///   for `NULL`.
+/// - `seed`, a number derived from the masking key and the value, or random
+///   without a key, to pass to `fake`.
+///
+/// The value of the script replaces the original; `()` makes it `NULL`.
+pub struct Script {
+    ast: AST,
+}
+
+impl Script {
+    pub fn compile(source: &str) -> ExtractResult<Self> {
+        let ast = ENGINE
+            .compile(source)
+            .map_err(|err| anyhow!("unable to compile script: {err}"))?;
+        Ok(Self { ast })
+    }
+
+    /// Run the script for `value`, returning `None` for `NULL`.
+    pub fn run(
+        &self,
+        value: &str,
+        seed: u64,
+        context: &CellContext,
+    ) -> ExtractResult<Option<String>> {
+        let text =
+            |value: Option<&str>| value.map_or(Dynamic::UNIT, |value| value.to_string().into());
+
+        let mut column = Map::new();
+        column.insert(
+            "name".into(),
+            text(context.column.map(|c| c.column.as_str())),
+        );
+        column.insert(
+            "table".into(),
+            text(context.column.map(|c| c.table.as_str())),
+        );
+        column.insert(
+            "database".into(),
+            text(context.column.and_then(|c| c.database.as_deref())),
+        );
+        column.insert(
+            "type".into(),
+            text(context.data_type.map(ToString::to_string).as_deref()),
+        );
+        let row = context
+            .row
+            .iter()
+            .map(|(name, value)| (name.as_str().into(), text(value.as_deref())))
+            .collect::<Map>();
+
+        let mut scope = Scope::new();
+        scope.push_constant("value", value.to_string());
+        scope.push_constant("column", column);
+        scope.push_constant("row", row);
+        scope.push_constant("seed", seed as INT);
+
+        let result = ENGINE
+            .eval_ast_with_scope::<Dynamic>(&mut scope, &self.ast)
+            .map_err(|err| anyhow!("script failed for `{value}`: {err}"))?;
+        Ok(match result {
+            result if result.is_unit() => None,
+            result if result.is_string() => Some(result.into_string().unwrap()),
+            result => Some(result.to_string()),
+        })
+    }
+}
+
+impl Debug for Script {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "Script")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::masking::ColumnRef;
+
+    #[test]
+    fn can_run_scripts() {
+        let column = ColumnRef::new(Some("app"), "addresses", "postal_code");
+        let row = vec![
+            ("country".to_string(), Some("NL".to_string())),
+            ("postal_code".to_string(), Some("1017 CT".to_string())),
+            ("notes".to_string(), None),
+        ];
+        let context = CellContext {
+            column: Some(&column),
+            data_type: None,
+            row: &row,
+            masked: &row,
+        };
+
+        let script = Script::compile(
+            r#"if row.country == "NL" { value.sub_string(0, 4) + " XX" } else { "00000" }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            script.run("1017 CT", 0, &context).unwrap().unwrap(),
+            "1017 XX"
+        );
+
+        let script = Script::compile("`${column.table}.${column.name} ${column.type}`").unwrap();
+        assert_eq!(
+            script.run("", 0, &context).unwrap().unwrap(),
+            "addresses.postal_code "
+        );
+
+        let script = Script::compile("if row.notes == () { () } else { value }").unwrap();
+        assert_eq!(script.run("x", 0, &context).unwrap(), None);
+        assert_eq!(
+            Script::compile("value.len() * 2")
+                .unwrap()
+                .run("abc", 0, &context)
+                .unwrap()
+                .unwrap(),
+            "6"
+        );
+
+        let script = Script::compile(r#"fake("contact::email", seed)"#).unwrap();
+        let fake = script.run("", 42, &context).unwrap().unwrap();
+        assert!(fake.contains('@'));
+        assert_eq!(script.run("", 42, &context).unwrap().unwrap(), fake);
+
+        assert!(Script::compile("value +").is_err());
+        assert!(Script::compile("loop {}")
+            .unwrap()
+            .run("", 0, &context)
+            .is_err());
+        assert!(Script::compile(r#"fake("nope", 1)"#)
+            .unwrap()
+            .run("", 0, &context)
+            .is_err());
+    }
+}
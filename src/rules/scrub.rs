@@ -0,0 +1,164 @@
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    ops::Range,
+};
+
+use crate::masking::{iban_remainder, luhn, Builtin};
+
+use super::{fake_with_seed, preserve_format, registry, SplitMix};
+
+/// Replace the PII that `detectors` find inside `text`, leaving the text
+/// around it as it is. Each value becomes a fake of the same kind drawn
+/// from `seed` and the value, or a marker such as `[EMAIL]` when `redact`.
+///
+/// Where detectors find overlapping values, the more specific one wins,
+/// see [`Builtin::ALL`].
+pub fn scrub(
+    text: &str,
+    detectors: &[Builtin],
+    redact: bool,
+    seed: impl Fn(&str) -> u64,
+) -> String {
+    let mut found: Vec<(Range<usize>, Builtin)> = Vec::new();
+    for builtin in Builtin::ALL
+        .into_iter()
+        .filter(|builtin| detectors.contains(builtin))
+    {
+        for range in builtin.find_in(text) {
+            if found
+                .iter()
+                .all(|(taken, _)| range.end <= taken.start || taken.end <= range.start)
+            {
+                found.push((range, builtin));
+            }
+        }
+    }
+    found.sort_by_key(|(range, _)| range.start);
+
+    let mut scrubbed = String::with_capacity(text.len());
+    let mut position = 0;
+    for (range, builtin) in found {
+        let value = &text[range.clone()];
+        scrubbed.push_str(&text[position..range.start]);
+        match redact {
+            true => scrubbed.push_str(&format!("[{}]", builtin.name().to_uppercase())),
+            false => scrubbed.push_str(&fake(builtin, value, seed(value))),
+        }
+        position = range.end;
+    }
+    scrubbed.push_str(&text[position..]);
+
+    scrubbed
+}
+
+/// A value of the kind `builtin` detects, in the format of `value`.
+fn fake(builtin: Builtin, value: &str, seed: u64) -> String {
+    let mut generator = SplitMix(seed);
+    match builtin {
+        Builtin::Email => fake_with_seed(
+            &*registry()
+                .for_name("email")
+                .expect("email is a registered faker"),
+            u128::from(seed >> 1),
+        ),
+        Builtin::Phone => preserve_format(value, 0, seed),
+        // The first digit tells the card network
+        Builtin::CreditCard => {
+            let fake = preserve_format(value, 1, seed);
+            let (head, _) = fake.split_at(fake.len() - 1);
+            (b'0'..=b'9')
+                .map(|check| format!("{head}{}", char::from(check)))
+                .find(|fake| {
+                    luhn(
+                        &fake
+                            .chars()
+                            .filter(char::is_ascii_digit)
+                            .collect::<String>(),
+                    )
+                })
+                .expect("one check digit is valid")
+        }
+        // The country stays, and the check digits are made to match
+        Builtin::Iban => {
+            let fake = preserve_format(value, 4, seed);
+            let compact = fake.replace(' ', "").to_ascii_uppercase();
+            let check = 98 - iban_remainder(&format!("{}00{}", &compact[..2], &compact[4..]));
+            format!("{}{check:02}{}", &fake[..2], &fake[4..])
+        }
+        Builtin::Ip => match value.parse::<IpAddr>() {
+            Ok(IpAddr::V4(_)) => {
+                let [_, a, b, c] = (generator.next() as u32).to_be_bytes();
+                Ipv4Addr::new(10, a, b, c).to_string()
+            }
+            _ => {
+                // A unique local address
+                let bits = u128::from(generator.next()) << 64 | u128::from(generator.next());
+                Ipv6Addr::from(0xfd << 120 | bits >> 8).to_string()
+            }
+        },
+        // Numbers starting with 9 are never issued
+        Builtin::Ssn => format!(
+            "9{:02}-{:02}-{:04}",
+            generator.below(100),
+            generator.below(99) + 1,
+            generator.below(9999) + 1
+        ),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn can_scrub_pii_in_text() {
+        let text = "Call +1 (555) 010-9999 or mail ann@example.com. \
+            Paid with 4111 1111 1111 1111 from GB82 WEST 1234 5698 7654 32 on 2024-01-05, \
+            SSN 123-45-6789, from 192.168.0.1.";
+        let all = Builtin::ALL;
+
+        assert_eq!(
+            scrub(text, &all, true, |_| 0),
+            "Call [PHONE] or mail [EMAIL]. Paid with [CREDIT_CARD] from [IBAN] on 2024-01-05, \
+            SSN [SSN], from [IP]."
+        );
+        assert_eq!(
+            scrub(text, &[Builtin::Email], true, |_| 0),
+            text.replace("ann@example.com", "[EMAIL]")
+        );
+
+        let scrubbed = scrub(text, &all, false, |value| value.len() as u64);
+        assert_eq!(
+            scrub(text, &all, false, |value| value.len() as u64),
+            scrubbed
+        );
+        assert!(scrubbed.starts_with("Call +"));
+        assert!(scrubbed.contains(" on 2024-01-05, SSN 9"));
+        for (builtin, original) in [
+            (Builtin::Email, "ann@example.com"),
+            (Builtin::Phone, "+1 (555) 010-9999"),
+            (Builtin::CreditCard, "4111 1111 1111 1111"),
+            (Builtin::Iban, "GB82 WEST 1234 5698 7654 32"),
+            (Builtin::Ip, "192.168.0.1"),
+        ] {
+            assert!(!scrubbed.contains(original), "{original}");
+            assert!(
+                !builtin.find_in(&scrubbed).is_empty(),
+                "{builtin:?} in {scrubbed}"
+            );
+        }
+        assert!(Builtin::Ssn.find_in(&scrubbed).is_empty());
+
+        assert_eq!(
+            scrub("nothing to see", &all, false, |_| 0),
+            "nothing to see"
+        );
+    }
+
+    #[test]
+    fn can_fake_ipv6_addresses() {
+        let fake = fake(Builtin::Ip, "2001:db8::1", 7);
+        assert!(fake.starts_with("fd"));
+        assert!(fake.parse::<Ipv6Addr>().is_ok());
+    }
+}
@@ -0,0 +1,167 @@
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+use anyhow::{bail, Context};
+
+use crate::ExtractResult;
+
+use super::CellContext;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    /// A column of the row, masked unless `original`.
+    Column {
+        name: String,
+        original: bool,
+    },
+}
+
+/// A value built from other values of the same row, such as
+/// `{first_name}.{last_name}@example.test`.
+///
+/// `{column}` is the value of the column after masking and
+/// `{original.column}` the value before. `NULL`s become empty, and `{{` and
+/// `}}` are literal braces.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    format: String,
+    parts: Vec<Part>,
+}
+
+impl Template {
+    pub fn parse(format: &str) -> ExtractResult<Self> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = format.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' | '}' if chars.peek() == Some(&c) => {
+                    chars.next();
+                    text.push(c);
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => bail!("unclosed `{{` in template `{format}`"),
+                        }
+                    }
+                    let (name, original) = match name.trim().strip_prefix("original.") {
+                        Some(name) => (name, true),
+                        None => (name.trim(), false),
+                    };
+                    let name = name.trim_matches('`').to_lowercase();
+                    if name.is_empty() {
+                        bail!("empty column name in template `{format}`");
+                    }
+                    parts.push(Part::Text(std::mem::take(&mut text)));
+                    parts.push(Part::Column { name, original });
+                }
+                '}' => bail!("unmatched `}}` in template `{format}`, write `}}}}` for a brace"),
+                c => text.push(c),
+            }
+        }
+        parts.push(Part::Text(text));
+        parts.retain(|part| *part != Part::Text(String::new()));
+
+        Ok(Self {
+            format: format.to_string(),
+            parts,
+        })
+    }
+
+    /// The columns the template needs masked first.
+    pub fn masked_columns(&self) -> impl Iterator<Item = &str> {
+        self.parts.iter().filter_map(|part| match part {
+            Part::Column {
+                name,
+                original: false,
+            } => Some(name.as_str()),
+            _ => None,
+        })
+    }
+
+    pub fn render(&self, context: &CellContext) -> ExtractResult<String> {
+        let mut rendered = String::new();
+        for part in self.parts.iter() {
+            match part {
+                Part::Text(text) => rendered.push_str(text),
+                Part::Column { name, original } => {
+                    let row = if *original {
+                        context.row
+                    } else {
+                        context.masked
+                    };
+                    let (_, value) = row
+                        .iter()
+                        .find(|(column, _)| column.eq_ignore_ascii_case(name))
+                        .with_context(|| {
+                            format!(
+                                "template `{}` needs `{name}`, which the row does not have",
+                                self.format
+                            )
+                        })?;
+                    rendered.push_str(value.as_deref().unwrap_or_default());
+                }
+            }
+        }
+
+        Ok(rendered)
+    }
+}
+
+impl Display for Template {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}", self.format)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn can_render_templates() {
+        let row = vec![
+            ("first_name".to_string(), Some("John".to_string())),
+            ("last_name".to_string(), Some("Doe".to_string())),
+            ("middle_name".to_string(), None),
+        ];
+        let masked = vec![
+            ("first_name".to_string(), Some("Ann".to_string())),
+            ("last_name".to_string(), Some("Lee".to_string())),
+            ("middle_name".to_string(), None),
+        ];
+        let context = CellContext {
+            row: &row,
+            masked: &masked,
+            ..Default::default()
+        };
+
+        let template =
+            Template::parse("{first_name}.{ `Last_Name` }{middle_name}@example.test").unwrap();
+        assert_eq!(
+            template.masked_columns().collect::<Vec<&str>>(),
+            vec!["first_name", "last_name", "middle_name"]
+        );
+        assert_eq!(template.render(&context).unwrap(), "Ann.Lee@example.test");
+
+        let template = Template::parse("{{{original.first_name}}} {last_name}").unwrap();
+        assert_eq!(
+            template.masked_columns().collect::<Vec<&str>>(),
+            vec!["last_name"]
+        );
+        assert_eq!(template.render(&context).unwrap(), "{John} Lee");
+
+        assert!(Template::parse("{email}")
+            .unwrap()
+            .render(&context)
+            .is_err());
+        for invalid in ["{first_name", "first_name}", "{}", "{original.}"] {
+            assert!(Template::parse(invalid).is_err(), "{invalid}");
+        }
+    }
+}
@@ -1,11 +1,21 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    fs,
+    hash::{BuildHasher, Hasher, RandomState},
+    str::FromStr,
+};
 
+use anyhow::{bail, Context};
 use config::{Config, ConfigError, Environment, File};
 
 use regex::Regex;
-use serde::{Deserialize, Serialize};
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize};
 
-use crate::rules::{self, get_struct_by_name};
+use crate::{
+    masking::{ColumnRef, ColumnSelector, Predicate},
+    rules::{self, CellContext, Lookup, RuleExpression, Script, Strategy, Template},
+    ExtractResult,
+};
 
 // lazy_static! {
 //     static ref FN_NAMES_TO_MODULE: HashMap<String, Box<dyn Fn(String) -> String>> = {
@@ -16,61 +26,547 @@ use crate::rules::{self, get_struct_by_name};
 #[derive(Debug, Serialize, Deserialize)]
 pub struct MaskingRegex {
     pub name: Option<String>,
-    pub regex: String,
+    /// Compiled when the config is loaded, failing on an invalid pattern.
+    #[serde(with = "serde_regex")]
+    pub regex: Regex,
+}
+
+/// (De)serializes a [`Regex`] as its pattern.
+mod serde_regex {
+    use regex::Regex;
+    use serde::{de::Error as _, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(regex: &Regex, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(regex.as_str())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Regex, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        Regex::new(&pattern).map_err(|err| D::Error::custom(format!("invalid pattern: {err}")))
+    }
+}
+
+/// A link between two columns that is not declared as a foreign key in the
+/// dump. Both sides are given as `table.column` or `database.table.column`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MaskingRelationship {
+    pub column: String,
+    pub references: String,
+}
+
+/// Values to mask inside the JSON documents held by the selected columns.
+/// Without a `rule`, the rule or faker named after the last key of the
+/// path is used.
+#[derive(Debug, Deserialize)]
+pub struct JsonPathRule {
+    /// Selector of the columns, see [`ColumnSelector`].
+    pub column: String,
+    /// Path to the values, such as `profile.address.street`, see
+    /// [`crate::masking::JsonPath`].
+    pub path: String,
+    #[serde(default)]
+    pub rule: Option<MaskingRule>,
+}
+
+/// Whether a value pattern flags whole columns or masks single cells.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DetectionScope {
+    /// Mask every value of a column once enough of its values match.
+    #[default]
+    Column,
+    /// Mask each matching value, wherever it is.
+    Cell,
+}
+
+/// A pattern that recognises PII from the values of a column. Without a
+/// `regex` or `detector`, the name selects one of the built-in detectors.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ValuePattern {
+    pub name: String,
+    #[serde(default)]
+    pub detector: Option<String>,
+    #[serde(default)]
+    pub regex: Option<String>,
+    /// Share of a column's values that must match for it to be flagged.
+    #[serde(default)]
+    pub threshold: Option<f64>,
+    #[serde(default)]
+    pub scope: DetectionScope,
+}
+
+#[derive(Debug)]
+pub enum RuleKind {
+    Faker(Box<dyn rules::FromStrFaking>),
+    Strategy(Strategy),
+    Script(Script),
+    /// Replacements from a lookup file, with a rule for the values it lacks.
+    Lookup {
+        lookup: Lookup,
+        fallback: Option<Box<MaskingRule>>,
+    },
 }
 
 #[derive(Debug)]
-pub struct MaskingRule(Box<dyn rules::FromStrFaking>);
+pub struct MaskingRule {
+    name: String,
+    kind: RuleKind,
+    /// Generate a fresh value every time, even when a masking key is set.
+    pub random: bool,
+    /// Only mask values in rows matching this, see [`Predicate`].
+    pub when: Option<Predicate>,
+}
 
 impl MaskingRule {
-    pub fn inner(&self) -> &dyn rules::FromStrFaking {
-        &*self.0
+    pub fn kind(&self) -> &RuleKind {
+        &self.kind
+    }
+
+    /// The faker behind the rule, unless it is a [`Strategy`].
+    pub fn faker(&self) -> Option<&dyn rules::FromStrFaking> {
+        match self.kind {
+            RuleKind::Faker(ref faker) => Some(&**faker),
+            RuleKind::Strategy(_) | RuleKind::Script(_) | RuleKind::Lookup { .. } => None,
+        }
+    }
+
+    pub fn strategy(&self) -> Option<&Strategy> {
+        match self.kind {
+            RuleKind::Strategy(ref strategy) => Some(strategy),
+            RuleKind::Faker(_) | RuleKind::Script(_) | RuleKind::Lookup { .. } => None,
+        }
+    }
+
+    /// The rule as written in the config.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The faker named after `column`, for columns without a rule. Fails
+    /// when no faker has its name.
+    pub fn for_column(column: &str) -> ExtractResult<Self> {
+        let column = column.to_lowercase();
+        let faker = rules::registry().for_name(&column).with_context(|| {
+            format!("`{column}` has no masking rule and no faker is named after it")
+        })?;
+        Ok(MaskingRule {
+            kind: RuleKind::Faker(faker),
+            name: column,
+            random: false,
+            when: None,
+        })
+    }
+
+    /// Generate a replacement for `original`, or `None` when it should
+    /// become `NULL`. With a masking key, fakes are derived from the key,
+    /// the rule and the original value.
+    ///
+    /// Scripts fail when they do, lookups on values they lack, `fpe`
+    /// without a masking key or on values it can't encrypt, and `tokenize`,
+    /// which needs the token vault of a [`crate::masking::Masker`].
+    pub fn apply(
+        &self,
+        original: &str,
+        masking_key: Option<&str>,
+    ) -> ExtractResult<Option<String>> {
+        self.apply_in(original, masking_key, &CellContext::default())
+    }
+
+    /// [`MaskingRule::apply`] for a value in the column and row of
+    /// `context`, which scripts can look at.
+    pub fn apply_in(
+        &self,
+        original: &str,
+        masking_key: Option<&str>,
+        context: &CellContext,
+    ) -> ExtractResult<Option<String>> {
+        match self.kind {
+            RuleKind::Strategy(Strategy::Template(ref template)) => {
+                template.render(context).map(Some)
+            }
+            RuleKind::Strategy(Strategy::Tokenize) => {
+                bail!("rule `{}` needs a token vault", self.name)
+            }
+            RuleKind::Strategy(Strategy::Fpe(ref fpe)) => match masking_key {
+                Some(key) => fpe
+                    .encrypt(key, original)
+                    .map(Some)
+                    .with_context(|| format!("masking rule `{}` failed", self.name)),
+                None => bail!("rule `{}` needs a masking key", self.name),
+            },
+            RuleKind::Strategy(Strategy::ShiftDate { days, ref entity }) => {
+                let entity = match (entity, context.row.first()) {
+                    (Some(entity), _) => context
+                        .row
+                        .iter()
+                        .find(|(column, _)| column.eq_ignore_ascii_case(entity))
+                        .map(|(_, value)| value.as_deref().unwrap_or_default())
+                        .with_context(|| {
+                            format!(
+                                "rule `{}` needs `{entity}`, which the row does not have",
+                                self.name
+                            )
+                        })?,
+                    (None, Some((_, value))) => value.as_deref().unwrap_or_default(),
+                    // Without a row, dates only shift with themselves
+                    (None, None) => original,
+                };
+                let offset = rules::shift_offset(masking_key, days, entity);
+                rules::shift_date(original, context.data_type, offset).map(Some)
+            }
+            RuleKind::Strategy(Strategy::Noise { percent }) => {
+                let seed = match masking_key {
+                    Some(key) if !self.random => {
+                        rules::keyed_seed(key, &self.name, original) as u64
+                    }
+                    _ => RandomState::new().build_hasher().finish() >> 1,
+                };
+                // Uniform in [-1, 1], as seeds are below 2^63
+                let unit = seed as f64 / (1u64 << 62) as f64 - 1.0;
+                let factor = 1.0 + unit * f64::from(percent) / 100.0;
+                rules::add_noise(original, context.data_type, factor)
+                    .map(Some)
+                    .with_context(|| format!("masking rule `{}` failed", self.name))
+            }
+            RuleKind::Strategy(Strategy::IdRemap) => {
+                rules::remap_id(rules::run_key(masking_key), original, context.data_type)
+                    .map(Some)
+                    .with_context(|| format!("masking rule `{}` failed", self.name))
+            }
+            RuleKind::Strategy(ref strategy) => Ok(strategy.apply(original, masking_key)),
+            RuleKind::Faker(ref faker) => Ok(Some(self.fake(&**faker, original, masking_key))),
+            RuleKind::Script(ref script) => {
+                let seed = match masking_key {
+                    Some(key) if !self.random => {
+                        rules::keyed_seed(key, &self.name, original) as u64
+                    }
+                    _ => RandomState::new().build_hasher().finish() >> 1,
+                };
+                script
+                    .run(original, seed, context)
+                    .with_context(|| format!("masking rule `{}` failed", self.name))
+            }
+            RuleKind::Lookup {
+                ref lookup,
+                ref fallback,
+            } => match (lookup.get(original), fallback) {
+                (Some(replacement), _) => Ok(Some(replacement.to_string())),
+                (None, Some(fallback)) => fallback.apply_in(original, masking_key, context),
+                (None, None) => bail!(
+                    "`{original}` is not in `{}`, and rule `{}` has no fallback",
+                    lookup.path(),
+                    self.name
+                ),
+            },
+        }
+    }
+
+    /// Whether the rule looks at the original values of the rest of the
+    /// row: scripts, and `shift_date` for its entity.
+    pub fn reads_row(&self) -> bool {
+        matches!(
+            self.kind,
+            RuleKind::Script(_) | RuleKind::Strategy(Strategy::ShiftDate { .. })
+        )
+    }
+
+    /// Whether the rule masks the values of `row`, given as values by column
+    /// name. Rows without the columns of its `when` predicate are masked, as
+    /// it can't tell.
+    pub fn applies_to(&self, row: &[(String, Option<String>)]) -> bool {
+        self.when
+            .as_ref()
+            .is_none_or(|when| when.matches(row).unwrap_or(true))
+    }
+
+    /// Whether [`MaskingRule::apply`] always gives the same replacement for
+    /// the same value.
+    ///
+    /// Scripts count as deterministic when their `seed` is, and `shift_date`
+    /// always does, though they look at the rest of the row. Templates don't, so linked columns reuse
+    /// the replacements of the rows that had the columns they refer to.
+    pub fn is_deterministic(&self, masking_key: Option<&str>) -> bool {
+        match self.kind {
+            RuleKind::Strategy(Strategy::Shuffle | Strategy::Template(_)) => false,
+            RuleKind::Strategy(Strategy::PreserveFormat { .. }) => masking_key.is_some(),
+            RuleKind::Strategy(Strategy::Hash { ref salt }) => {
+                salt.is_some() || masking_key.is_some()
+            }
+            RuleKind::Strategy(Strategy::Scrub { redact, .. }) => redact || masking_key.is_some(),
+            RuleKind::Strategy(Strategy::Noise { .. }) => masking_key.is_some() && !self.random,
+            RuleKind::Strategy(_) => true,
+            RuleKind::Faker(_) | RuleKind::Script(_) => masking_key.is_some() && !self.random,
+            RuleKind::Lookup { ref fallback, .. } => fallback
+                .as_ref()
+                .is_none_or(|fallback| fallback.is_deterministic(masking_key)),
+        }
+    }
+
+    fn fake(
+        &self,
+        faker: &dyn rules::FromStrFaking,
+        original: &str,
+        masking_key: Option<&str>,
+    ) -> String {
+        match masking_key {
+            Some(key) if !self.random => {
+                rules::fake_with_seed(faker, rules::keyed_seed(key, &self.name, original))
+            }
+            _ => rules::fake_random(faker),
+        }
     }
 }
 
-impl From<&str> for MaskingRule {
-    fn from(value: &str) -> Self {
-        let mut parts = value.split("::");
-        let _domain = parts.next().unwrap();
-        let fn_name = parts.next().unwrap();
-        let fn_ = get_struct_by_name(fn_name);
-        MaskingRule(fn_)
+/// Rules are either a faker, as `module::faker(args)` or just `faker`, or
+/// a [`Strategy`] such as `redact` or `truncate(8)`, see
+/// [`RuleExpression`]. Rules that name no known faker or strategy are
+/// rejected.
+///
+/// Rules with `{column}` placeholders are a [`Template`], short for
+/// `template('...')`.
+///
+/// `lookup(file, fallback)` replaces values with the ones a [`Lookup`] file
+/// maps them to, and masks the others with the `fallback` rule, failing
+/// without one.
+impl FromStr for MaskingRule {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> ExtractResult<Self> {
+        let value = value.trim();
+        if value.contains('{') && !value.starts_with("template(") {
+            return Ok(MaskingRule {
+                name: value.to_string(),
+                kind: RuleKind::Strategy(Strategy::Template(
+                    Template::parse(value)
+                        .with_context(|| format!("invalid masking rule `{value}`"))?,
+                )),
+                random: false,
+                when: None,
+            });
+        }
+
+        let kind = value
+            .parse::<RuleExpression>()
+            .and_then(|expression| match Strategy::parse(&expression) {
+                Some(strategy) => strategy.map(RuleKind::Strategy),
+                None if expression.module.is_none() && expression.function == "lookup" => {
+                    parse_lookup(&expression)
+                }
+                None => rules::registry().resolve(&expression).map(RuleKind::Faker),
+            })
+            .with_context(|| format!("invalid masking rule `{value}`"))?;
+
+        Ok(MaskingRule {
+            name: value.to_string(),
+            kind,
+            random: false,
+            when: None,
+        })
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+fn parse_lookup(expression: &RuleExpression) -> ExtractResult<RuleKind> {
+    let args = expression.arguments(&["file", "fallback"])?;
+    args.require("file", "lookup('customers.csv', fallback='redact')")?;
+    let fallback = args
+        .string("fallback")
+        .map(|fallback| fallback.parse::<MaskingRule>().map(Box::new))
+        .transpose()
+        .context("invalid fallback")?;
+
+    Ok(RuleKind::Lookup {
+        lookup: Lookup::load(&args.string("file").unwrap())?,
+        fallback,
+    })
+}
+
+/// Rules can be given as just the rule, or as a table with options. A table
+/// can give a [`Script`] instead of a rule, inline as `script` or as the
+/// path of a file in `script_file`, and limit the rule to some rows with a
+/// `when` [`Predicate`].
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawMaskingRule {
+    Rule(String),
+    Table {
+        #[serde(default)]
+        rule: Option<String>,
+        #[serde(default)]
+        script: Option<String>,
+        #[serde(default)]
+        script_file: Option<String>,
+        #[serde(default)]
+        random: bool,
+        #[serde(default)]
+        when: Option<String>,
+    },
+}
+
+impl RawMaskingRule {
+    fn parse(self) -> ExtractResult<MaskingRule> {
+        let (rule, script, script_file, random, when) = match self {
+            Self::Rule(rule) => (Some(rule), None, None, false, None),
+            Self::Table {
+                rule,
+                script,
+                script_file,
+                random,
+                when,
+            } => (rule, script, script_file, random, when),
+        };
+
+        let rule = match (rule, script, script_file) {
+            (Some(rule), None, None) => rule.parse()?,
+            (None, Some(source), None) => MaskingRule {
+                name: "script".to_string(),
+                kind: RuleKind::Script(Script::compile(&source)?),
+                random: false,
+                when: None,
+            },
+            (None, None, Some(path)) => {
+                let source = fs::read_to_string(&path)
+                    .with_context(|| format!("unable to read script `{path}`"))?;
+                MaskingRule {
+                    kind: RuleKind::Script(
+                        Script::compile(&source).with_context(|| format!("in `{path}`"))?,
+                    ),
+                    name: format!("script_file('{path}')"),
+                    random: false,
+                    when: None,
+                }
+            }
+            _ => bail!("a masking rule needs exactly one of `rule`, `script` or `script_file`"),
+        };
+
+        let when = when
+            .map(|when| Predicate::parse(&when).context("invalid `when`"))
+            .transpose()?;
+        Ok(MaskingRule {
+            random,
+            when,
+            ..rule
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for MaskingRule {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        RawMaskingRule::deserialize(deserializer)?
+            .parse()
+            .map_err(|err| D::Error::custom(format!("{err:#}")))
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct MaskingConfig {
+    /// Selectors of the columns to mask, given as `column`, `table.column`
+    /// or `database.table.column`, see [`ColumnSelector`].
+    #[serde(default)]
     pub columns: Vec<String>,
+    /// Selectors of columns that are never masked, even when selected by
+    /// `columns`, `patterns` or `value_patterns`.
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    #[serde(default)]
     pub patterns: Vec<MaskingRegex>,
-    #[serde(skip)]
-    pub regexes: Vec<Regex>,
-    #[serde(skip)]
+    #[serde(default, skip_serializing)]
     pub rules: HashMap<String, MaskingRule>,
+    /// Secret that makes masking deterministic: the same original value
+    /// always masks to the same fake value for a given rule.
+    #[serde(default, skip_serializing)]
+    pub masking_key: Option<String>,
+    /// Columns that must be masked consistently, on top of the foreign keys
+    /// declared in the dump.
+    #[serde(default)]
+    pub relationships: Vec<MaskingRelationship>,
+    /// Patterns matched against the values in `INSERT` statements, where
+    /// `patterns` only match column names.
+    #[serde(default)]
+    pub value_patterns: Vec<ValuePattern>,
+    /// Values inside JSON documents to mask, for columns whose values are
+    /// kept otherwise.
+    #[serde(default, skip_serializing)]
+    pub json_paths: Vec<JsonPathRule>,
 }
 
 impl MaskingConfig {
+    /// Whether a column with this name is masked in every table it is in,
+    /// as it is selected by a selector without a table or by a pattern, and
+    /// not exempted by an `exclude` selector without a table.
     pub fn filter_column(&self, column: &str) -> bool {
-        let lowercased_columns: Vec<String> =
-            self.columns.iter().map(|c| c.to_lowercase()).collect();
-        let lowercased_column = column.to_lowercase();
+        let column = ColumnRef::new(None, "", column);
+        let unscoped = |selectors: &[String]| {
+            selectors
+                .iter()
+                .filter_map(|selector| ColumnSelector::parse(selector).ok())
+                .any(|selector| selector.is_unscoped() && selector.matches(&column))
+        };
 
-        if lowercased_columns.contains(&lowercased_column) {
+        if unscoped(&self.exclude) {
+            return false;
+        }
+        if unscoped(&self.columns) {
             return true;
         }
 
-        for regex in &self.build_regexes() {
-            if regex.is_match(&column) {
-                return true;
-            }
+        self.patterns
+            .iter()
+            .any(|pattern| pattern.regex.is_match(&column.column))
+    }
+
+    /// Generate a replacement for `original`, a value in `column`, or `None`
+    /// when it should become `NULL`.
+    ///
+    /// The rule is looked up by column name first, then by the name of the
+    /// first pattern matching the column, and finally falls back to the
+    /// faker named after the column. With a masking key the replacement is
+    /// derived from the key, the rule and the original value.
+    pub fn mask_value(&self, column: &str, original: &str) -> ExtractResult<Option<String>> {
+        let key = self.masking_key.as_deref();
+        match self.rule_for(column) {
+            Some(rule) => rule.apply(original, key),
+            None => MaskingRule::for_column(column)?.apply(original, key),
         }
-        false
     }
 
-    fn build_regexes(&self) -> Vec<Regex> {
-        self.patterns
-            .iter()
-            .map(|regex| Regex::new(&regex.regex).unwrap())
-            .collect()
+    /// Fake a replacement for `original`, a value in `column`, ignoring
+    /// rules that are not fakers.
+    pub fn fake_for(&self, column: &str, original: &str) -> ExtractResult<String> {
+        let key = self.masking_key.as_deref();
+        let fake = match self.rule_for(column).filter(|rule| rule.faker().is_some()) {
+            Some(rule) => rule.apply(original, key)?,
+            None => MaskingRule::for_column(column)?.apply(original, key)?,
+        };
+        Ok(fake.expect("fakers always give a value"))
+    }
+
+    /// Whether [`MaskingConfig::mask_value`] always gives the same
+    /// replacement for the same value in `column`.
+    pub fn is_deterministic(&self, column: &str) -> bool {
+        let key = self.masking_key.as_deref();
+        match self.rule_for(column) {
+            Some(rule) => rule.is_deterministic(key),
+            None => key.is_some(),
+        }
+    }
+
+    /// The rule for `column`, if the config has one.
+    pub fn rule_for(&self, column: &str) -> Option<&MaskingRule> {
+        let column = column.to_lowercase();
+        self.rules.get(&column).or_else(|| {
+            self.patterns
+                .iter()
+                .filter(|pattern| pattern.regex.is_match(&column))
+                .find_map(|pattern| {
+                    pattern
+                        .name
+                        .as_ref()
+                        .and_then(|name| self.rules.get(&name.to_lowercase()))
+                })
+        })
     }
 }
 
@@ -98,7 +594,7 @@ mod tests {
         let config = parse_masking_config("./tests/more.yaml").unwrap();
         assert_eq!(config.patterns.len(), 1);
         assert_eq!(
-            config.patterns[0].regex,
+            config.patterns[0].regex.as_str(),
             "^[a-zA-Z0-9_.+-]+@[a-zA-Z0-9-]+\\.[a-zA-Z0-9-.]+$"
         );
     }
@@ -107,9 +603,203 @@ mod tests {
     fn test_filtering_columns() {
         let config = parse_masking_config("./tests/more.yaml");
         let cfg = config.unwrap();
-        assert_eq!(cfg.filter_column("email"), false);
-        assert_eq!(cfg.filter_column("account"), true);
-        assert_eq!(cfg.filter_column("password"), true);
-        assert_eq!(cfg.filter_column("age"), false);
+        assert!(!cfg.filter_column("email"));
+        assert!(cfg.filter_column("account"));
+        assert!(cfg.filter_column("password"));
+        assert!(!cfg.filter_column("age"));
+    }
+
+    #[test]
+    fn test_filtering_columns_with_selectors() {
+        let cfg = MaskingConfig {
+            columns: vec!["*phone*".to_string(), "users.name".to_string()],
+            exclude: vec!["fax_phone".to_string()],
+            ..Default::default()
+        };
+        assert!(cfg.filter_column("Mobile_Phone"));
+        assert!(!cfg.filter_column("fax_phone"));
+        assert!(!cfg.filter_column("name"));
+    }
+
+    #[test]
+    fn test_loads_rules() {
+        let cfg = parse_masking_config("./tests/more.yaml").unwrap();
+        assert_eq!(
+            format!("{:?}", cfg.rules.get("email").unwrap().faker().unwrap()),
+            "FnFaker(contact::email)"
+        );
+        assert!(cfg
+            .fake_for("email", "john@example.com")
+            .unwrap()
+            .contains('@'));
+
+        let password = cfg.rules.get("password").unwrap();
+        assert_eq!(password.name(), "internet::username()");
+        assert!(password.random);
+        assert!(!cfg.rules.get("email").unwrap().random);
+    }
+
+    #[test]
+    fn test_rejects_invalid_patterns() {
+        let config = Config::builder()
+            .add_source(File::from_str(
+                "patterns:\n  - name: email\n    regex: \"^(mail\"\n",
+                config::FileFormat::Yaml,
+            ))
+            .build()
+            .unwrap()
+            .try_deserialize::<MaskingConfig>();
+
+        let err = format!("{:#}", config.unwrap_err());
+        assert!(err.contains("invalid pattern"), "{err}");
+    }
+
+    #[test]
+    fn test_rejects_unknown_rules() {
+        for rule in [
+            "contact::mail()",
+            "name::email()",
+            "email()x",
+            "number::between(a)",
+        ] {
+            let config = Config::builder()
+                .add_source(File::from_str(
+                    &format!("rules:\n  email: \"{rule}\"\n"),
+                    config::FileFormat::Yaml,
+                ))
+                .build()
+                .unwrap()
+                .try_deserialize::<MaskingConfig>();
+
+            let err = format!("{:#}", config.unwrap_err());
+            assert!(
+                err.contains(&format!("invalid masking rule `{rule}`")),
+                "{err}"
+            );
+        }
+
+        let rule = "datetime::date(min='2000-01-01')"
+            .parse::<MaskingRule>()
+            .unwrap();
+        assert!(rule
+            .apply("1999-12-31", None)
+            .unwrap()
+            .unwrap()
+            .starts_with("20"));
+    }
+
+    #[test]
+    fn test_loads_script_rules() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut file, b"value.to_upper()").unwrap();
+        let path = file.path().display().to_string();
+
+        let config = Config::builder()
+            .add_source(File::from_str(
+                &format!(
+                    "rules:\n  code:\n    script: value.sub_string(0, 2)\n  name:\n    script_file: {path}\n    random: true\n"
+                ),
+                config::FileFormat::Yaml,
+            ))
+            .build()
+            .unwrap()
+            .try_deserialize::<MaskingConfig>()
+            .unwrap();
+
+        assert_eq!(config.mask_value("code", "ABCD").unwrap().unwrap(), "AB");
+        let name = &config.rules["name"];
+        assert_eq!(name.name(), format!("script_file('{path}')"));
+        assert!(name.random && !name.is_deterministic(Some("key")));
+        assert_eq!(name.apply("jo", None).unwrap().unwrap(), "JO");
+
+        for (rule, error) in [
+            ("script: 'value +'", "unable to compile script"),
+            ("script_file: /nonexistent.rhai", "unable to read script"),
+            ("rule: redact\n    script: value", "exactly one of"),
+        ] {
+            let config = Config::builder()
+                .add_source(File::from_str(
+                    &format!("rules:\n  code:\n    {rule}\n"),
+                    config::FileFormat::Yaml,
+                ))
+                .build()
+                .unwrap()
+                .try_deserialize::<MaskingConfig>();
+
+            let err = format!("{:#}", config.unwrap_err());
+            assert!(err.contains(error), "{err}");
+        }
+    }
+
+    #[test]
+    fn test_loads_dictionary_and_lookup_rules() {
+        let dir = tempfile::tempdir().unwrap();
+        let departments = dir.path().join("departments.txt");
+        std::fs::write(&departments, "Sales\nFinance\nLegal\n").unwrap();
+        let customers = dir.path().join("customers.csv");
+        std::fs::write(&customers, "original,replacement\nC-1,T-100\n").unwrap();
+        let (departments, customers) = (departments.display(), customers.display());
+
+        let mut config = Config::builder()
+            .add_source(File::from_str(
+                &format!(
+                    "rules:\n  department: dictionary::pick('{departments}')\n  customer_id: lookup('{customers}', fallback='constant(T-000)')\n  account_id: lookup(file='{customers}')\n"
+                ),
+                config::FileFormat::Yaml,
+            ))
+            .build()
+            .unwrap()
+            .try_deserialize::<MaskingConfig>()
+            .unwrap();
+        config.masking_key = Some("secret".to_string());
+
+        let department = config.mask_value("department", "R&D").unwrap().unwrap();
+        assert!(["Sales", "Finance", "Legal"].contains(&department.as_str()));
+        assert_eq!(
+            config.mask_value("department", "R&D").unwrap().unwrap(),
+            department
+        );
+        assert_eq!(
+            config.mask_value("customer_id", "C-1").unwrap().unwrap(),
+            "T-100"
+        );
+        assert_eq!(
+            config.mask_value("customer_id", "C-2").unwrap().unwrap(),
+            "T-000"
+        );
+        assert!(config.is_deterministic("customer_id"));
+        assert!(config.mask_value("account_id", "C-2").is_err());
+
+        for rule in [
+            "lookup()",
+            "lookup('/nonexistent.csv')",
+            &format!("lookup('{customers}', fallback='nope')"),
+            "dictionary::pick('/nonexistent.txt')",
+        ] {
+            assert!(rule.parse::<MaskingRule>().is_err(), "{rule}");
+        }
+    }
+
+    #[test]
+    fn test_masking_key_makes_fakes_repeatable() {
+        let mut cfg = parse_masking_config("./tests/more.yaml").unwrap();
+        cfg.masking_key = Some("secret".to_string());
+        cfg.rules.insert(
+            "contact_email".to_string(),
+            "contact::email()".parse().unwrap(),
+        );
+
+        let fake = cfg.fake_for("email", "john@example.com").unwrap();
+        assert_eq!(fake, cfg.fake_for("EMAIL", "john@example.com").unwrap());
+        assert_eq!(
+            fake,
+            cfg.fake_for("contact_email", "john@example.com").unwrap()
+        );
+        assert_ne!(fake, cfg.fake_for("email", "jane@example.com").unwrap());
+
+        let passwords = (0..20)
+            .map(|_| cfg.fake_for("password", "hunter2").unwrap())
+            .collect::<std::collections::HashSet<String>>();
+        assert!(passwords.len() > 1);
     }
 }
@@ -1,5 +1,6 @@
+use anyhow::Context;
 use regex::Regex;
-use std::{collections::HashMap, path::Path}; // 1.1.8
+use std::{collections::HashMap, fs::File, io::Read, path::Path}; // 1.1.8
 
 use sql_parse::{
     parse_statements, CreateDefinition, CreateTable, ParseOptions, QualifiedName, SQLDialect,
@@ -7,77 +8,55 @@ use sql_parse::{
 };
 
 use crate::{
+    dump::StatementReader,
     types::{Column, ColumnType, Database, Table},
     ExtractResult,
 };
 
 pub fn simple_parse(code_path: &Path) -> ExtractResult<Vec<Database>> {
+    let sql_dump = File::open(code_path).context("unable to read sql dump")?;
+    parse_schema(sql_dump)
+}
+
+/// Extract the databases and tables from a SQL dump, reading it one
+/// statement at a time.
+pub fn parse_schema<R: Read>(sql_dump: R) -> ExtractResult<Vec<Database>> {
     let options = ParseOptions::new()
         .dialect(SQLDialect::MariaDB)
         .arguments(sql_parse::SQLArguments::QuestionMark)
         .warn_unquoted_identifiers(true);
 
-    let mut issues = Vec::new();
-
-    let sql_dump = std::fs::read_to_string(code_path).expect("unable to read sql dump");
-
     // Regex to capture the `USE` statement and the database name
-    let db_regex = Regex::new(r"USE `([^`]+)`;").unwrap();
+    let db_regex = Regex::new(r"^USE\s+`([^`]+)`").unwrap();
 
-    // Split by `USE` while retaining the delimiters
-    let mut databases = Vec::new();
-    let mut current_db_name = String::new();
-    let mut current_db_sql = String::new();
+    let mut databases: Vec<Database> = Vec::new();
 
-    for line in sql_dump.lines() {
-        if let Some(captures) = db_regex.captures(line) {
-            // Process the previous database if any
-            if !current_db_name.is_empty() {
-                let ast = parse_statements(&current_db_sql, &mut issues, &options);
-                let mut tables = Vec::new();
-                for node in ast.iter() {
-                    match node {
-                        Statement::CreateTable(create_table) => {
-                            let tbl = parse_create_table(create_table);
-                            tables.push(tbl.clone());
-                        }
-                        _ => {}
-                    }
-                }
-                databases.push(Database {
-                    db_name: current_db_name.clone(),
-                    tables,
-                });
+    for statement in StatementReader::new(sql_dump) {
+        let statement = statement?;
 
-                // Clear the SQL statements for the new database
-                current_db_sql.clear();
+        match statement.keyword().as_str() {
+            "USE" => {
+                if let Some(captures) = db_regex.captures(&statement.body) {
+                    databases.push(Database {
+                        db_name: captures.get(1).unwrap().as_str().to_string(),
+                        tables: Vec::new(),
+                    });
+                }
             }
-            // Capture the new database name
-            current_db_name = captures.get(1).unwrap().as_str().to_string();
-        } else {
-            // Append the line to the current database's SQL statements
-            current_db_sql.push_str(line);
-            current_db_sql.push('\n');
-        }
-    }
-
-    // Process the last database if any
-    if !current_db_name.is_empty() {
-        let ast = parse_statements(&current_db_sql, &mut issues, &options);
-        let mut tables = Vec::new();
-        for node in ast.iter() {
-            match node {
-                Statement::CreateTable(create_table) => {
-                    let tbl = parse_create_table(create_table);
-                    tables.push(tbl.clone());
+            // Tables are only collected once a database has been selected
+            "CREATE" => {
+                if let Some(database) = databases.last_mut() {
+                    let mut issues = Vec::new();
+                    let ast = parse_statements(&statement.body, &mut issues, &options);
+                    for node in ast.iter() {
+                        if let Statement::CreateTable(create_table) = node {
+                            database.tables.push(parse_create_table(create_table));
+                        }
+                    }
                 }
-                _ => {}
             }
+            _ => {}
         }
-        databases.push(Database {
-            db_name: current_db_name,
-            tables,
-        });
     }
 
     Ok(databases)